@@ -0,0 +1,204 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+//
+// Dashboard built on top of the JSON routes of the api binary, without any external dependencies.
+"use strict";
+
+const SVG_NS = "http://www.w3.org/2000/svg";
+const MARGIN = { top: 10, right: 50, bottom: 25, left: 50 };
+
+// forward the API key when the api binary requires authentication, e.g. `/?api_key=pl_...`
+const API_KEY = new URLSearchParams(window.location.search).get("api_key");
+
+function route(path) {
+  return API_KEY ? `${path}?api_key=${encodeURIComponent(API_KEY)}` : path;
+}
+
+// the api serializes times as ISO 8601 with extended years and nanoseconds, e.g.
+// `+002024-04-16T09:30:00.000000000Z` which not all browsers can parse
+function parseTime(time) {
+  return new Date(time.replace(/^\+00/, "").replace(/(\.\d{3})\d*/, "$1"));
+}
+
+function formatNumber(value, digits) {
+  return value.toLocaleString(undefined, { maximumFractionDigits: digits, minimumFractionDigits: digits });
+}
+
+function element(parent, name, attributes, text) {
+  const node = document.createElementNS(SVG_NS, name);
+  for (const [key, value] of Object.entries(attributes)) {
+    node.setAttribute(key, value);
+  }
+  if (text !== undefined) {
+    node.textContent = text;
+  }
+  parent.appendChild(node);
+  return node;
+}
+
+function frame(svg) {
+  svg.replaceChildren();
+  const [, , width, height] = svg.getAttribute("viewBox").split(" ").map(Number);
+  return {
+    left: MARGIN.left,
+    right: width - MARGIN.right,
+    top: MARGIN.top,
+    bottom: height - MARGIN.bottom,
+  };
+}
+
+function scale(domainMin, domainMax, rangeMin, rangeMax) {
+  const span = domainMax - domainMin || 1;
+  return (value) => rangeMin + ((value - domainMin) / span) * (rangeMax - rangeMin);
+}
+
+function niceMax(value) {
+  if (value <= 0) {
+    return 1;
+  }
+  const magnitude = Math.pow(10, Math.floor(Math.log10(value)));
+  return Math.ceil(value / magnitude) * magnitude;
+}
+
+function yAxis(svg, box, y, max, unit, side) {
+  const x = side === "left" ? box.left : box.right;
+  const anchor = side === "left" ? "end" : "start";
+  const offset = side === "left" ? -5 : 5;
+  for (let i = 0; i <= 4; ++i) {
+    const value = (max * i) / 4;
+    if (side === "left") {
+      element(svg, "line", { class: "grid", x1: box.left, x2: box.right, y1: y(value), y2: y(value) });
+    }
+    element(svg, "text", { x: x + offset, y: y(value) + 4, "text-anchor": anchor }, `${formatNumber(value, max < 4 ? 1 : 0)} ${unit}`);
+  }
+}
+
+function path(svg, points, x, y, cssClass) {
+  if (points.length === 0) {
+    return;
+  }
+  const d = points.map(([px, py], i) => `${i === 0 ? "M" : "L"}${x(px).toFixed(1)},${y(py).toFixed(1)}`).join("");
+  element(svg, "path", { class: cssClass, d });
+}
+
+function drawToday(samples) {
+  const svg = document.getElementById("today");
+  const box = frame(svg);
+
+  const start = new Date();
+  start.setHours(0, 0, 0, 0);
+  const end = new Date(start.getTime() + 24 * 3600 * 1000);
+  const x = scale(start.getTime(), end.getTime(), box.left, box.right);
+
+  const maxPower = niceMax(Math.max(0, ...samples.map((s) => Math.max(s.power_ch1, s.power_ch2))));
+  const power = scale(0, maxPower, box.bottom, box.top);
+  const altitude = scale(0, 90, box.bottom, box.top);
+
+  yAxis(svg, box, power, maxPower, "W", "left");
+  yAxis(svg, box, altitude, 90, "°", "right");
+  for (let hour = 0; hour <= 24; hour += 3) {
+    const time = start.getTime() + hour * 3600 * 1000;
+    element(svg, "text", { x: x(time), y: box.bottom + 18, "text-anchor": "middle" }, `${hour}:00`);
+  }
+
+  const points = (value) => samples.map((s) => [parseTime(s.time).getTime(), value(s)]);
+  path(svg, points((s) => Math.max(0, (s.sun_altitude * 180) / Math.PI)), x, altitude, "sun");
+  path(svg, points((s) => s.power_ch1), x, power, "ch1");
+  path(svg, points((s) => s.power_ch2), x, power, "ch2");
+}
+
+function drawBars(svgId, rows, label) {
+  const svg = document.getElementById(svgId);
+  const box = frame(svg);
+
+  const max = niceMax(Math.max(0, ...rows.map((row) => (row.ch1 || 0) + (row.ch2 || 0))));
+  const y = scale(0, max, box.bottom, box.top);
+  yAxis(svg, box, y, max, "kWh", "left");
+
+  const step = (box.right - box.left) / Math.max(rows.length, 1);
+  const width = Math.max(1, step * 0.8);
+  const labelEvery = Math.ceil(rows.length / 12);
+  rows.forEach((row, i) => {
+    const left = box.left + i * step + (step - width) / 2;
+    const ch1 = row.ch1 || 0;
+    const ch2 = row.ch2 || 0;
+    element(svg, "rect", { class: "ch1", x: left, width, y: y(ch1), height: box.bottom - y(ch1) });
+    element(svg, "rect", { class: "ch2", x: left, width, y: y(ch1 + ch2), height: y(ch1) - y(ch1 + ch2) });
+    if (i % labelEvery === 0) {
+      element(svg, "text", { x: left + width / 2, y: box.bottom + 18, "text-anchor": "middle" }, label(row));
+    }
+  });
+}
+
+function drawMonthly(days) {
+  const months = new Map();
+  for (const day of days) {
+    const month = day.date.slice(0, 7);
+    const total = months.get(month) || { ch1: 0, ch2: 0 };
+    total.ch1 += day.ch1 || 0;
+    total.ch2 += day.ch2 || 0;
+    months.set(month, total);
+  }
+
+  const body = document.querySelector("#monthly tbody");
+  body.replaceChildren();
+  for (const [month, total] of [...months].reverse()) {
+    const row = body.insertRow();
+    for (const value of [month, formatNumber(total.ch1, 1), formatNumber(total.ch2, 1), formatNumber(total.ch1 + total.ch2, 1)]) {
+      row.insertCell().textContent = value;
+    }
+  }
+}
+
+function showLive(sample) {
+  const text = (id, value) => (document.getElementById(id).textContent = value);
+  text("live-power", `${formatNumber(sample.power_ch1 + sample.power_ch2, 0)} W`);
+  text("live-ch1", `${formatNumber(sample.power_ch1, 0)} W`);
+  text("live-ch2", `${formatNumber(sample.power_ch2, 0)} W`);
+  text("live-energy", `${formatNumber(sample.energy_today_ch1 + sample.energy_today_ch2, 2)} kWh`);
+  text("live-time", parseTime(sample.time).toLocaleTimeString());
+}
+
+async function fetchJson(path) {
+  const response = await fetch(route(path));
+  if (!response.ok) {
+    throw new Error(`${path}: ${response.status} ${response.statusText}`);
+  }
+  return response.json();
+}
+
+async function refresh() {
+  const [today, hourly, daily] = await Promise.all([
+    fetchJson("powerToday"),
+    fetchJson("generatedByHourToday"),
+    fetchJson("generatedByDay"),
+  ]);
+
+  drawToday(today);
+  drawBars("hourly", hourly, (row) => `${row.hour}:00`);
+  // the first day has no predecessor and thus covers everything generated before logging started
+  const days = daily.slice(1);
+  drawBars("daily", days.slice(-30), (row) => row.date.slice(5));
+  drawMonthly(days);
+  return today;
+}
+
+async function main() {
+  let today = await refresh();
+  if (today.length > 0) {
+    showLive(today[today.length - 1]);
+  }
+
+  let lastRefresh = Date.now();
+  const live = new EventSource(route("live"));
+  live.addEventListener("sample", (event) => {
+    const sample = JSON.parse(event.data);
+    showLive(sample);
+    // the aggregated routes only change with new samples, so there is no need to poll them
+    if (Date.now() - lastRefresh > 60 * 1000) {
+      lastRefresh = Date.now();
+      refresh().catch(console.error);
+    }
+  });
+}
+
+main().catch(console.error);
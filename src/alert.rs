@@ -0,0 +1,289 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Rules checked after every collector run, see `config::ALERT_RULES`. The state of each rule
+//! is kept in the `alerts` table, so a condition lasting for hours is only notified again
+//! once its cooldown passed.
+use anyhow::Result;
+use std::time::Duration;
+
+use crate::collector::InverterStatus;
+use crate::db::{self, Alert, ChannelReading, Totals};
+use crate::notify::{self, Notification, Notifier, Status};
+
+pub enum Condition {
+    /// the inverter is offline or switched off while the sun is up
+    OffDuringDaylight,
+    /// a channel reads 0 W while another one produces at least `min_power` W
+    ChannelZero { min_power: f32 },
+    /// the lifetime energy of a channel is below the one of the previous sample
+    LifetimeReset,
+    /// the yield since midnight (UTC) is below `ratio` of what the global tilted irradiance
+    /// promised, once at least `min_expected` kWh are expected. The irradiance is the current
+    /// value of the weather model stored with each sample, not a day-ahead forecast.
+    YieldBelowIrradiance {
+        /// in kWp
+        peak_power: f64,
+        performance_ratio: f64,
+        ratio: f64,
+        min_expected: f64,
+    },
+}
+
+impl Condition {
+    /// Also identifies the rule in the `alerts` table, so use each condition only once
+    pub fn name(&self) -> &'static str {
+        match self {
+            Condition::OffDuringDaylight => "off-during-daylight",
+            Condition::ChannelZero { .. } => "channel-zero",
+            Condition::LifetimeReset => "lifetime-reset",
+            Condition::YieldBelowIrradiance { .. } => "yield-below-irradiance",
+        }
+    }
+
+    /// The message describing the problem if the condition holds
+    pub fn check(&self, context: &Context) -> Option<String> {
+        match *self {
+            Condition::OffDuringDaylight => {
+                if !crate::health::is_daylight(context.time) {
+                    return None;
+                }
+                match context.inverter {
+                    InverterStatus::Offline => Some("inverter is offline".to_string()),
+                    _ if context.switched_off => Some("inverter is switched off".to_string()),
+                    _ => None,
+                }
+            }
+            Condition::ChannelZero { min_power } => {
+                let zero = context.channels.iter().find(|c| c.power == 0.0)?;
+                let producing = context.channels.iter().find(|c| c.power >= min_power)?;
+                Some(format!(
+                    "channel {} reads 0 W while channel {} produces {} W",
+                    zero.channel, producing.channel, producing.power
+                ))
+            }
+            Condition::LifetimeReset => context.channels.iter().find_map(|channel| {
+                let previous = context
+                    .previous_channels
+                    .iter()
+                    .find(|previous| previous.channel == channel.channel)?;
+                (channel.energy_total < previous.energy_total).then(|| {
+                    format!(
+                        "lifetime energy of channel {} dropped from {} kWh to {} kWh",
+                        channel.channel, previous.energy_total, channel.energy_total
+                    )
+                })
+            }),
+            Condition::YieldBelowIrradiance {
+                peak_power,
+                performance_ratio,
+                ratio,
+                min_expected,
+            } => {
+                let (first, last) = (context.today.first()?, context.today.last()?);
+                let generated = last.generated - first.generated;
+                let expected = expected_energy(&context.today, peak_power, performance_ratio);
+                (expected >= min_expected && generated < ratio * expected).then(|| {
+                    format!(
+                        "generated {generated:.2} kWh today, expected {expected:.2} kWh from the modelled irradiance"
+                    )
+                })
+            }
+        }
+    }
+}
+
+/// The energy in kWh panels with `peak_power` kWp should have generated between the first
+/// and the last of the chronological `samples`, judging by the global tilted irradiance
+pub fn expected_energy(samples: &[Totals], peak_power: f64, performance_ratio: f64) -> f64 {
+    samples
+        .windows(2)
+        .filter_map(|pair| {
+            let hours = (pair[1].time - pair[0].time).as_seconds_f64() / 3600.0;
+            Some(expected_power(&pair[1], peak_power, performance_ratio)? / 1000.0 * hours)
+        })
+        .sum()
+}
+
+/// The power in W expected from the irradiance of `sample`, the peak power is rated at
+/// 1000 W/m²
+pub fn expected_power(sample: &Totals, peak_power: f64, performance_ratio: f64) -> Option<f64> {
+    Some(sample.global_tilted_irradiance? * peak_power * performance_ratio)
+}
+
+pub struct Rule {
+    pub condition: Condition,
+    /// minimum time between two notifications of the rule firing
+    pub cooldown: Duration,
+}
+
+/// What the rules are checked against
+pub struct Context {
+    pub time: time::OffsetDateTime,
+    pub inverter: InverterStatus,
+    /// whether the sample of this run reports the inverter as switched off
+    pub switched_off: bool,
+    /// of the latest sample, which might be older than this run
+    pub channels: Vec<ChannelReading>,
+    /// of the sample before the latest one
+    pub previous_channels: Vec<ChannelReading>,
+    /// samples since midnight (UTC) in chronological order
+    pub today: Vec<Totals>,
+}
+
+impl Context {
+    pub async fn load(
+        db: &sea_orm::DatabaseConnection,
+        time: time::OffsetDateTime,
+        inverter: InverterStatus,
+    ) -> Result<Self> {
+        let recent = db::select_recent(db, 2).await?;
+        // without a sample of this run, the readings of an earlier one must not be checked again
+        let stored = recent.last().is_some_and(|latest| latest.time == time);
+        let mut channels = Vec::new();
+        if stored {
+            for sample in &recent {
+                channels.push(db::select_channels(db, sample.id).await?);
+            }
+        }
+        let midnight = time
+            .to_offset(time::UtcOffset::UTC)
+            .replace_time(time::Time::MIDNIGHT);
+        Ok(Self {
+            time,
+            inverter,
+            switched_off: stored
+                && recent
+                    .last()
+                    .is_some_and(|latest| latest.on_off == Some(false)),
+            channels: channels.pop().unwrap_or_default(),
+            previous_channels: channels.pop().unwrap_or_default(),
+            today: db::select_totals(db, midnight, midnight + time::Duration::DAY).await?,
+        })
+    }
+}
+
+/// Check all `rules`, persist their state and notify about changes. Notifier errors don't
+/// stop the evaluation, they are returned together with the name of the notifier instead.
+pub async fn evaluate(
+    db: &sea_orm::DatabaseConnection,
+    rules: &[Rule],
+    notifiers: &[Box<dyn Notifier>],
+    context: &Context,
+) -> Result<Vec<(&'static str, anyhow::Error)>> {
+    let time = context.time;
+    let mut alerts = db::select_alerts(db).await?;
+    let mut errors = Vec::new();
+    for rule in rules {
+        let name = rule.condition.name();
+        let mut alert = match alerts.iter().position(|alert| alert.rule == name) {
+            Some(index) => alerts.swap_remove(index),
+            None => Alert {
+                rule: name.to_string(),
+                active: false,
+                since: None,
+                last_notified: None,
+                message: None,
+            },
+        };
+
+        let notification = match rule.condition.check(context) {
+            Some(message) => {
+                if !alert.active {
+                    alert.active = true;
+                    alert.since = Some(time);
+                }
+                alert.message = Some(message.clone());
+                let due = alert
+                    .last_notified
+                    .is_none_or(|last| time - last >= rule.cooldown);
+                due.then(|| Notification::alert(name, Status::Firing, message, time))
+            }
+            None if alert.active => {
+                alert.active = false;
+                // stay quiet about problems nobody was told about, e.g. during the cooldown
+                let notified = alert
+                    .last_notified
+                    .zip(alert.since)
+                    .is_some_and(|(notified, since)| notified >= since);
+                notified.then(|| {
+                    let message = alert.message.clone().unwrap_or_default();
+                    Notification::alert(name, Status::Resolved, message, time)
+                })
+            }
+            None => continue,
+        };
+
+        if let Some(notification) = notification {
+            let failed = notify::send_all(notifiers, &notification).await;
+            // retry next time when nobody could be reached
+            if notification.status() == Some(Status::Firing) && failed.len() < notifiers.len() {
+                alert.last_notified = Some(time);
+            }
+            errors.extend(failed);
+        }
+        db::upsert_alert(db, &alert).await?;
+    }
+    Ok(errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::alert::{Condition, Context};
+    use crate::collector::InverterStatus;
+    use crate::db::{ChannelReading, Totals};
+
+    fn channel(channel: i32, power: f32) -> ChannelReading {
+        ChannelReading {
+            time: time::OffsetDateTime::UNIX_EPOCH,
+            channel,
+            power,
+            energy_today: 0.0,
+            energy_total: 0.0,
+        }
+    }
+
+    #[test]
+    fn conditions() {
+        let start = time::OffsetDateTime::from_unix_timestamp(1718960400).unwrap();
+        // an hour at 500 W/m², promising 0.32 kWh
+        let reading = |minutes: i64, generated: f64| Totals {
+            time: start + time::Duration::minutes(minutes),
+            generated,
+            power: 0.0,
+            max_power: 800.0,
+            cloud_cover: None,
+            global_tilted_irradiance: Some(500.0),
+        };
+        let mut context = Context {
+            time: start,
+            inverter: InverterStatus::Online,
+            switched_off: false,
+            channels: vec![channel(1, 180.0), channel(2, 0.0)],
+            previous_channels: Vec::new(),
+            today: vec![reading(0, 100.0), reading(30, 100.1), reading(60, 100.15)],
+        };
+
+        let channel_zero = Condition::ChannelZero { min_power: 20.0 };
+        assert_eq!(
+            channel_zero.check(&context).unwrap(),
+            "channel 2 reads 0 W while channel 1 produces 180 W"
+        );
+        let yield_below = |min_expected| Condition::YieldBelowIrradiance {
+            peak_power: 0.8,
+            performance_ratio: 0.8,
+            ratio: 0.5,
+            min_expected,
+        };
+        assert_eq!(
+            yield_below(0.3).check(&context).unwrap(),
+            "generated 0.15 kWh today, expected 0.32 kWh from the modelled irradiance"
+        );
+        assert_eq!(yield_below(0.5).check(&context), None);
+        assert!(Condition::LifetimeReset.check(&context).is_none());
+
+        context.channels = vec![channel(1, 15.0), channel(2, 0.0)];
+        context.today[2].generated = 100.2;
+        assert_eq!(channel_zero.check(&context), None);
+        assert_eq!(yield_below(0.3).check(&context), None);
+    }
+}
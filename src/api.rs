@@ -0,0 +1,395 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! The HTTP API serving the collected data and the built-in dashboard
+use std::sync::Arc;
+use tower_http::compression::CompressionLayer;
+
+use axum::{
+    Json, Router,
+    body::Body,
+    extract::{Query, Request, State},
+    http::{HeaderMap, Method, StatusCode, header},
+    middleware::{self, Next},
+    response::{
+        Html, IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+    routing::{MethodRouter, get},
+};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::auth::{self, Scope};
+use crate::config;
+use crate::db;
+use crate::error::PowerlogError;
+use crate::finance;
+use crate::health;
+use crate::live;
+use crate::metrics;
+
+struct AppState {
+    db: sea_orm::DatabaseConnection,
+    live: Arc<live::Feed>,
+    /// the current time for the health checks and the amortization
+    clock: health::Clock,
+}
+
+/// The error of all handlers, rendered as problem details
+struct AppError(PowerlogError);
+
+/// An RFC 9457 problem details body
+#[derive(Serialize)]
+struct Problem {
+    r#type: &'static str,
+    title: String,
+    status: u16,
+    detail: String,
+}
+
+// Tell axum how to convert `AppError` into a response.
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let status = self.0.status_code();
+        // the chain may contain queries, paths or addresses, which are no business of clients
+        let detail = if status.is_server_error() {
+            let mut chain = self.0.to_string();
+            let mut source = std::error::Error::source(&self.0);
+            while let Some(err) = source {
+                chain.push_str(&format!(": {err}"));
+                source = err.source();
+            }
+            eprintln!("request failed: {chain}");
+            "the request failed, see the server log for details".to_string()
+        } else {
+            self.0.to_string()
+        };
+        let problem = Problem {
+            r#type: "about:blank",
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail,
+        };
+        (
+            status,
+            [(header::CONTENT_TYPE, "application/problem+json")],
+            Json(problem),
+        )
+            .into_response()
+    }
+}
+
+// This enables using `?` on the results of the database and other layers in handlers
+impl From<PowerlogError> for AppError {
+    fn from(err: PowerlogError) -> Self {
+        Self(err)
+    }
+}
+
+/// Serialize a stream of rows into a JSON array body
+///
+/// Errors on the first row still yield a proper problem response. Afterwards the status has been
+/// sent already, so an error mid-stream is logged and aborts the body, which clients see as a
+/// truncated response.
+async fn json_array<S, T>(rows: S) -> Result<Response, AppError>
+where
+    S: Stream<Item = crate::error::Result<T>> + Send + 'static,
+    T: Serialize + Send + 'static,
+{
+    let mut rows = Box::pin(rows);
+    let Some(head) = rows.next().await.transpose()? else {
+        return Ok(([(header::CONTENT_TYPE, "application/json")], "[]").into_response());
+    };
+
+    let mut first = true;
+    let elements = futures::stream::once(async { Ok(head) })
+        .chain(rows)
+        .ready_chunks(1000)
+        .map(move |rows| {
+            let mut chunk = Vec::new();
+            for row in rows {
+                let row = row.inspect_err(|err| eprintln!("streaming rows failed: {err:?}"))?;
+                chunk.push(if first { b'[' } else { b',' });
+                first = false;
+                serde_json::to_writer(&mut chunk, &row)?;
+            }
+            Ok::<_, crate::error::Source>(chunk)
+        });
+    let end = futures::stream::once(async { Ok(b"]".to_vec()) });
+    Ok((
+        [(header::CONTENT_TYPE, "application/json")],
+        Body::from_stream(elements.chain(end)),
+    )
+        .into_response())
+}
+
+/// A GET route that streams the result of a `db::select_*` function as JSON array, which also
+/// gets the current time of the clock
+fn db_route<F, Fut, S, T>(select: F) -> MethodRouter<Arc<AppState>>
+where
+    F: Fn(sea_orm::DatabaseConnection, time::OffsetDateTime) -> Fut + Clone + Send + Sync + 'static,
+    Fut: Future<Output = crate::error::Result<S>> + Send,
+    S: Stream<Item = crate::error::Result<T>> + Send + 'static,
+    T: Serialize + Send + 'static,
+{
+    get(|State(state): State<Arc<AppState>>| async move {
+        let rows = select(state.db.clone(), (state.clock)()).await?;
+        json_array(rows).await
+    })
+}
+
+async fn prometheus_metrics(
+    State(state): State<Arc<AppState>>,
+) -> Result<impl IntoResponse, AppError> {
+    let latest = db::select_latest(&state.db).await?;
+    let channels = match &latest {
+        Some(latest) => db::select_channels(&state.db, latest.id).await?,
+        None => Vec::new(),
+    };
+    let collector_errors = db::collector_errors_count(&state.db).await?;
+    Ok((
+        [(header::CONTENT_TYPE, metrics::CONTENT_TYPE)],
+        metrics::render(latest.as_ref(), &channels, collector_errors),
+    ))
+}
+
+#[derive(serde::Deserialize)]
+struct SavingsQuery {
+    /// `day`, `month` or `year`, defaults to `day`
+    period: Option<String>,
+}
+
+async fn savings(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<SavingsQuery>,
+) -> Result<Json<Vec<finance::Savings>>, AppError> {
+    let period = match query.period.as_deref() {
+        None => finance::Period::Day,
+        Some(period) => period
+            .parse()
+            .map_err(|reason| PowerlogError::InvalidQuery {
+                parameter: "period".to_string(),
+                reason,
+            })?,
+    };
+    let config = finance::Config::from_config();
+    let days = finance::days(&config, &db::select_energy_by_day(&state.db).await?);
+    Ok(Json(finance::savings(&days, period)))
+}
+
+async fn amortization(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<finance::Amortization>, AppError> {
+    let config = finance::Config::from_config();
+    let days = finance::days(&config, &db::select_energy_by_day(&state.db).await?);
+    let today = (state.clock)().date();
+    Ok(Json(finance::amortization(&config, &days, today)))
+}
+
+async fn co2_avoided(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<crate::co2::Report>, AppError> {
+    Ok(Json(crate::co2::configured_report(&state.db).await?))
+}
+
+async fn status(State(state): State<Arc<AppState>>) -> Result<Json<health::Status>, AppError> {
+    let now = (state.clock)();
+    Ok(Json(health::status(&state.db, now).await?))
+}
+
+async fn health_check(State(state): State<Arc<AppState>>) -> Result<Response, AppError> {
+    let now = (state.clock)();
+    let status = health::status(&state.db, now).await?;
+    Ok(if status.healthy() {
+        (StatusCode::OK, "ok").into_response()
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "stale").into_response()
+    })
+}
+
+async fn live_stream(
+    State(state): State<Arc<AppState>>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, axum::Error>>> {
+    // only replay what the client missed when it reconnects
+    let last_event_id = headers
+        .get("last-event-id")
+        .and_then(|id| id.to_str().ok())
+        .and_then(|id| id.parse().ok());
+    let (replay, receiver) = state.live.subscribe(last_event_id);
+
+    let new_samples = futures::stream::unfold(receiver, |mut receiver| async move {
+        loop {
+            match receiver.recv().await {
+                Ok(sample) => return Some((sample, receiver)),
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    let stream = futures::stream::iter(replay)
+        .chain(new_samples)
+        .map(|sample| {
+            Event::default()
+                .event("sample")
+                .id(sample.id.to_string())
+                .json_data(&*sample)
+        });
+
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+async fn dashboard() -> Html<&'static str> {
+    Html(include_str!("../dashboard/index.html"))
+}
+
+async fn dashboard_js() -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/javascript; charset=utf-8")],
+        include_str!("../dashboard/dashboard.js"),
+    )
+}
+
+fn unauthorized() -> Response {
+    (
+        StatusCode::UNAUTHORIZED,
+        [(header::WWW_AUTHENTICATE, "Bearer")],
+        "missing or invalid API key",
+    )
+        .into_response()
+}
+
+// accepts `Authorization: Bearer <key>`, `X-API-Key: <key>` or `?api_key=<key>`, the latter
+// is required for `EventSource` which cannot send custom headers
+fn credentials(request: &Request) -> Option<&str> {
+    let headers = request.headers();
+    if let Some(authorization) = headers.get(header::AUTHORIZATION) {
+        return authorization.to_str().ok()?.strip_prefix("Bearer ");
+    }
+    if let Some(key) = headers.get("x-api-key") {
+        return key.to_str().ok();
+    }
+    request
+        .uri()
+        .query()?
+        .split('&')
+        .find_map(|param| param.strip_prefix("api_key="))
+}
+
+async fn authenticate(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let required = if matches!(*request.method(), Method::GET | Method::HEAD) {
+        Scope::Read
+    } else {
+        Scope::Write
+    };
+
+    let Some(key) = credentials(&request) else {
+        return unauthorized();
+    };
+
+    match db::api_key_scope(&state.db, &auth::hash_key(key)).await {
+        Ok(Some(scope)) if scope >= required => next.run(request).await,
+        Ok(Some(_)) => (StatusCode::FORBIDDEN, "insufficient scope").into_response(),
+        Ok(None) => unauthorized(),
+        Err(err) => AppError(err).into_response(),
+    }
+}
+
+/// All routes of the api binary, spawns a task following the database for the live feed
+pub fn app(db: sea_orm::DatabaseConnection) -> Router {
+    app_with_clock(db, health::system_clock())
+}
+
+/// Like [`app`], but judging the health by the time of `clock`, e.g. of the simulator
+pub fn app_with_clock(db: sea_orm::DatabaseConnection, clock: health::Clock) -> Router {
+    let live = Arc::new(live::Feed::new(config::LIVE_REPLAY));
+    tokio::spawn(live::follow_db(
+        live.clone(),
+        db.clone(),
+        config::LIVE_POLL_INTERVAL,
+    ));
+
+    let shared_state = Arc::new(AppState { db, live, clock });
+
+    let mut data = Router::new()
+        .route("/powerToday", db_route(db::select_power_today))
+        .route(
+            "/generatedByHourToday",
+            db_route(db::select_generated_by_hour_today),
+        )
+        .route(
+            "/generatedByDay",
+            db_route(|db, _| db::select_generated_by_day(db)),
+        )
+        .route("/channelsToday", db_route(db::select_channels_today))
+        .route(
+            "/selfConsumptionByDay",
+            db_route(|db, _| db::select_self_consumption_by_day(db)),
+        )
+        .route("/savings", get(savings))
+        .route("/amortization", get(amortization))
+        .route("/analytics/co2", get(co2_avoided))
+        .route("/metrics", get(prometheus_metrics))
+        .route("/live", get(live_stream))
+        .route("/status", get(status));
+    if config::API_AUTH {
+        data = data.route_layer(middleware::from_fn_with_state(
+            shared_state.clone(),
+            authenticate,
+        ));
+    }
+
+    // the dashboard itself contains no data and is thus always accessible, as is the health
+    // check for load balancers and supervisors
+    Router::new()
+        .route("/", get(dashboard))
+        .route("/dashboard.js", get(dashboard_js))
+        .route("/health", get(health_check))
+        .merge(data)
+        .layer(CompressionLayer::new())
+        .with_state(shared_state)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::AppError;
+    use crate::error::PowerlogError;
+    use axum::{http::StatusCode, response::IntoResponse};
+
+    async fn respond(err: PowerlogError) -> (StatusCode, serde_json::Value) {
+        let response = AppError(err).into_response();
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn problem_details() {
+        let (status, problem) = respond(PowerlogError::InvalidQuery {
+            parameter: "period".to_string(),
+            reason: "unknown period week".to_string(),
+        })
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            problem["detail"],
+            "invalid query parameter period: unknown period week"
+        );
+
+        let (status, problem) = respond(PowerlogError::Database(sea_orm::DbErr::Custom(
+            "no such table: powerlog".to_string(),
+        )))
+        .await;
+        assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(problem["title"], "Internal Server Error");
+        assert!(!problem["detail"].as_str().unwrap().contains("powerlog"));
+    }
+}
@@ -0,0 +1,68 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! API keys for the api binary, only their SHA-256 hash is stored in the database
+use anyhow::{Result, bail};
+use sha2::{Digest, Sha256};
+
+/// Access granted by an API key, `Write` implies `Read`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Scope {
+    Read,
+    Write,
+}
+
+impl Scope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::Read => "read",
+            Scope::Write => "write",
+        }
+    }
+}
+
+impl std::str::FromStr for Scope {
+    type Err = anyhow::Error;
+
+    fn from_str(scope: &str) -> Result<Self> {
+        match scope {
+            "read" => Ok(Scope::Read),
+            "write" => Ok(Scope::Write),
+            _ => bail!("invalid scope {scope:?}, expected read or write"),
+        }
+    }
+}
+
+pub fn generate_key() -> Result<String> {
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes)?;
+    Ok(format!("pl_{}", hex::encode(bytes)))
+}
+
+pub fn hash_key(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::auth::Scope;
+
+    #[test]
+    fn write_implies_read() {
+        assert!(Scope::Write >= Scope::Read);
+        assert!(Scope::Read < Scope::Write);
+        assert_eq!("write".parse::<Scope>().unwrap(), Scope::Write);
+        assert!("admin".parse::<Scope>().is_err());
+    }
+
+    #[test]
+    fn hash_key() {
+        let key = crate::auth::generate_key().unwrap();
+        assert!(key.starts_with("pl_"));
+        assert_eq!(key.len(), 3 + 64);
+        assert_ne!(key, crate::auth::generate_key().unwrap());
+        assert_eq!(
+            crate::auth::hash_key("pl_test"),
+            "ba3dcf482946f8103c247fca3443998a83a41647409d9ba62cc3a922748c1586"
+        );
+    }
+}
@@ -1,131 +1,77 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use aliasable::prelude::AliasableBox;
-use anyhow::Result;
-use std::sync::Arc;
-use tower_http::compression::CompressionLayer;
-
-use axum::{
-    Router,
-    extract::State,
-    http::StatusCode,
-    response::{IntoResponse, Response},
-    routing::get,
-};
-
-use axum_streams::*;
+use anyhow::{Result, bail};
 
+use powerlog::api;
+use powerlog::auth::{self, Scope};
+use powerlog::config;
 use powerlog::db;
-
-struct AppState {
-    db: sea_orm::DatabaseConnection,
-}
-
-// Make our own error that wraps `anyhow::Error`.
-struct AppError(anyhow::Error);
-
-// Tell axum how to convert `AppError` into a response.
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Something went wrong: {}", self.0),
-        )
-            .into_response()
-    }
-}
-
-// This enables using `?` on functions that return `Result<_, anyhow::Error>` to turn them into
-// `Result<_, AppError>`. That way you don't need to do that manually.
-impl<E> From<E> for AppError
-where
-    E: Into<anyhow::Error>,
-{
-    fn from(err: E) -> Self {
-        Self(err.into())
-    }
-}
-
-// see also: https://morestina.net/blog/1868/self-referential-types-for-fun-and-profit
-struct AsyncDbResponse {
-    // actually has lifetime of `db`
-    // declared first so it's droped before `db`
-    stream: StreamBodyAs<'static>,
-    #[allow(dead_code)]
-    db: AliasableBox<sea_orm::DatabaseConnection>,
-}
-
-impl IntoResponse for AsyncDbResponse {
-    fn into_response(self) -> Response {
-        self.stream.into_response()
+use powerlog::notify;
+use powerlog::report;
+use powerlog::server;
+
+const KEY_USAGE: &str = "usage: api key create <name> [read|write]
+       api key revoke <name>
+       api key list";
+
+async fn key_command(db: sea_orm::DatabaseConnection, args: &[&str]) -> Result<()> {
+    let now = time::OffsetDateTime::now_utc();
+    match args {
+        ["create", name] | ["create", name, _] => {
+            let scope = match args.get(2) {
+                Some(scope) => scope.parse()?,
+                None => Scope::Read,
+            };
+            let key = auth::generate_key()?;
+            db::insert_api_key(&db, name, auth::hash_key(&key), scope, now).await?;
+            // this is the only time the key is visible, we only store its hash
+            println!("{key}");
+        }
+        ["revoke", name] => {
+            if !db::revoke_api_key(&db, name, now).await? {
+                bail!("no active API key named {name:?}");
+            }
+        }
+        ["list"] => {
+            for key in db::select_api_keys(&db).await? {
+                let revoked = match key.revoked {
+                    Some(revoked) => format!("revoked {revoked}"),
+                    None => "active".to_string(),
+                };
+                println!(
+                    "{}\t{}\tcreated {}\t{revoked}",
+                    key.name,
+                    key.scope.as_str(),
+                    key.created
+                );
+            }
+        }
+        ["--help"] | ["-h"] => println!("{KEY_USAGE}"),
+        _ => bail!(KEY_USAGE),
     }
-}
-
-fn to_aliasable(db: sea_orm::DatabaseConnection) -> AliasableBox<sea_orm::DatabaseConnection> {
-    AliasableBox::from_unique(Box::new(db))
-}
-
-async fn power_today(State(state): State<Arc<AppState>>) -> Result<impl IntoResponse, AppError> {
-    let db = to_aliasable(state.db.clone());
-    let db_stream = db::select_power_today(db.as_ref()).await?;
-    let json_stream = StreamBodyAsOptions::new()
-        .buffering_ready_items(1000)
-        .json_array(db_stream);
-    let json_stream = unsafe { std::mem::transmute(json_stream) };
-    Ok(AsyncDbResponse {
-        stream: json_stream,
-        db,
-    })
-}
-
-async fn generated_by_hour_today(
-    State(state): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, AppError> {
-    let db = to_aliasable(state.db.clone());
-    let db_stream = db::select_generated_by_hour_today(db.as_ref()).await?;
-    let json_stream = StreamBodyAsOptions::new()
-        .buffering_ready_items(1000)
-        .json_array(db_stream);
-    let json_stream = unsafe { std::mem::transmute(json_stream) };
-    Ok(AsyncDbResponse {
-        stream: json_stream,
-        db,
-    })
-}
-
-async fn generated_by_day(
-    State(state): State<Arc<AppState>>,
-) -> Result<impl IntoResponse, AppError> {
-    let db = to_aliasable(state.db.clone());
-    let db_stream = db::select_generated_by_day(db.as_ref()).await?;
-    let json_stream = StreamBodyAsOptions::new()
-        .buffering_ready_items(1000)
-        .json_array(db_stream);
-    let json_stream = unsafe { std::mem::transmute(json_stream) };
-    Ok(AsyncDbResponse {
-        stream: json_stream,
-        db,
-    })
+    Ok(())
 }
 
 #[tokio::main]
 async fn main() -> Result<()> {
     env_logger::init();
 
-    let db = db::setup();
-    let shared_state = Arc::new(AppState { db: db.await? });
-
-    // build our application with a single route
-    let app = Router::new()
-        .route("/powerToday", get(power_today))
-        .route("/generatedByHourToday", get(generated_by_hour_today))
-        .route("/generatedByDay", get(generated_by_day))
-        .layer(CompressionLayer::new())
-        .with_state(shared_state);
-
-    // run our app with hyper, listening locally on port 3000
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:4334").await?;
-    axum::serve(listener, app).await?;
-
-    Ok(())
+    let db = db::setup().await?;
+
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    let args = args.iter().map(String::as_str).collect::<Vec<_>>();
+    match args.as_slice() {
+        [] => {
+            let client = reqwest::Client::builder()
+                .timeout(config::NOTIFY_TIMEOUT)
+                .build()?;
+            tokio::try_join!(
+                server::serve(config::API_LISTEN, api::app(db.clone())),
+                report::run_schedules(db, config::REPORTS, notify::configured(client)),
+            )?;
+            Ok(())
+        }
+        ["key", args @ ..] => key_command(db, args).await,
+        _ => bail!("usage: api [key ...]\n\n{KEY_USAGE}"),
+    }
 }
@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use anyhow::{Result, bail};
+use std::sync::Arc;
+
+use powerlog::simulator::{
+    self, broker, ez1, household, inbox, open_meteo, opendtu, plant, shelly, sunspec,
+};
+
+const USAGE: &str = "usage: simulator [--listen <address>] [--modbus-listen <address>]
+                 [--smtp-listen <address>] [--mqtt-listen <address>] [--offline]
+                 [--delay-ms <ms>] [--malformed] [--failed] [--alarm]
+                 [--weather-fixture <file>...]
+
+Simulates the local API of an APsystems EZ1 inverter, by default on 127.0.0.1:8050.
+Point the collector to it via POWERLOG_INVERTER_URL=http://127.0.0.1:8050.
+Faults can be changed at runtime via POST /simulator/faults and counters be reset
+via POST /simulator/resetCounters.
+
+The Open-Meteo API is served as well, point the collector to it via
+POWERLOG_WEATHER_URL=http://127.0.0.1:8050. It replies with the given fixture
+files in turn, see fixtures/open-meteo.
+
+The same panels can be observed through the other supported inverters, see
+driver::Config: OpenDTU and a Shelly Plus 1PM on the same address and SunSpec via
+Modbus TCP, by default on 127.0.0.1:5020. A household consuming their output is
+metered by a Tasmota SML reader and a Shelly 3EM, see meter::Config, on the same
+address as well.
+
+Notifications are printed when POSTed below /notify on the same address, e.g.
+/notify/webhook or /notify/message for Gotify, and when mailed to the SMTP server
+listening on 127.0.0.1:2525 by default, see notify::Config.
+
+Messages published to the MQTT broker listening on 127.0.0.1:1883 by default are
+printed as well, see config::MQTT_BROKER.";
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    env_logger::init();
+
+    let mut listen = "127.0.0.1:8050".to_string();
+    let mut modbus_listen = "127.0.0.1:5020".to_string();
+    let mut smtp_listen = "127.0.0.1:2525".to_string();
+    let mut mqtt_listen = "127.0.0.1:1883".to_string();
+    let mut faults = ez1::Faults::default();
+    let mut weather = Vec::new();
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--listen" => match args.next() {
+                Some(address) => listen = address,
+                None => bail!(USAGE),
+            },
+            "--modbus-listen" => match args.next() {
+                Some(address) => modbus_listen = address,
+                None => bail!(USAGE),
+            },
+            "--smtp-listen" => match args.next() {
+                Some(address) => smtp_listen = address,
+                None => bail!(USAGE),
+            },
+            "--mqtt-listen" => match args.next() {
+                Some(address) => mqtt_listen = address,
+                None => bail!(USAGE),
+            },
+            "--offline" => faults.offline = true,
+            "--delay-ms" => match args.next().map(|delay| delay.parse()) {
+                Some(Ok(delay)) => faults.delay_ms = delay,
+                _ => bail!(USAGE),
+            },
+            "--malformed" => faults.malformed = true,
+            "--failed" => faults.failed = true,
+            "--alarm" => faults.alarm = true,
+            "--weather-fixture" => match args.next() {
+                Some(path) => weather.push(open_meteo::Reply::fixture(path)?),
+                None => bail!(USAGE),
+            },
+            "--help" | "-h" => {
+                println!("{USAGE}");
+                return Ok(());
+            }
+            _ => bail!(USAGE),
+        }
+    }
+
+    let plant = Arc::new(plant::Plant::new(simulator::system_clock()));
+    let ez1 = Arc::new(ez1::Ez1::with_plant(plant.clone()));
+    ez1.set_faults(faults);
+    if weather.is_empty() {
+        weather.push(open_meteo::Reply::ok(include_str!(
+            "../../fixtures/open-meteo/dwd-icon-overcast.json"
+        )));
+    }
+    let open_meteo = Arc::new(open_meteo::OpenMeteo::new(weather));
+
+    let opendtu = Arc::new(opendtu::OpenDtu::new(plant.clone()));
+    let shelly = Arc::new(shelly::ShellyPlusPm::new(plant.clone()));
+    let household = Arc::new(household::Household::new(plant.clone()));
+    let sunspec = Arc::new(sunspec::SunSpec::new(plant));
+    let inbox = Arc::new(inbox::Inbox::default());
+    let broker = Arc::new(broker::Broker::default());
+
+    let listener = tokio::net::TcpListener::bind(&listen).await?;
+    let modbus_listener = tokio::net::TcpListener::bind(&modbus_listen).await?;
+    let smtp_listener = tokio::net::TcpListener::bind(&smtp_listen).await?;
+    let mqtt_listener = tokio::net::TcpListener::bind(&mqtt_listen).await?;
+    println!(
+        "simulating EZ1 {}, OpenDTU {} and Shelly {} on http://{}",
+        ez1::DEVICE_ID,
+        opendtu::SERIAL,
+        shelly::MAC,
+        listener.local_addr()?
+    );
+    println!(
+        "simulating SunSpec {} on {}",
+        sunspec::SERIAL,
+        modbus_listener.local_addr()?
+    );
+    println!(
+        "receiving notifications on http://{0}/notify and SMTP on {1}",
+        listener.local_addr()?,
+        smtp_listener.local_addr()?
+    );
+    println!("receiving MQTT messages on {}", mqtt_listener.local_addr()?);
+    let app = ez1
+        .router()
+        .merge(open_meteo.router())
+        .merge(opendtu.router())
+        .merge(shelly.router())
+        .merge(household.router())
+        .merge(inbox.clone().router());
+    tokio::try_join!(
+        async { axum::serve(listener, app).await },
+        sunspec.serve(modbus_listener),
+        inbox.serve_smtp(smtp_listener),
+        broker.serve(mqtt_listener),
+    )?;
+
+    Ok(())
+}
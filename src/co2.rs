@@ -0,0 +1,288 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! CO2 emissions avoided by the generated energy, see `config::CO2_INTENSITY`
+use std::collections::BTreeMap;
+
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+
+use crate::db::{EnergyByDay, EnergyByHour};
+use crate::error::PowerlogError;
+use crate::finance::Period;
+
+/// Carbon intensity of the grid in g CO2 per kWh
+pub enum Intensity {
+    /// a static factor per year, years without one use the closest configured year
+    PerYear(&'static [(i32, f64)]),
+    /// an hourly series from a CSV file with a header, e.g. exported from Energy-Charts or
+    /// electricityMaps, the columns are selected by their header
+    Csv {
+        path: &'static str,
+        time_column: &'static str,
+        intensity_column: &'static str,
+    },
+}
+
+/// The factor of the year, or of the closest configured year
+pub fn per_year(factors: &[(i32, f64)], year: i32) -> Option<f64> {
+    factors
+        .iter()
+        .min_by_key(|(configured, _)| (configured - year).abs())
+        .map(|(_, factor)| *factor)
+}
+
+/// Normalize the timestamp formats of common exports to RFC 3339, e.g.
+/// `2024-01-01 00:00:00` or `2024-01-01T00:00+01:00`
+fn parse_time(value: &str) -> Option<time::OffsetDateTime> {
+    use time::format_description::well_known::Rfc3339;
+
+    let value = value.trim().replace(' ', "T");
+    let (date, time) = value.split_once('T')?;
+    let offset_at = time.find(['Z', '+', '-']).unwrap_or(time.len());
+    let (clock, offset) = time.split_at(offset_at);
+    let clock = match clock.len() {
+        5 => format!("{clock}:00"),
+        _ => clock.to_string(),
+    };
+    let offset = match offset {
+        "" => "Z",
+        offset => offset,
+    };
+    time::OffsetDateTime::parse(&format!("{date}T{clock}{offset}"), &Rfc3339).ok()
+}
+
+fn hour(time: time::OffsetDateTime) -> time::OffsetDateTime {
+    let time = time.to_offset(time::UtcOffset::UTC);
+    time.replace_time(time::Time::from_hms(time.hour(), 0, 0).unwrap())
+}
+
+/// Intensity per hour in UTC
+#[derive(Debug, Default)]
+pub struct Series(BTreeMap<time::OffsetDateTime, f64>);
+
+impl Series {
+    /// Rows with unparsable values are skipped, like the unit rows of some exports.
+    /// Semicolon separated files are expected to use decimal commas.
+    pub fn parse_csv(content: &str, time_column: &str, intensity_column: &str) -> Result<Self> {
+        let mut lines = content.lines();
+        let header = lines.next().context("empty CSV")?;
+        let separator = match header.contains(';') && !header.contains(',') {
+            true => ';',
+            false => ',',
+        };
+        let split = |line: &'_ str| -> Vec<String> {
+            line.split(separator)
+                .map(|cell| cell.trim().trim_matches('"').to_string())
+                .collect()
+        };
+        let header = split(header);
+        let column = |name: &str| {
+            header
+                .iter()
+                .position(|cell| cell == name)
+                .with_context(|| format!("no column {name:?}"))
+        };
+        let (time_column, intensity_column) = (column(time_column)?, column(intensity_column)?);
+
+        let mut series = BTreeMap::new();
+        for line in lines {
+            let cells = split(line);
+            let (Some(time), Some(intensity)) =
+                (cells.get(time_column), cells.get(intensity_column))
+            else {
+                continue;
+            };
+            let intensity = match separator {
+                ';' => intensity.replace(',', "."),
+                _ => intensity.clone(),
+            };
+            if let (Some(time), Ok(intensity)) = (parse_time(time), intensity.parse::<f64>()) {
+                series.insert(hour(time), intensity);
+            }
+        }
+        if series.is_empty() {
+            bail!("no intensities in CSV");
+        }
+        Ok(Self(series))
+    }
+
+    pub fn get(&self, time: time::OffsetDateTime) -> Option<f64> {
+        self.0.get(&hour(time)).copied()
+    }
+}
+
+/// The CO2 avoided over a period
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Avoided {
+    pub period: String,
+    /// in kWh
+    pub generated: f64,
+    pub avoided_kg: f64,
+    /// energy in kWh generated while the intensity was unknown, not part of `avoided_kg`
+    pub without_intensity: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub days: Vec<Avoided>,
+    pub months: Vec<Avoided>,
+    pub total_kg: f64,
+}
+
+/// Sum up chronological `(date, generated, intensity)` entries per `period`
+fn aggregate(entries: &[(time::Date, f64, Option<f64>)], period: Period) -> Vec<Avoided> {
+    let mut result: Vec<Avoided> = Vec::new();
+    for &(date, generated, intensity) in entries {
+        let label = period.label(date);
+        if result.last().is_none_or(|last| last.period != label) {
+            result.push(Avoided {
+                period: label,
+                generated: 0.0,
+                avoided_kg: 0.0,
+                without_intensity: 0.0,
+            });
+        }
+        let sum = result.last_mut().unwrap();
+        sum.generated += generated;
+        match intensity {
+            Some(intensity) => sum.avoided_kg += generated * intensity / 1000.0,
+            None => sum.without_intensity += generated,
+        }
+    }
+    result
+}
+
+fn report(entries: &[(time::Date, f64, Option<f64>)]) -> Report {
+    let days = aggregate(entries, Period::Day);
+    Report {
+        total_kg: days.iter().map(|day| day.avoided_kg).sum(),
+        months: aggregate(entries, Period::Month),
+        days,
+    }
+}
+
+pub fn report_per_year(factors: &[(i32, f64)], energy: &[EnergyByDay]) -> Report {
+    let entries: Vec<_> = energy
+        .iter()
+        .map(|day| (day.date, day.generated, per_year(factors, day.date.year())))
+        .collect();
+    report(&entries)
+}
+
+pub fn report_hourly(series: &Series, energy: &[EnergyByHour]) -> Report {
+    let entries: Vec<_> = energy
+        .iter()
+        .map(|hour| (hour.hour.date(), hour.generated, series.get(hour.hour)))
+        .collect();
+    report(&entries)
+}
+
+/// The report for the configured intensity, reading the CSV file on every call
+pub async fn configured_report(db: &sea_orm::DatabaseConnection) -> crate::error::Result<Report> {
+    match crate::config::CO2_INTENSITY {
+        Intensity::PerYear(factors) => Ok(report_per_year(
+            factors,
+            &crate::db::select_energy_by_day(db).await?,
+        )),
+        Intensity::Csv {
+            path,
+            time_column,
+            intensity_column,
+        } => {
+            let content = tokio::fs::read_to_string(path).await.map_err(|err| {
+                PowerlogError::Configuration {
+                    context: format!("failed to read {path}"),
+                    source: err.into(),
+                }
+            })?;
+            let series =
+                Series::parse_csv(&content, time_column, intensity_column).map_err(|err| {
+                    PowerlogError::Configuration {
+                        context: format!("invalid intensities in {path}"),
+                        source: err.into(),
+                    }
+                })?;
+            Ok(report_hourly(
+                &series,
+                &crate::db::select_energy_by_hour(db).await?,
+            ))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::co2::{Series, per_year, report_hourly, report_per_year};
+    use crate::db::{EnergyByDay, EnergyByHour};
+    use crate::finance::date;
+    use time::Month::{December, January};
+
+    #[test]
+    fn static_factors() {
+        let factors = &[(2023, 400.0), (2024, 300.0)];
+        assert_eq!(per_year(factors, 2022), Some(400.0));
+        assert_eq!(per_year(factors, 2025), Some(300.0));
+        assert_eq!(per_year(&[], 2025), None);
+
+        let energy = [
+            EnergyByDay {
+                date: date(2023, December, 31),
+                generated: 2.0,
+                feed_in: None,
+            },
+            EnergyByDay {
+                date: date(2024, January, 1),
+                generated: 1.0,
+                feed_in: None,
+            },
+        ];
+        let report = report_per_year(factors, &energy);
+        assert_eq!(report.days.len(), 2);
+        assert_eq!(report.months[1].period, "2024-01");
+        assert_eq!(report.months[0].avoided_kg, 0.8);
+        assert_eq!(report.total_kg, 1.1);
+    }
+
+    #[test]
+    fn hourly_series() {
+        let electricity_maps = "\
+Datetime (UTC),Country,Carbon Intensity gCO₂eq/kWh (LCA)
+2024-01-01 10:00:00,Germany,400.5
+2024-01-01 11:00:00,Germany,300
+2024-01-01 12:00:00,Germany,
+";
+        let series = Series::parse_csv(
+            electricity_maps,
+            "Datetime (UTC)",
+            "Carbon Intensity gCO₂eq/kWh (LCA)",
+        )
+        .unwrap();
+        let at = |hour| {
+            date(2024, January, 1)
+                .with_hms(hour, 0, 0)
+                .unwrap()
+                .assume_utc()
+        };
+        assert_eq!(series.get(at(10)), Some(400.5));
+        assert_eq!(series.get(at(12)), None);
+
+        let energy_charts = "\
+\"Datum\";\"CO2-Intensität\"
+\"\";\"g/kWh\"
+\"2024-01-01T12:00+01:00\";\"300,5\"
+";
+        let series = Series::parse_csv(energy_charts, "Datum", "CO2-Intensität").unwrap();
+        assert_eq!(series.get(at(11)), Some(300.5));
+        assert!(Series::parse_csv(energy_charts, "Datum", "Strompreis").is_err());
+
+        let energy = [10, 11, 12].map(|hour| EnergyByHour {
+            hour: at(hour),
+            generated: 1.0,
+        });
+        let report = report_hourly(&series, &energy);
+        assert_eq!(report.days.len(), 1);
+        assert_eq!(report.days[0].generated, 3.0);
+        assert_eq!(report.days[0].avoided_kg, 0.3005);
+        assert_eq!(report.days[0].without_intensity, 2.0);
+    }
+}
@@ -0,0 +1,403 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Gathering a [`Sample`] from the inverter, weather API and meter and writing it to all sinks
+use anyhow::{Result, bail};
+use std::time::Duration;
+
+use crate::driver::InverterDriver;
+use crate::error::PowerlogError;
+use crate::meter::Meter;
+use crate::notify::{self, Notifier};
+use crate::retry::{Policy, retry};
+use crate::sample::{Retries, Sample};
+use crate::{alert, config, db, sink, sun, weather};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InverterStatus {
+    Online,
+    Offline,
+    Error,
+}
+
+impl InverterStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InverterStatus::Online => "online",
+            InverterStatus::Offline => "offline",
+            InverterStatus::Error => "error",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WeatherStatus {
+    Ok,
+    Error,
+    /// the weather API is not queried when the inverter is offline
+    Skipped,
+}
+
+impl WeatherStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            WeatherStatus::Ok => "ok",
+            WeatherStatus::Error => "error",
+            WeatherStatus::Skipped => "skipped",
+        }
+    }
+}
+
+/// How a collector run ended, reported to the supervisor via the exit code
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// all data was gathered and written
+    Complete,
+    /// the inverter is switched off, which is expected at night
+    Offline,
+    /// a sample was stored, but some of its data or some sinks are missing
+    Partial,
+    /// nothing was stored, but the next run may well succeed
+    Recoverable,
+    /// nothing was stored and the next run will likely fail as well
+    Fatal,
+}
+
+impl Outcome {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Outcome::Complete => "complete",
+            Outcome::Offline => "offline",
+            Outcome::Partial => "partial",
+            Outcome::Recoverable => "recoverable",
+            Outcome::Fatal => "fatal",
+        }
+    }
+
+    /// Fatal errors exit with 1 like any other error, recoverable ones with `EX_TEMPFAIL`
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Outcome::Complete | Outcome::Offline => 0,
+            Outcome::Fatal => 1,
+            Outcome::Partial => 2,
+            Outcome::Recoverable => 75,
+        }
+    }
+
+    /// Errors of the inverter or weather API are recoverable, everything else, most notably
+    /// database errors, is considered fatal
+    pub fn classify(err: &anyhow::Error) -> Self {
+        match err.downcast_ref::<crate::error::PowerlogError>() {
+            Some(crate::error::PowerlogError::Database(_)) | None => Outcome::Fatal,
+            Some(_) => Outcome::Recoverable,
+        }
+    }
+}
+
+/// Outcome of a single collector invocation
+#[derive(Debug)]
+pub struct Run {
+    pub start: time::OffsetDateTime,
+    pub duration: time::Duration,
+    pub inverter: InverterStatus,
+    pub weather: WeatherStatus,
+    pub errors: Vec<String>,
+    /// whether a row was written to the `powerlog` table
+    pub inserted: bool,
+    pub outcome: Outcome,
+}
+
+impl Run {
+    pub fn new(start: time::OffsetDateTime) -> Self {
+        Self {
+            start,
+            duration: time::Duration::ZERO,
+            inverter: InverterStatus::Error,
+            weather: WeatherStatus::Skipped,
+            errors: Vec::new(),
+            inserted: false,
+            outcome: Outcome::Fatal,
+        }
+    }
+}
+
+fn http_client(
+    connect_timeout: Duration,
+    read_timeout: Duration,
+) -> reqwest::Result<reqwest::Client> {
+    reqwest::Client::builder()
+        .connect_timeout(connect_timeout)
+        .read_timeout(read_timeout)
+        .gzip(true)
+        .brotli(true)
+        .deflate(true)
+        .build()
+}
+
+pub struct Collector {
+    pub db: sea_orm::DatabaseConnection,
+    pub inverter: Box<dyn InverterDriver>,
+    pub inverter_retry: Policy,
+    pub inverter_precheck_retry: Policy,
+    /// also used by the sinks
+    pub client: reqwest::Client,
+    pub weather_url: String,
+    pub weather_retry: Policy,
+    /// household meter read alongside the inverter, see `config::METER`
+    pub meter: Option<Box<dyn Meter>>,
+    pub meter_retry: Policy,
+    pub alert_rules: &'static [alert::Rule],
+    pub notifiers: Vec<Box<dyn Notifier>>,
+}
+
+impl Collector {
+    /// A collector as configured in [`crate::config`], the addresses can be overridden via
+    /// the `POWERLOG_INVERTER_URL`, `POWERLOG_WEATHER_URL` and `POWERLOG_METER_URL`
+    /// environment variables
+    pub fn from_config(db: sea_orm::DatabaseConnection) -> reqwest::Result<Self> {
+        Ok(Self {
+            db,
+            inverter: config::INVERTER.driver(
+                http_client(
+                    config::INVERTER_CONNECT_TIMEOUT,
+                    config::INVERTER_READ_TIMEOUT,
+                )?,
+                std::env::var("POWERLOG_INVERTER_URL").ok(),
+            ),
+            inverter_retry: config::INVERTER_RETRY,
+            inverter_precheck_retry: config::INVERTER_PRECHECK_RETRY,
+            client: http_client(
+                config::WEATHER_CONNECT_TIMEOUT,
+                config::WEATHER_READ_TIMEOUT,
+            )?,
+            weather_url: std::env::var("POWERLOG_WEATHER_URL")
+                .unwrap_or_else(|_| config::WEATHER_URL.to_string()),
+            weather_retry: config::WEATHER_RETRY,
+            meter: match &config::METER {
+                Some(meter) => Some(meter.meter(
+                    http_client(config::METER_CONNECT_TIMEOUT, config::METER_READ_TIMEOUT)?,
+                    std::env::var("POWERLOG_METER_URL").ok(),
+                )),
+                None => None,
+            },
+            meter_retry: config::METER_RETRY,
+            alert_rules: config::ALERT_RULES,
+            notifiers: notify::configured(http_client(
+                config::NOTIFY_TIMEOUT,
+                config::NOTIFY_TIMEOUT,
+            )?),
+        })
+    }
+
+    /// Collect a sample for `time` and record the attempt in the `collector_runs` table,
+    /// including the failed ones, to explain gaps in the data
+    pub async fn run(&self, time: time::OffsetDateTime) -> Result<Outcome> {
+        let started = std::time::Instant::now();
+        let mut run = Run::new(time);
+        run.outcome = match self.collect(&mut run).await {
+            Ok(outcome) => outcome,
+            Err(err) => {
+                eprintln!("Error: {err:?}");
+                run.errors.push(format!("{err:#}"));
+                Outcome::classify(&err)
+            }
+        };
+        run.duration = started.elapsed().try_into().unwrap_or(time::Duration::MAX);
+        // failing notifications are recorded, but don't change the outcome
+        if let Err(err) = self.alert(&mut run).await {
+            eprintln!("Error: {err:?}");
+            run.errors.push(format!("alerts: {err:#}"));
+        }
+        db::insert_collector_run(&self.db, &run).await?;
+
+        Ok(run.outcome)
+    }
+
+    /// Check the alert rules against the data stored so far
+    async fn alert(&self, run: &mut Run) -> Result<()> {
+        if self.alert_rules.is_empty() {
+            return Ok(());
+        }
+        let context = alert::Context::load(&self.db, run.start, run.inverter).await?;
+        let errors = alert::evaluate(&self.db, self.alert_rules, &self.notifiers, &context).await?;
+        for (name, err) in &errors {
+            eprintln!("{name} notifier: {err:?}");
+            db::insert_collector_error(&self.db, run.start, format!("{name} notifier: {err:?}"))
+                .await?;
+            run.errors.push(format!("{name} notifier: {err:#}"));
+        }
+        Ok(())
+    }
+
+    async fn collect(&self, run: &mut Run) -> Result<Outcome> {
+        let db = &self.db;
+        let time = run.start;
+
+        // fail early when the inverter is offline
+        let precheck = retry(&self.inverter_precheck_retry, || self.inverter.on_off()).await;
+        let mut retries = Retries {
+            inverter: precheck.retries,
+            weather: 0,
+        };
+        let on_off = match precheck.result {
+            Err(PowerlogError::InverterOffline(e)) => {
+                println!("inverter is offline: {:?}", e);
+                run.inverter = InverterStatus::Offline;
+                return Ok(Outcome::Offline);
+            }
+            Err(e) => {
+                db::insert_collector_error(db, time, format!("{e:?}")).await?;
+                return Err(e.into());
+            }
+            Ok(on_off) => on_off,
+        };
+        run.inverter = InverterStatus::Online;
+
+        // access inverter and weather API concurrently
+        let (output_data, max_power, model, weather, meter) = futures::join!(
+            retry(&self.inverter_retry, || self.inverter.output_data()),
+            retry(&self.inverter_retry, || self.inverter.max_power()),
+            retry(&self.inverter_retry, || self.inverter.device_info()),
+            retry(&self.weather_retry, || weather::query(
+                &self.client,
+                &self.weather_url
+            )),
+            async {
+                match &self.meter {
+                    Some(meter) => Some(retry(&self.meter_retry, || meter.read()).await),
+                    None => None,
+                }
+            },
+        );
+
+        // a sample with missing data is better than no sample at all
+        let mut partial = false;
+
+        // gracefully handle failures of weather api access
+        retries.weather = weather.retries;
+        let weather = match weather.result {
+            Ok(weather) => {
+                run.weather = WeatherStatus::Ok;
+                Some(weather)
+            }
+            Err(err) => {
+                eprintln!("{:?}", err);
+                run.weather = WeatherStatus::Error;
+                partial = true;
+                db::insert_collector_error(db, time, format!("{err:?}")).await?;
+                run.errors.push(format!("{:#}", anyhow::Error::new(err)));
+                None
+            }
+        };
+
+        // like the weather, consumption data is nice to have
+        let meter = match meter.map(|meter| meter.result) {
+            None => None,
+            Some(Ok(reading)) => Some(reading),
+            Some(Err(err)) => {
+                eprintln!("{:?}", err);
+                partial = true;
+                db::insert_collector_error(db, time, format!("{err:?}")).await?;
+                run.errors.push(format!("{:#}", anyhow::Error::new(err)));
+                None
+            }
+        };
+
+        // don't do anything if the output data can't be read out, it's the essence of each sample
+        retries.inverter += output_data.retries + max_power.retries + model.retries;
+        let output_data = match output_data.result {
+            Ok(output_data) => output_data,
+            Err(err) => {
+                run.inverter = InverterStatus::Error;
+                db::insert_collector_error(db, time, format!("{err:?}")).await?;
+                return Err(err.into());
+            }
+        };
+
+        // the max power is a setting that rarely changes, fall back to the last known value
+        let max_power = match max_power.result {
+            Ok(max_power) => max_power,
+            Err(err) => {
+                db::insert_collector_error(db, time, format!("{err:?}")).await?;
+                let Some(latest) = db::select_latest(db).await? else {
+                    return Err(err.into());
+                };
+                eprintln!("{err:?}, using last known max power {}", latest.max_power);
+                run.errors.push(format!("{:#}", anyhow::Error::new(err)));
+                partial = true;
+                latest.max_power.into()
+            }
+        };
+        // like without weather data, a sample without the model is only partial
+        let model = match model.result {
+            Ok(model) => Some(model),
+            Err(err) => {
+                eprintln!("{err:?}");
+                db::insert_collector_error(db, time, format!("{err:?}")).await?;
+                run.errors.push(format!("{:#}", anyhow::Error::new(err)));
+                partial = true;
+                None
+            }
+        };
+        let sunpos = sun::position(time);
+        println!(
+            "weather: {weather:?}, output data: {output_data:?}, model: {model:?}, max power: {max_power} on/off: {on_off:?}, meter: {meter:?}, sun: {sunpos:?}"
+        );
+
+        let sample = Sample {
+            time,
+            output_data,
+            model,
+            max_power,
+            on_off,
+            weather,
+            meter,
+            sunpos,
+            retries,
+        };
+
+        // write data, a failing sink must not affect the others
+        let sinks = sink::configured(db.clone(), self.client.clone());
+        let errors = sink::fan_out(&sinks, &sample).await;
+        for (name, err) in &errors {
+            eprintln!("{name} sink: {err:?}");
+            db::insert_collector_error(db, time, format!("{name} sink: {err:?}")).await?;
+            run.errors.push(format!("{name} sink: {err:#}"));
+        }
+        run.inserted = !errors.iter().any(|(name, _)| *name == "sqlite");
+        if errors.len() == sinks.len() {
+            bail!("failed to write sample to any sink");
+        }
+
+        Ok(if partial || !errors.is_empty() {
+            Outcome::Partial
+        } else {
+            Outcome::Complete
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::collector::Outcome;
+    use crate::error::PowerlogError;
+
+    #[test]
+    fn classify_errors() {
+        let bad_response = anyhow::Error::new(PowerlogError::BadResponse {
+            endpoint: "getMaxPower",
+            reason: "message FAILED".to_string(),
+        });
+        assert_eq!(Outcome::classify(&bad_response), Outcome::Recoverable);
+        assert_eq!(Outcome::Recoverable.exit_code(), 75);
+
+        let database = anyhow::Error::new(PowerlogError::Database(sea_orm::DbErr::Custom(
+            "disk I/O error".to_string(),
+        )));
+        assert_eq!(Outcome::classify(&database), Outcome::Fatal);
+        assert_eq!(
+            Outcome::classify(&anyhow::anyhow!("something else")),
+            Outcome::Fatal
+        );
+        assert_eq!(Outcome::Offline.exit_code(), 0);
+    }
+}
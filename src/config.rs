@@ -0,0 +1,155 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::retry::Policy;
+use std::time::Duration;
+
+pub const LATITUDE: f64 = 52.500;
+pub const LONGITUDE: f64 = 13.493;
+pub const INVERTER_IP: &str = "192.168.178.150";
+// base URL of the local API, can be overridden via `POWERLOG_INVERTER_URL`, e.g. to use the
+// `simulator` binary instead
+pub const INVERTER_URL: &str = const_format::formatcp!("http://{INVERTER_IP}:8050");
+// the inverter to collect from, `POWERLOG_INVERTER_URL` overrides its URL or Modbus address,
+// e.g. `driver::Config::OpenDtu { url: "http://192.168.178.151", serial: "116180000001" }`
+pub const INVERTER: crate::driver::Config = crate::driver::Config::Ez1 {
+    url: INVERTER_URL,
+    lifetime_offsets: LIFETIME_OFFSETS,
+};
+
+// MQTT broker host and port to publish every sample to, disabled when `None`
+pub const MQTT_BROKER: Option<(&str, u16)> = None;
+pub const MQTT_TOPIC_PREFIX: &str = "powerlog";
+// see https://www.home-assistant.io/integrations/mqtt/#mqtt-discovery
+pub const MQTT_DISCOVERY_PREFIX: &str = "homeassistant";
+
+// InfluxDB line protocol output for every sample, disabled when `None`
+pub const INFLUX_OUTPUT: Option<crate::influx::Output> = None;
+
+// addresses the api binary listens on, see `server::Listen`
+pub const API_LISTEN: &[crate::server::Listen] = &[crate::server::Listen::Tcp("127.0.0.1:4334")];
+
+// require an API key for all data routes of the api binary, see `api key --help`
+pub const API_AUTH: bool = false;
+
+// number of samples replayed to clients connecting to the live stream
+pub const LIVE_REPLAY: u32 = 12;
+// how often the API checks the database for new samples
+pub const LIVE_POLL_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5);
+
+// how often the collector is run, e.g. by a systemd timer or cron
+pub const COLLECT_INTERVAL: std::time::Duration = std::time::Duration::from_secs(5 * 60);
+// data is considered stale when the latest sample is older than this
+pub const STALE_AFTER: std::time::Duration = std::time::Duration::from_secs(3 * 5 * 60);
+// sun altitude in radians above which the inverter is expected to produce data
+pub const DAYLIGHT_SUN_ALTITUDE: f64 = 0.15;
+
+// the EZ1 often drops the first request after waking up, so retry a bit
+pub const INVERTER_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+pub const INVERTER_READ_TIMEOUT: Duration = Duration::from_secs(10);
+pub const INVERTER_RETRY: Policy = Policy {
+    attempts: 3,
+    initial_backoff: Duration::from_secs(1),
+    max_backoff: Duration::from_secs(8),
+};
+// the on/off check is expected to fail all night long, so keep it short
+pub const INVERTER_PRECHECK_RETRY: Policy = Policy {
+    attempts: 2,
+    initial_backoff: Duration::from_secs(1),
+    max_backoff: Duration::from_secs(1),
+};
+
+// Fixup bogus data, the channel counter reset at 2025-08-31T15:00:01.561151476Z in the hardware for some reason
+// and now reports new values starting at zero again, breaking our analysis as we expect monotonically increasing
+// values. We now manually add some value in the EZ1 driver and I fixed the database manually via
+//
+//   UPDATE powerlog SET energy_total_ch2 = energy_total_ch2 + 540.606323242188 WHERE time > '2025-08-31T15:00:01' AND energy_total_ch2 < 500;
+//
+// Set `device_id` to the id reported by getDeviceInfo to only correct that inverter.
+pub const LIFETIME_OFFSETS: &[crate::driver::LifetimeOffset] = &[crate::driver::LifetimeOffset {
+    device_id: None,
+    channel: 2,
+    energy: 540.606323242188,
+}];
+
+// base URL of the Open-Meteo API, can be overridden via `POWERLOG_WEATHER_URL`
+pub const WEATHER_URL: &str = "https://api.open-meteo.com";
+pub const WEATHER_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+pub const WEATHER_READ_TIMEOUT: Duration = Duration::from_secs(20);
+pub const WEATHER_RETRY: Policy = Policy {
+    attempts: 3,
+    initial_backoff: Duration::from_secs(2),
+    max_backoff: Duration::from_secs(30),
+};
+
+// household meter reading grid import and export, see `meter::Config`, disabled when `None`.
+// `POWERLOG_METER_URL` overrides the URL of HTTP meters, e.g.
+//   Some(meter::Config::Shelly3Em { url: "http://192.168.178.152" })
+pub const METER: Option<crate::meter::Config> = None;
+pub const METER_CONNECT_TIMEOUT: Duration = Duration::from_secs(3);
+pub const METER_READ_TIMEOUT: Duration = Duration::from_secs(10);
+pub const METER_RETRY: Policy = Policy {
+    attempts: 2,
+    initial_backoff: Duration::from_secs(1),
+    max_backoff: Duration::from_secs(4),
+};
+// how long to wait for a message of an MQTT meter
+pub const METER_MQTT_TIMEOUT: Duration = Duration::from_secs(20);
+
+// tariffs per kWh for `/savings` and `/amortization`, the first rate valid on a day applies
+pub const IMPORT_PRICES: &[crate::finance::Rate] = &[crate::finance::Rate {
+    from: crate::finance::date(2024, time::Month::January, 1),
+    until: None,
+    per_kwh: 0.35,
+}];
+pub const FEED_IN_RATES: &[crate::finance::Rate] = &[];
+// cost of inverter, panels, mounting and installation
+pub const HARDWARE_COST: f64 = 650.0;
+// share of the generated energy consumed by the household on days without meter readings
+pub const ASSUMED_SELF_CONSUMPTION: f64 = 0.8;
+
+// grid carbon intensity in g CO2/kWh for `/analytics/co2`, e.g. an hourly series via
+//   co2::Intensity::Csv { path: "co2.csv", time_column: "Datetime (UTC)",
+//       intensity_column: "Carbon Intensity gCO₂eq/kWh (LCA)" }
+pub const CO2_INTENSITY: crate::co2::Intensity =
+    crate::co2::Intensity::PerYear(&[(2023, 380.0), (2024, 363.0)]);
+
+// rated power of all panels in kWp and the share of it expected to reach the grid, to
+// estimate the yield from the irradiance
+pub const PEAK_POWER: f64 = 0.8;
+pub const PERFORMANCE_RATIO: f64 = 0.8;
+
+// rules checked after every collector run, each condition may only be used once
+pub const ALERT_RULES: &[crate::alert::Rule] = &[
+    crate::alert::Rule {
+        condition: crate::alert::Condition::OffDuringDaylight,
+        cooldown: Duration::from_secs(6 * 60 * 60),
+    },
+    crate::alert::Rule {
+        condition: crate::alert::Condition::ChannelZero { min_power: 20.0 },
+        cooldown: Duration::from_secs(6 * 60 * 60),
+    },
+    crate::alert::Rule {
+        condition: crate::alert::Condition::LifetimeReset,
+        cooldown: Duration::from_secs(60 * 60),
+    },
+    crate::alert::Rule {
+        condition: crate::alert::Condition::YieldBelowIrradiance {
+            peak_power: PEAK_POWER,
+            performance_ratio: PERFORMANCE_RATIO,
+            ratio: 0.5,
+            min_expected: 1.0,
+        },
+        cooldown: Duration::from_secs(24 * 60 * 60),
+    },
+];
+// where alerts are sent to, e.g.
+//   notify::Config::Ntfy { url: "https://ntfy.sh/my-powerlog", token: None }
+//   notify::Config::Smtp { address: "192.168.178.1:25", from: "powerlog@example.org",
+//       to: &["me@example.org"] }
+pub const NOTIFIERS: &[crate::notify::Config] = &[];
+pub const NOTIFY_TIMEOUT: Duration = Duration::from_secs(10);
+
+// reports delivered by the api binary while it runs, e.g. yesterday's as Markdown at midnight UTC
+//   report::Schedule { period: finance::Period::Day, at: time::Time::MIDNIGHT,
+//       format: report::Format::Markdown, delivery: report::Delivery::Notify }
+pub const REPORTS: &[crate::report::Schedule] = &[];
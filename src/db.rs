@@ -0,0 +1,1239 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use futures::StreamExt;
+use sea_orm::{ConnectionTrait, DbBackend, DbErr, EntityTrait, FromQueryResult, Statement};
+
+use crate::auth::Scope;
+use crate::error::{PowerlogError, Result};
+use serde::Serialize;
+
+mod powerlog {
+    use sea_orm::entity::prelude::*;
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    #[sea_orm(table_name = "powerlog")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+
+        pub time: time::OffsetDateTime,
+
+        pub power_ch1: f32,
+        pub power_ch2: f32,
+
+        pub energy_today_ch1: f32,
+        pub energy_today_ch2: f32,
+
+        pub energy_total_ch1: f32,
+        pub energy_total_ch2: f32,
+
+        pub max_power: f32,
+
+        #[sea_orm(nullable)]
+        pub cloud_cover: f32,
+
+        #[sea_orm(nullable)]
+        pub terrestrial_radiation: f32,
+        #[sea_orm(nullable)]
+        pub direct_radiation: f32,
+        #[sea_orm(nullable)]
+        pub diffuse_radiation: f32,
+        #[sea_orm(nullable)]
+        pub shortwave_radiation: f32,
+        #[sea_orm(nullable)]
+        pub direct_normal_irradiance: f32,
+        #[sea_orm(nullable)]
+        pub global_tilted_irradiance: f32,
+
+        pub sun_azimuth: f32,
+        pub sun_altitude: f32,
+
+        #[sea_orm(nullable)]
+        pub on_off: bool,
+
+        #[sea_orm(nullable)]
+        pub inverter_retries: i32,
+        #[sea_orm(nullable)]
+        pub weather_retries: i32,
+
+        // as reported by getDeviceInfo
+        #[sea_orm(nullable)]
+        pub model: String,
+        #[sea_orm(nullable)]
+        pub firmware: String,
+
+        // as reported by the household meter, see `config::METER`
+        #[sea_orm(nullable)]
+        pub grid_import_total: f32,
+        #[sea_orm(nullable)]
+        pub grid_export_total: f32,
+        #[sea_orm(nullable)]
+        pub grid_power: f32,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+/// The readings of every channel of a `powerlog` row, the `*_ch1` and `*_ch2` columns there
+/// only cover the first two channels
+mod channel_readings {
+    use sea_orm::entity::prelude::*;
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    #[sea_orm(table_name = "channel_readings")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+
+        #[sea_orm(indexed)]
+        pub powerlog_id: i32,
+        /// 1-based like in the local API
+        pub channel: i32,
+
+        pub power: f32,
+        pub energy_today: f32,
+        pub energy_total: f32,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+mod collector_errors {
+    use sea_orm::entity::prelude::*;
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    #[sea_orm(table_name = "collector_errors")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+
+        pub time: time::OffsetDateTime,
+
+        pub message: String,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+mod collector_runs {
+    use sea_orm::entity::prelude::*;
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    #[sea_orm(table_name = "collector_runs")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+
+        pub start: time::OffsetDateTime,
+        pub duration_ms: i64,
+
+        pub inverter: String,
+        pub weather: String,
+        pub error: Option<String>,
+        pub inserted: bool,
+        #[sea_orm(nullable)]
+        pub outcome: String,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+mod api_keys {
+    use sea_orm::entity::prelude::*;
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    #[sea_orm(table_name = "api_keys")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+
+        #[sea_orm(unique)]
+        pub name: String,
+        #[sea_orm(unique)]
+        pub key_hash: String,
+        pub scope: String,
+
+        pub created: time::OffsetDateTime,
+        pub revoked: Option<time::OffsetDateTime>,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+/// The state of each alert rule, see [`crate::alert`]
+mod alerts {
+    use sea_orm::entity::prelude::*;
+    #[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
+    #[sea_orm(table_name = "alerts")]
+    pub struct Model {
+        #[sea_orm(primary_key)]
+        pub id: i32,
+
+        #[sea_orm(unique)]
+        pub rule: String,
+        pub active: bool,
+        /// when the rule started firing most recently
+        pub since: Option<time::OffsetDateTime>,
+        /// when a notification about the rule firing was sent last
+        pub last_notified: Option<time::OffsetDateTime>,
+        pub message: Option<String>,
+    }
+
+    #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+    pub enum Relation {}
+
+    impl ActiveModelBehavior for ActiveModel {}
+}
+
+/// Create the table for `entity` and add columns that were introduced after the table was
+/// created initially. New columns thus always have to be nullable. Returns whether the table
+/// was created.
+async fn create_table<E>(db: &sea_orm::DatabaseConnection, entity: E) -> Result<bool>
+where
+    E: EntityTrait,
+{
+    use sea_orm::{ColumnTrait, Iden, Iterable, sea_query::Table};
+
+    let builder = db.get_database_backend();
+    let schema = sea_orm::Schema::new(builder);
+    let existed = db
+        .query_one(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            "SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = ?",
+            [entity.table_name().into()],
+        ))
+        .await?
+        .is_some();
+    let create_table = builder.build(schema.create_table_from_entity(entity).if_not_exists());
+    db.execute(create_table).await?;
+    for mut index in schema.create_index_from_entity(entity) {
+        db.execute(builder.build(index.if_not_exists())).await?;
+    }
+
+    let columns = db
+        .query_all(Statement::from_string(
+            DbBackend::Sqlite,
+            format!("PRAGMA table_info({})", entity.table_name()),
+        ))
+        .await?
+        .iter()
+        .map(|row| row.try_get::<String>("", "name"))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for column in E::Column::iter() {
+        if columns.contains(&column.to_string()) {
+            continue;
+        }
+        // existing rows have no value for it
+        if !column.def().is_null() {
+            return Err(DbErr::Migration(format!(
+                "new column {}.{} must be nullable",
+                entity.table_name(),
+                column.to_string()
+            ))
+            .into());
+        }
+        let add_column = builder.build(
+            Table::alter()
+                .table(entity)
+                .add_column(&mut schema.get_column_def::<E>(column)),
+        );
+        db.execute(add_column).await?;
+    }
+
+    Ok(!existed)
+}
+
+pub async fn setup() -> Result<sea_orm::DatabaseConnection> {
+    connect("sqlite://powerlog.sqlite3?mode=rwc").await
+}
+
+/// Connect to the database at `url` and create or migrate all tables
+pub async fn connect(url: &str) -> Result<sea_orm::DatabaseConnection> {
+    let db = sea_orm::Database::connect(url).await?;
+
+    create_table(&db, powerlog::Entity).await?;
+    if create_table(&db, channel_readings::Entity).await? {
+        backfill_channel_readings(&db).await?;
+    }
+    create_table(&db, collector_errors::Entity).await?;
+    create_table(&db, collector_runs::Entity).await?;
+    create_table(&db, api_keys::Entity).await?;
+    create_table(&db, alerts::Entity).await?;
+
+    Ok(db)
+}
+
+/// Copy the channels of rows written before the `channel_readings` table existed
+async fn backfill_channel_readings(db: &sea_orm::DatabaseConnection) -> Result<()> {
+    db.execute_unprepared(
+        r#"INSERT INTO channel_readings (powerlog_id, channel, power, energy_today, energy_total)
+                SELECT id, channel.number,
+                    CASE channel.number WHEN 1 THEN power_ch1 ELSE power_ch2 END,
+                    CASE channel.number WHEN 1 THEN energy_today_ch1 ELSE energy_today_ch2 END,
+                    CASE channel.number WHEN 1 THEN energy_total_ch1 ELSE energy_total_ch2 END
+                FROM powerlog, (SELECT 1 AS number UNION ALL SELECT 2) AS channel
+                WHERE NOT EXISTS (SELECT 1 FROM channel_readings WHERE powerlog_id = powerlog.id)
+                ORDER BY id, channel.number"#,
+    )
+    .await?;
+    Ok(())
+}
+
+pub async fn insert(
+    db: &sea_orm::DatabaseConnection,
+    sample: &crate::sample::Sample,
+) -> Result<()> {
+    use sea_orm::ActiveValue::{NotSet, Set};
+    use sea_orm::TransactionTrait;
+
+    let output_data = &sample.output_data;
+    let (channel1, channel2) = (output_data.channel(1), output_data.channel(2));
+
+    let mut row = powerlog::ActiveModel {
+        // primary key, will be auto generated
+        id: NotSet,
+
+        time: Set(sample.time),
+
+        power_ch1: Set(channel1.power as f32),
+        power_ch2: Set(channel2.power as f32),
+        energy_today_ch1: Set(channel1.energy_generation_startup as f32),
+        energy_today_ch2: Set(channel2.energy_generation_startup as f32),
+        energy_total_ch1: Set(channel1.energy_generation_lifetime as f32),
+        energy_total_ch2: Set(channel2.energy_generation_lifetime as f32),
+        max_power: Set(sample.max_power as f32),
+
+        // optional values, see below
+        cloud_cover: NotSet,
+        terrestrial_radiation: NotSet,
+        direct_radiation: NotSet,
+        diffuse_radiation: NotSet,
+        shortwave_radiation: NotSet,
+        direct_normal_irradiance: NotSet,
+        global_tilted_irradiance: NotSet,
+
+        sun_azimuth: Set(sample.sunpos.azimuth as f32),
+        sun_altitude: Set(sample.sunpos.altitude as f32),
+
+        on_off: Set(sample.on_off == crate::inverter::Status::On),
+
+        inverter_retries: Set(sample.retries.inverter as i32),
+        weather_retries: Set(sample.retries.weather as i32),
+
+        model: NotSet,
+        firmware: NotSet,
+
+        grid_import_total: NotSet,
+        grid_export_total: NotSet,
+        grid_power: NotSet,
+    };
+
+    if let Some(model) = &sample.model {
+        row.model = Set(model.name.clone());
+        row.firmware = Set(model.firmware.clone());
+    }
+
+    if let Some(meter) = &sample.meter {
+        row.grid_import_total = Set(meter.import_total as f32);
+        row.grid_export_total = Set(meter.export_total as f32);
+        row.grid_power = Set(meter.power as f32);
+    }
+
+    if let Some(weather) = &sample.weather {
+        row.cloud_cover = Set(weather.cloud_cover / 100.0);
+        row.terrestrial_radiation = Set(weather.terrestrial_radiation_instant);
+        row.direct_radiation = Set(weather.direct_radiation_instant);
+        row.diffuse_radiation = Set(weather.diffuse_radiation_instant);
+        row.shortwave_radiation = Set(weather.shortwave_radiation_instant);
+        row.direct_normal_irradiance = Set(weather.direct_normal_irradiance_instant);
+        row.global_tilted_irradiance = Set(weather.global_tilted_irradiance_instant);
+    }
+
+    let channels = output_data.channels.iter().enumerate();
+    let txn = db.begin().await?;
+    // only insert, reading the row back would fail on the NULL columns without weather data
+    let powerlog_id = powerlog::Entity::insert(row)
+        .exec(&txn)
+        .await?
+        .last_insert_id;
+    channel_readings::Entity::insert_many(channels.map(|(i, channel)| {
+        channel_readings::ActiveModel {
+            id: NotSet,
+            powerlog_id: Set(powerlog_id),
+            channel: Set(i as i32 + 1),
+            power: Set(channel.power as f32),
+            energy_today: Set(channel.energy_generation_startup as f32),
+            energy_total: Set(channel.energy_generation_lifetime as f32),
+        }
+    }))
+    .exec(&txn)
+    .await?;
+    txn.commit().await?;
+
+    Ok(())
+}
+
+pub async fn insert_collector_run(
+    db: &sea_orm::DatabaseConnection,
+    run: &crate::collector::Run,
+) -> Result<()> {
+    use sea_orm::ActiveModelTrait;
+    use sea_orm::ActiveValue::{NotSet, Set};
+
+    collector_runs::ActiveModel {
+        id: NotSet,
+        start: Set(run.start),
+        duration_ms: Set(run.duration.whole_milliseconds() as i64),
+        inverter: Set(run.inverter.as_str().to_string()),
+        weather: Set(run.weather.as_str().to_string()),
+        error: Set((!run.errors.is_empty()).then(|| run.errors.join("; "))),
+        inserted: Set(run.inserted),
+        outcome: Set(run.outcome.as_str().to_string()),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+#[derive(FromQueryResult, Serialize, Debug)]
+pub struct CollectorRun {
+    #[serde(with = "time::serde::iso8601")]
+    pub start: time::OffsetDateTime,
+    pub duration_ms: i64,
+    pub inverter: String,
+    pub weather: String,
+    pub error: Option<String>,
+    pub inserted: bool,
+    pub outcome: Option<String>,
+}
+
+pub async fn select_last_collector_run(
+    db: &sea_orm::DatabaseConnection,
+) -> Result<Option<CollectorRun>> {
+    Ok(CollectorRun::find_by_statement(Statement::from_string(
+        DbBackend::Sqlite,
+        r#"SELECT * FROM collector_runs ORDER BY id DESC LIMIT 1"#,
+    ))
+    .one(db)
+    .await?)
+}
+
+/// Record an error encountered by the collector, see also [`collector_errors_count`]
+pub async fn insert_collector_error(
+    db: &sea_orm::DatabaseConnection,
+    time: time::OffsetDateTime,
+    message: String,
+) -> Result<()> {
+    use sea_orm::ActiveModelTrait;
+    use sea_orm::ActiveValue::{NotSet, Set};
+
+    collector_errors::ActiveModel {
+        id: NotSet,
+        time: Set(time),
+        message: Set(message),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn collector_errors_count(db: &sea_orm::DatabaseConnection) -> Result<u64> {
+    use sea_orm::PaginatorTrait;
+    Ok(collector_errors::Entity::find().count(db).await?)
+}
+
+#[derive(FromQueryResult, Clone, Debug, PartialEq)]
+pub struct Alert {
+    pub rule: String,
+    pub active: bool,
+    pub since: Option<time::OffsetDateTime>,
+    pub last_notified: Option<time::OffsetDateTime>,
+    pub message: Option<String>,
+}
+
+pub async fn select_alerts(db: &sea_orm::DatabaseConnection) -> Result<Vec<Alert>> {
+    Ok(Alert::find_by_statement(Statement::from_string(
+        DbBackend::Sqlite,
+        r#"SELECT rule, active, since, last_notified, message FROM alerts ORDER BY rule"#,
+    ))
+    .all(db)
+    .await?)
+}
+
+pub async fn upsert_alert(db: &sea_orm::DatabaseConnection, alert: &Alert) -> Result<()> {
+    use sea_orm::ActiveValue::{NotSet, Set};
+    use sea_orm::sea_query::OnConflict;
+
+    alerts::Entity::insert(alerts::ActiveModel {
+        id: NotSet,
+        rule: Set(alert.rule.clone()),
+        active: Set(alert.active),
+        since: Set(alert.since),
+        last_notified: Set(alert.last_notified),
+        message: Set(alert.message.clone()),
+    })
+    .on_conflict(
+        OnConflict::column(alerts::Column::Rule)
+            .update_columns([
+                alerts::Column::Active,
+                alerts::Column::Since,
+                alerts::Column::LastNotified,
+                alerts::Column::Message,
+            ])
+            .to_owned(),
+    )
+    .exec(db)
+    .await?;
+
+    Ok(())
+}
+
+pub struct ApiKey {
+    pub name: String,
+    pub scope: crate::auth::Scope,
+    pub created: time::OffsetDateTime,
+    pub revoked: Option<time::OffsetDateTime>,
+}
+
+fn parse_scope(scope: &str) -> Result<Scope> {
+    scope
+        .parse()
+        .map_err(|err: anyhow::Error| DbErr::Type(err.to_string()).into())
+}
+
+pub async fn insert_api_key(
+    db: &sea_orm::DatabaseConnection,
+    name: &str,
+    key_hash: String,
+    scope: crate::auth::Scope,
+    time: time::OffsetDateTime,
+) -> Result<()> {
+    use sea_orm::ActiveModelTrait;
+    use sea_orm::ActiveValue::{NotSet, Set};
+
+    api_keys::ActiveModel {
+        id: NotSet,
+        name: Set(name.to_string()),
+        key_hash: Set(key_hash),
+        scope: Set(scope.as_str().to_string()),
+        created: Set(time),
+        revoked: Set(None),
+    }
+    .insert(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Revoke the API key with the given `name`, returns false if no such active key exists
+pub async fn revoke_api_key(
+    db: &sea_orm::DatabaseConnection,
+    name: &str,
+    time: time::OffsetDateTime,
+) -> Result<bool> {
+    use sea_orm::{ColumnTrait, QueryFilter, sea_query::Expr};
+
+    let result = api_keys::Entity::update_many()
+        .col_expr(api_keys::Column::Revoked, Expr::value(time))
+        .filter(api_keys::Column::Name.eq(name))
+        .filter(api_keys::Column::Revoked.is_null())
+        .exec(db)
+        .await?;
+
+    Ok(result.rows_affected > 0)
+}
+
+pub async fn select_api_keys(db: &sea_orm::DatabaseConnection) -> Result<Vec<ApiKey>> {
+    api_keys::Entity::find()
+        .all(db)
+        .await?
+        .into_iter()
+        .map(|key| {
+            Ok(ApiKey {
+                name: key.name,
+                scope: parse_scope(&key.scope)?,
+                created: key.created,
+                revoked: key.revoked,
+            })
+        })
+        .collect()
+}
+
+/// The scope of the active API key with the given hash, if any
+pub async fn api_key_scope(
+    db: &sea_orm::DatabaseConnection,
+    key_hash: &str,
+) -> Result<Option<crate::auth::Scope>> {
+    use sea_orm::{ColumnTrait, QueryFilter};
+
+    api_keys::Entity::find()
+        .filter(api_keys::Column::KeyHash.eq(key_hash))
+        .filter(api_keys::Column::Revoked.is_null())
+        .one(db)
+        .await?
+        .map(|key| parse_scope(&key.scope))
+        .transpose()
+}
+
+/// Stream the result of `statement` without borrowing `db`, so it can e.g. be returned as the
+/// body of an HTTP response. The rows are fetched by a separate task and passed on through a
+/// bounded channel, which stops the task once the returned stream gets dropped.
+/// The midnight (UTC) starting the day of `now`
+fn midnight(now: time::OffsetDateTime) -> time::OffsetDateTime {
+    now.to_offset(time::UtcOffset::UTC)
+        .replace_time(time::Time::MIDNIGHT)
+}
+
+async fn stream_select<T>(
+    db: sea_orm::DatabaseConnection,
+    statement: Statement,
+) -> Result<impl futures::stream::Stream<Item = Result<T>> + 'static>
+where
+    T: FromQueryResult + Send + 'static,
+{
+    let (started_sender, started) = tokio::sync::oneshot::channel();
+    let (sender, receiver) = tokio::sync::mpsc::channel(256);
+
+    tokio::spawn(async move {
+        let stream = powerlog::Entity::find()
+            .from_raw_sql(statement)
+            .into_model::<T>()
+            .stream(&db)
+            .await;
+        let mut stream = match stream {
+            Ok(stream) => {
+                let _ = started_sender.send(Ok(()));
+                stream
+            }
+            Err(err) => {
+                let _ = started_sender.send(Err(err));
+                return;
+            }
+        };
+        while let Some(row) = stream.next().await {
+            if sender.send(row).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    started
+        .await
+        .map_err(|_| DbErr::Custom("query task failed".to_string()))??;
+
+    Ok(futures::stream::unfold(
+        receiver,
+        |mut receiver| async move {
+            let row = receiver.recv().await?;
+            Some((row.map_err(PowerlogError::from), receiver))
+        },
+    ))
+}
+
+#[derive(FromQueryResult, Serialize)]
+pub struct PowerToday {
+    #[serde(skip)]
+    pub id: i32,
+    #[serde(with = "time::serde::iso8601")]
+    pub time: time::OffsetDateTime,
+    pub power_ch1: f32,
+    pub power_ch2: f32,
+    pub energy_today_ch1: f32,
+    pub energy_today_ch2: f32,
+
+    pub energy_total_ch1: f32,
+    pub energy_total_ch2: f32,
+
+    pub max_power: f32,
+
+    pub cloud_cover: Option<f32>,
+
+    pub terrestrial_radiation: Option<f32>,
+    pub direct_radiation: Option<f32>,
+    pub diffuse_radiation: Option<f32>,
+    pub shortwave_radiation: Option<f32>,
+    pub direct_normal_irradiance: Option<f32>,
+    pub global_tilted_irradiance: Option<f32>,
+
+    pub sun_azimuth: f32,
+    pub sun_altitude: f32,
+
+    pub on_off: Option<bool>,
+
+    pub grid_import_total: Option<f32>,
+    pub grid_export_total: Option<f32>,
+    pub grid_power: Option<f32>,
+}
+
+/// The samples since the midnight (UTC) starting the day of `now`
+pub async fn select_power_today(
+    db: sea_orm::DatabaseConnection,
+    now: time::OffsetDateTime,
+) -> Result<impl futures::stream::Stream<Item = Result<PowerToday>>> {
+    stream_select::<PowerToday>(
+        db,
+        Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            r#"SELECT * FROM powerlog WHERE time >= ?"#,
+            [midnight(now).into()],
+        ),
+    )
+    .await
+}
+
+pub async fn select_latest(db: &sea_orm::DatabaseConnection) -> Result<Option<PowerToday>> {
+    Ok(PowerToday::find_by_statement(Statement::from_string(
+        DbBackend::Sqlite,
+        r#"SELECT * FROM powerlog ORDER BY id DESC LIMIT 1"#,
+    ))
+    .one(db)
+    .await?)
+}
+
+/// The last `count` samples in chronological order
+pub async fn select_recent(
+    db: &sea_orm::DatabaseConnection,
+    count: u32,
+) -> Result<Vec<PowerToday>> {
+    Ok(
+        PowerToday::find_by_statement(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            r#"SELECT * FROM (SELECT * FROM powerlog ORDER BY id DESC LIMIT ?) ORDER BY id ASC"#,
+            [count.into()],
+        ))
+        .all(db)
+        .await?,
+    )
+}
+
+/// All samples inserted after the one with the given `id` in chronological order
+pub async fn select_after(db: &sea_orm::DatabaseConnection, id: i32) -> Result<Vec<PowerToday>> {
+    Ok(
+        PowerToday::find_by_statement(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            r#"SELECT * FROM powerlog WHERE id > ? ORDER BY id ASC"#,
+            [id.into()],
+        ))
+        .all(db)
+        .await?,
+    )
+}
+
+#[derive(FromQueryResult, Serialize)]
+pub struct ChannelReading {
+    #[serde(with = "time::serde::iso8601")]
+    pub time: time::OffsetDateTime,
+    pub channel: i32,
+    pub power: f32,
+    pub energy_today: f32,
+    pub energy_total: f32,
+}
+
+/// The readings of all channels since the midnight (UTC) starting the day of `now`, ordered by
+/// time and channel
+pub async fn select_channels_today(
+    db: sea_orm::DatabaseConnection,
+    now: time::OffsetDateTime,
+) -> Result<impl futures::stream::Stream<Item = Result<ChannelReading>>> {
+    stream_select::<ChannelReading>(
+        db,
+        Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            r#"SELECT time, channel, power, energy_today, energy_total
+                FROM channel_readings JOIN powerlog ON powerlog.id = powerlog_id
+                WHERE time >= ?
+                ORDER BY powerlog_id, channel"#,
+            [midnight(now).into()],
+        ),
+    )
+    .await
+}
+
+/// The readings of all channels of the `powerlog` row with the given `id`
+pub async fn select_channels(
+    db: &sea_orm::DatabaseConnection,
+    id: i32,
+) -> Result<Vec<ChannelReading>> {
+    Ok(
+        ChannelReading::find_by_statement(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            r#"SELECT time, channel, power, energy_today, energy_total
+                FROM channel_readings JOIN powerlog ON powerlog.id = powerlog_id
+                WHERE powerlog_id = ?
+                ORDER BY channel"#,
+            [id.into()],
+        ))
+        .all(db)
+        .await?,
+    )
+}
+
+/// A sample summed up over all channels
+#[derive(FromQueryResult, Clone, Debug, PartialEq)]
+pub struct Totals {
+    pub time: time::OffsetDateTime,
+    /// lifetime energy in kWh
+    pub generated: f64,
+    /// in W
+    pub power: f64,
+    pub max_power: f64,
+    pub cloud_cover: Option<f64>,
+    pub global_tilted_irradiance: Option<f64>,
+}
+
+/// The samples taken from `start` until before `end` in chronological order
+pub async fn select_totals(
+    db: &sea_orm::DatabaseConnection,
+    start: time::OffsetDateTime,
+    end: time::OffsetDateTime,
+) -> Result<Vec<Totals>> {
+    Ok(Totals::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Sqlite,
+        r#"SELECT time, SUM(energy_total) AS generated, SUM(power) AS power, max_power,
+                cloud_cover, global_tilted_irradiance
+            FROM channel_readings JOIN powerlog ON powerlog.id = powerlog_id
+            WHERE time >= ? AND time < ?
+            GROUP BY powerlog_id
+            ORDER BY powerlog_id"#,
+        [start.into(), end.into()],
+    ))
+    .all(db)
+    .await?)
+}
+
+/// The readings of all channels from `start` until before `end`, ordered by time and channel
+pub async fn select_channels_between(
+    db: &sea_orm::DatabaseConnection,
+    start: time::OffsetDateTime,
+    end: time::OffsetDateTime,
+) -> Result<Vec<ChannelReading>> {
+    Ok(
+        ChannelReading::find_by_statement(Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            r#"SELECT time, channel, power, energy_today, energy_total
+                FROM channel_readings JOIN powerlog ON powerlog.id = powerlog_id
+                WHERE time >= ? AND time < ?
+                ORDER BY powerlog_id, channel"#,
+            [start.into(), end.into()],
+        ))
+        .all(db)
+        .await?,
+    )
+}
+
+#[derive(FromQueryResult)]
+pub struct DatabaseStats {
+    pub rows: i64,
+    pub size_bytes: i64,
+}
+
+pub async fn database_stats(db: &sea_orm::DatabaseConnection) -> Result<DatabaseStats> {
+    DatabaseStats::find_by_statement(Statement::from_string(
+        DbBackend::Sqlite,
+        r#"SELECT
+                (SELECT COUNT(*) FROM powerlog) AS rows,
+                (SELECT page_count * page_size FROM pragma_page_count(), pragma_page_size()) AS size_bytes"#,
+    ))
+    .one(db)
+    .await?
+    .ok_or_else(|| DbErr::RecordNotFound("database stats".to_string()).into())
+}
+
+#[derive(FromQueryResult)]
+struct WeatherCoverage {
+    attempts: i64,
+    successes: i64,
+}
+
+/// Share of the weather queries of the 24h before `now` that succeeded, `None` without queries
+pub async fn weather_success_rate(
+    db: &sea_orm::DatabaseConnection,
+    now: time::OffsetDateTime,
+) -> Result<Option<f64>> {
+    let coverage = WeatherCoverage::find_by_statement(Statement::from_sql_and_values(
+        DbBackend::Sqlite,
+        r#"SELECT COUNT(*) AS attempts, COUNT(*) FILTER (WHERE weather = 'ok') AS successes
+                FROM collector_runs
+                WHERE weather != 'skipped' AND start > ?"#,
+        [(now - time::Duration::DAY).into()],
+    ))
+    .one(db)
+    .await?;
+    Ok(coverage
+        .filter(|coverage| coverage.attempts > 0)
+        .map(|coverage| coverage.successes as f64 / coverage.attempts as f64))
+}
+
+#[derive(FromQueryResult, Serialize)]
+pub struct GeneratedByHour {
+    hour: String,
+    ch1: Option<f32>,
+    ch2: Option<f32>,
+}
+
+/// The energy generated per hour since the midnight (UTC) starting the day of `now`
+pub async fn select_generated_by_hour_today(
+    db: sea_orm::DatabaseConnection,
+    now: time::OffsetDateTime,
+) -> Result<impl futures::stream::Stream<Item = Result<GeneratedByHour>>> {
+    stream_select::<GeneratedByHour>(
+        db,
+        Statement::from_sql_and_values(
+            DbBackend::Sqlite,
+            r#"SELECT
+                    strftime('%H', time) AS hour,
+                    (MAX(energy_total_ch1) - (lag(energy_total_ch1) OVER win)) as ch1,
+                    (MAX(energy_total_ch2) - (lag(energy_total_ch2) OVER win)) as ch2
+                FROM powerlog
+                WHERE time >= ?
+                GROUP BY hour
+                WINDOW win AS (ROWS 1 PRECEDING)"#,
+            [midnight(now).into()],
+        ),
+    )
+    .await
+}
+
+#[derive(FromQueryResult, Serialize)]
+pub struct GeneratedByDay {
+    date: String,
+    ch1: Option<f32>,
+    ch2: Option<f32>,
+}
+
+pub async fn select_generated_by_day(
+    db: sea_orm::DatabaseConnection,
+) -> Result<impl futures::stream::Stream<Item = Result<GeneratedByDay>>> {
+    stream_select::<GeneratedByDay>(
+        db,
+        Statement::from_string(
+            DbBackend::Sqlite,
+            r#"SELECT
+                    date(time) AS date,
+                    (MAX(energy_total_ch1) - (lag(energy_total_ch1, 1, 0) OVER win)) as ch1,
+                    (MAX(energy_total_ch2) - (lag(energy_total_ch2, 1, 0) OVER win)) as ch2
+                FROM powerlog
+                GROUP BY date
+                WINDOW win AS (ROWS 1 PRECEDING)
+                ORDER BY date ASC"#,
+        ),
+    )
+    .await
+}
+
+/// The energy balance of a day in kWh, only covering days with readings of the household meter
+#[derive(FromQueryResult, Serialize)]
+pub struct SelfConsumptionByDay {
+    date: String,
+    generated: Option<f32>,
+    imported: Option<f32>,
+    feed_in: Option<f32>,
+    self_consumed: Option<f32>,
+    consumption: Option<f32>,
+    /// share of the generated energy consumed by the household
+    self_consumption_ratio: Option<f32>,
+    /// share of the consumption covered by the generated energy
+    autarky: Option<f32>,
+}
+
+pub async fn select_self_consumption_by_day(
+    db: sea_orm::DatabaseConnection,
+) -> Result<impl futures::stream::Stream<Item = Result<SelfConsumptionByDay>>> {
+    stream_select::<SelfConsumptionByDay>(
+        db,
+        Statement::from_string(
+            DbBackend::Sqlite,
+            // like in generatedByDay, a day starts at the last counter values of the
+            // previous one, the first day at its own first values
+            r#"WITH samples AS (
+                    SELECT
+                        date(time) AS date,
+                        (SELECT SUM(energy_total) FROM channel_readings
+                            WHERE powerlog_id = powerlog.id) AS generated,
+                        grid_import_total AS imported,
+                        grid_export_total AS exported
+                    FROM powerlog
+                    WHERE grid_import_total IS NOT NULL AND grid_export_total IS NOT NULL
+                ), days AS (
+                    SELECT
+                        date,
+                        MAX(generated) - (lag(MAX(generated), 1, MIN(generated)) OVER win)
+                            AS generated,
+                        MAX(imported) - (lag(MAX(imported), 1, MIN(imported)) OVER win)
+                            AS imported,
+                        MAX(exported) - (lag(MAX(exported), 1, MIN(exported)) OVER win)
+                            AS feed_in
+                    FROM samples
+                    GROUP BY date
+                    WINDOW win AS (ORDER BY date ROWS 1 PRECEDING)
+                ), balance AS (
+                    SELECT *, MAX(generated - feed_in, 0) AS self_consumed FROM days
+                )
+                SELECT
+                    date,
+                    generated,
+                    imported,
+                    feed_in,
+                    self_consumed,
+                    imported + self_consumed AS consumption,
+                    self_consumed / NULLIF(generated, 0) AS self_consumption_ratio,
+                    self_consumed / NULLIF(imported + self_consumed, 0) AS autarky
+                FROM balance
+                ORDER BY date ASC"#,
+        ),
+    )
+    .await
+}
+
+/// The energy generated by all channels and fed in per day in kWh, see [`crate::finance`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnergyByDay {
+    pub date: time::Date,
+    pub generated: f64,
+    /// `None` without meter readings
+    pub feed_in: Option<f64>,
+}
+
+pub async fn select_energy_by_day(db: &sea_orm::DatabaseConnection) -> Result<Vec<EnergyByDay>> {
+    #[derive(FromQueryResult)]
+    struct Row {
+        date: String,
+        generated: Option<f64>,
+        feed_in: Option<f64>,
+    }
+
+    let rows = Row::find_by_statement(Statement::from_string(
+        DbBackend::Sqlite,
+        // like in selfConsumptionByDay, a day starts at the last counter values of the previous
+        // one, the first day at its own first values
+        r#"WITH samples AS (
+                SELECT
+                    date(time) AS date,
+                    (SELECT SUM(energy_total) FROM channel_readings
+                        WHERE powerlog_id = powerlog.id) AS generated,
+                    grid_export_total AS exported
+                FROM powerlog
+            ), days AS (
+                SELECT
+                    date,
+                    MAX(generated) AS generated,
+                    MIN(generated) AS first_generated,
+                    MAX(exported) AS exported,
+                    MIN(exported) AS first_exported
+                FROM samples
+                GROUP BY date
+            )
+            SELECT
+                date,
+                generated - (lag(generated, 1, first_generated) OVER win) AS generated,
+                exported - (lag(exported, 1, first_exported) OVER win) AS feed_in
+            FROM days
+            WINDOW win AS (ORDER BY date ROWS 1 PRECEDING)
+            ORDER BY date ASC"#,
+    ))
+    .all(db)
+    .await?;
+
+    let format = time::format_description::well_known::Iso8601::DATE;
+    rows.into_iter()
+        .map(|row| {
+            let date = time::Date::parse(&row.date, &format).map_err(|err| {
+                PowerlogError::Database(DbErr::Custom(format!("invalid date {}: {err}", row.date)))
+            })?;
+            Ok(EnergyByDay {
+                date,
+                generated: row.generated.unwrap_or(0.0),
+                feed_in: row.feed_in,
+            })
+        })
+        .collect()
+}
+
+/// The energy generated by all channels per hour in kWh, see [`crate::co2`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct EnergyByHour {
+    /// start of the hour in UTC
+    pub hour: time::OffsetDateTime,
+    pub generated: f64,
+}
+
+pub async fn select_energy_by_hour(db: &sea_orm::DatabaseConnection) -> Result<Vec<EnergyByHour>> {
+    #[derive(FromQueryResult)]
+    struct Row {
+        hour: String,
+        generated: Option<f64>,
+    }
+
+    let rows = Row::find_by_statement(Statement::from_string(
+        DbBackend::Sqlite,
+        // like in select_energy_by_day, the first hour starts at its own first values
+        r#"WITH samples AS (
+                SELECT
+                    strftime('%Y-%m-%dT%H:00:00Z', time) AS hour,
+                    (SELECT SUM(energy_total) FROM channel_readings
+                        WHERE powerlog_id = powerlog.id) AS generated
+                FROM powerlog
+            ), hours AS (
+                SELECT hour, MAX(generated) AS generated, MIN(generated) AS first_generated
+                FROM samples
+                GROUP BY hour
+            )
+            SELECT hour, generated - (lag(generated, 1, first_generated) OVER win) AS generated
+            FROM hours
+            WINDOW win AS (ORDER BY hour ROWS 1 PRECEDING)
+            ORDER BY hour ASC"#,
+    ))
+    .all(db)
+    .await?;
+
+    let format = time::format_description::well_known::Rfc3339;
+    rows.into_iter()
+        .map(|row| {
+            let hour = time::OffsetDateTime::parse(&row.hour, &format).map_err(|err| {
+                PowerlogError::Database(DbErr::Custom(format!("invalid hour {}: {err}", row.hour)))
+            })?;
+            Ok(EnergyByHour {
+                hour,
+                generated: row.generated.unwrap_or(0.0),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use sea_orm::ConnectionTrait;
+
+    #[tokio::test]
+    async fn channel_readings() {
+        let db = crate::db::connect("sqlite::memory:").await.unwrap();
+
+        // a row written before the channel readings existed
+        db.execute_unprepared(
+            r#"INSERT INTO powerlog (time, power_ch1, power_ch2, energy_today_ch1,
+                    energy_today_ch2, energy_total_ch1, energy_total_ch2, max_power, sun_azimuth,
+                    sun_altitude) VALUES ('2024-04-16T09:30:00Z', 10, 20, 1, 2, 100, 200,
+                    800, 0, 0)"#,
+        )
+        .await
+        .unwrap();
+        crate::db::backfill_channel_readings(&db).await.unwrap();
+        crate::db::backfill_channel_readings(&db).await.unwrap();
+        let legacy = crate::db::select_channels(&db, 1).await.unwrap();
+        assert_eq!(legacy.len(), 2);
+        assert_eq!((legacy[1].channel, legacy[1].energy_total), (2, 200.0));
+
+        let mut sample = crate::sample::tests::sample();
+        sample
+            .output_data
+            .channels
+            .push(crate::inverter::OutputChannel {
+                power: 7.0,
+                energy_generation_startup: 8.0,
+                energy_generation_lifetime: 9.0,
+            });
+        crate::db::insert(&db, &sample).await.unwrap();
+        let channels = crate::db::select_channels(&db, 2).await.unwrap();
+        let power: Vec<_> = channels.iter().map(|channel| channel.power).collect();
+        assert_eq!(power, [1.5, 4.0, 7.0]);
+        let latest = crate::db::select_latest(&db).await.unwrap().unwrap();
+        assert_eq!((latest.power_ch1, latest.power_ch2), (1.5, 4.0));
+    }
+
+    #[tokio::test]
+    async fn create_table_reports_new_tables() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        assert!(
+            crate::db::create_table(&db, crate::db::powerlog::Entity)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !crate::db::create_table(&db, crate::db::powerlog::Entity)
+                .await
+                .unwrap()
+        );
+        assert!(
+            crate::db::create_table(&db, crate::db::channel_readings::Entity)
+                .await
+                .unwrap()
+        );
+        assert!(
+            !crate::db::create_table(&db, crate::db::channel_readings::Entity)
+                .await
+                .unwrap()
+        );
+    }
+
+    #[tokio::test]
+    async fn new_columns_must_be_nullable() {
+        let db = sea_orm::Database::connect("sqlite::memory:").await.unwrap();
+        db.execute_unprepared("CREATE TABLE alerts (id INTEGER PRIMARY KEY, rule TEXT)")
+            .await
+            .unwrap();
+        let err = crate::db::create_table(&db, crate::db::alerts::Entity)
+            .await
+            .unwrap_err();
+        let crate::error::PowerlogError::Database(err) = err else {
+            panic!("{err:?}");
+        };
+        assert_eq!(
+            err.to_string(),
+            "Migration Error: new column alerts.active must be nullable"
+        );
+    }
+
+    #[tokio::test]
+    async fn energy_by_hour_and_day() {
+        let db = crate::db::connect("sqlite::memory:").await.unwrap();
+        let mut sample = crate::sample::tests::sample();
+        crate::db::insert(&db, &sample).await.unwrap();
+        sample.time += time::Duration::minutes(45);
+        for channel in &mut sample.output_data.channels {
+            channel.energy_generation_lifetime += 1.0;
+        }
+        crate::db::insert(&db, &sample).await.unwrap();
+
+        let hours = crate::db::select_energy_by_hour(&db).await.unwrap();
+        let hours: Vec<_> = hours
+            .iter()
+            .map(|hour| (hour.hour.hour(), hour.generated))
+            .collect();
+        assert_eq!(hours, [(9, 0.0), (10, 2.0)]);
+
+        let days = crate::db::select_energy_by_day(&db).await.unwrap();
+        assert_eq!(days.len(), 1);
+        assert_eq!((days[0].generated, days[0].feed_in), (2.0, None));
+    }
+
+    #[tokio::test]
+    async fn energy_by_day_starts_at_first_sample() {
+        let db = crate::db::connect("sqlite::memory:").await.unwrap();
+        // the inverter already counted 9 kWh before the first sample
+        let mut sample = crate::sample::tests::sample();
+        for (hours, lifetime) in [(0, [3.0, 6.0]), (2, [3.5, 6.5]), (24, [4.0, 7.5])] {
+            sample.time += time::Duration::hours(hours);
+            for (channel, lifetime) in sample.output_data.channels.iter_mut().zip(lifetime) {
+                channel.energy_generation_lifetime = lifetime;
+            }
+            crate::db::insert(&db, &sample).await.unwrap();
+        }
+
+        let days = crate::db::select_energy_by_day(&db).await.unwrap();
+        let generated: Vec<_> = days.iter().map(|day| day.generated).collect();
+        assert_eq!(generated, [1.0, 1.5]);
+    }
+}
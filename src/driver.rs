@@ -0,0 +1,181 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Inverters of different vendors behind a common interface, see `config::INVERTER`
+
+pub mod opendtu;
+pub mod shelly;
+pub mod sunspec;
+
+use futures::future::BoxFuture;
+
+use crate::error::Result;
+use crate::inverter::{Model, OutputData, Status};
+
+pub trait InverterDriver: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn output_data(&self) -> BoxFuture<'_, Result<OutputData>>;
+
+    /// The output power the inverter is currently limited to in W
+    fn max_power(&self) -> BoxFuture<'_, Result<f64>>;
+
+    fn on_off(&self) -> BoxFuture<'_, Result<Status>>;
+
+    fn device_info(&self) -> BoxFuture<'_, Result<Model>>;
+}
+
+/// The supported inverters and how to reach them
+pub enum Config {
+    /// APsystems local API, e.g. `http://192.168.1.2:8050`
+    Ez1 {
+        url: &'static str,
+        lifetime_offsets: &'static [LifetimeOffset],
+    },
+    /// Hoymiles inverter with the given serial number behind an OpenDTU, e.g.
+    /// `http://192.168.1.3`
+    OpenDtu {
+        url: &'static str,
+        serial: &'static str,
+    },
+    /// Shelly Plus PM measuring the output of the inverter, see [`shelly::ShellyPlusPm`]
+    ShellyPlusPm {
+        url: &'static str,
+        id: u32,
+        returned: bool,
+        max_power: f64,
+    },
+    /// SunSpec compatible inverter via Modbus TCP, e.g. `192.168.1.4:502`
+    SunSpec { address: &'static str, unit_id: u8 },
+}
+
+impl Config {
+    /// The driver for the configured inverter, `address` overrides the configured URL or
+    /// Modbus address
+    pub fn driver(
+        &self,
+        client: reqwest::Client,
+        address: Option<String>,
+    ) -> Box<dyn InverterDriver> {
+        let address = |configured: &str| address.unwrap_or_else(|| configured.to_string());
+        match *self {
+            Config::Ez1 {
+                url,
+                lifetime_offsets,
+            } => Box::new(Ez1 {
+                client,
+                url: address(url),
+                lifetime_offsets,
+            }),
+            Config::OpenDtu { url, serial } => Box::new(opendtu::OpenDtu {
+                client,
+                url: address(url),
+                serial: serial.to_string(),
+            }),
+            Config::ShellyPlusPm {
+                url,
+                id,
+                returned,
+                max_power,
+            } => Box::new(shelly::ShellyPlusPm {
+                client,
+                url: address(url),
+                id,
+                returned,
+                max_power,
+            }),
+            Config::SunSpec {
+                address: configured,
+                unit_id,
+            } => Box::new(sunspec::SunSpec::new(
+                address(configured),
+                unit_id,
+                crate::config::INVERTER_CONNECT_TIMEOUT,
+                crate::config::INVERTER_READ_TIMEOUT,
+            )),
+        }
+    }
+}
+
+/// A correction of the lifetime energy reported for a channel of a specific inverter, e.g. after
+/// its counter was reset
+pub struct LifetimeOffset {
+    /// applies to every inverter of the driver if `None`
+    pub device_id: Option<&'static str>,
+    /// 1-based like in the local API
+    pub channel: usize,
+    /// in kWh, added to the reported lifetime energy
+    pub energy: f64,
+}
+
+/// APsystems EZ1 and compatible inverters, see [`crate::inverter`]
+pub struct Ez1 {
+    pub client: reqwest::Client,
+    pub url: String,
+    /// applied to the output data of inverters with a matching or without a device id
+    pub lifetime_offsets: &'static [LifetimeOffset],
+}
+
+impl InverterDriver for Ez1 {
+    fn name(&self) -> &'static str {
+        "ez1"
+    }
+
+    fn output_data(&self) -> BoxFuture<'_, Result<OutputData>> {
+        Box::pin(async {
+            let mut output_data = crate::inverter::output_data(&self.client, &self.url).await?;
+            for offset in self.lifetime_offsets {
+                if offset
+                    .device_id
+                    .is_some_and(|device_id| device_id != output_data.device_id)
+                {
+                    continue;
+                }
+                let channel = offset
+                    .channel
+                    .checked_sub(1)
+                    .and_then(|index| output_data.channels.get_mut(index));
+                if let Some(channel) = channel {
+                    channel.energy_generation_lifetime += offset.energy;
+                }
+            }
+            Ok(output_data)
+        })
+    }
+
+    fn max_power(&self) -> BoxFuture<'_, Result<f64>> {
+        Box::pin(crate::inverter::max_power(&self.client, &self.url))
+    }
+
+    fn on_off(&self) -> BoxFuture<'_, Result<Status>> {
+        Box::pin(crate::inverter::on_off(&self.client, &self.url))
+    }
+
+    fn device_info(&self) -> BoxFuture<'_, Result<Model>> {
+        Box::pin(crate::inverter::device_info(&self.client, &self.url))
+    }
+}
+
+/// Decode the JSON `body` of a response from `endpoint`
+fn decode<T: serde::de::DeserializeOwned>(endpoint: &'static str, body: &[u8]) -> Result<T> {
+    serde_json::from_slice(body).map_err(|err| crate::error::PowerlogError::BadResponse {
+        endpoint,
+        reason: err.to_string(),
+    })
+}
+
+async fn get<T: serde::de::DeserializeOwned>(
+    request: reqwest::RequestBuilder,
+    endpoint: &'static str,
+) -> Result<T> {
+    use crate::inverter::request_error;
+
+    let body = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(request_error)?
+        .bytes()
+        .await
+        .map_err(request_error)?;
+    decode(endpoint, &body)
+}
@@ -0,0 +1,186 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Hoymiles inverters via the web API of OpenDTU, see
+//! https://www.opendtu.solar/firmware/web_api/
+use std::collections::BTreeMap;
+
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+use super::InverterDriver;
+use crate::error::{PowerlogError, Result};
+use crate::inverter::{Model, OutputChannel, OutputData, Status};
+
+pub const LIVE_DATA: &str = "api/livedata/status";
+pub const LIMIT: &str = "api/limit/status";
+pub const DEVICE_INFO: &str = "api/devinfo/status";
+
+pub struct OpenDtu {
+    pub client: reqwest::Client,
+    pub url: String,
+    /// serial number of the inverter, a DTU can poll several
+    pub serial: String,
+}
+
+/// A reading with its unit, e.g. `{"v": 512, "u": "Wh", "d": 0}`
+#[derive(Deserialize, Debug)]
+struct Value {
+    v: f64,
+    u: String,
+}
+
+impl Value {
+    fn convert(&self, units: &[(&str, f64)]) -> Result<f64> {
+        match units.iter().find(|(unit, _)| *unit == self.u) {
+            Some((_, factor)) => Ok(self.v * factor),
+            None => Err(PowerlogError::BadResponse {
+                endpoint: LIVE_DATA,
+                reason: format!("unexpected unit {:?}", self.u),
+            }),
+        }
+    }
+
+    fn watts(&self) -> Result<f64> {
+        self.convert(&[("W", 1.0), ("kW", 1000.0)])
+    }
+
+    fn kilowatt_hours(&self) -> Result<f64> {
+        self.convert(&[("Wh", 0.001), ("kWh", 1.0)])
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "PascalCase")]
+struct Dc {
+    power: Value,
+    yield_day: Value,
+    yield_total: Value,
+}
+
+#[derive(Deserialize, Debug)]
+struct Inverter {
+    serial: String,
+    reachable: bool,
+    producing: bool,
+    /// per panel input, keyed by its index
+    #[serde(rename = "DC", default)]
+    dc: BTreeMap<u32, Dc>,
+}
+
+#[derive(Deserialize, Debug)]
+struct LiveData {
+    inverters: Vec<Inverter>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Limit {
+    limit_relative: f64,
+    max_power: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct DeviceInfo {
+    hw_model_name: String,
+    fw_build_version: serde_json::Value,
+    max_power: f64,
+}
+
+impl OpenDtu {
+    fn request(&self, endpoint: &str) -> reqwest::RequestBuilder {
+        self.client
+            .get(format!("{}/{endpoint}", self.url))
+            .query(&[("inv", &self.serial)])
+    }
+
+    /// The live data of the inverter, which is offline while the DTU can't reach it
+    async fn inverter(&self) -> Result<Inverter> {
+        let live_data: LiveData = super::get(self.request(LIVE_DATA), LIVE_DATA).await?;
+        let inverter = live_data
+            .inverters
+            .into_iter()
+            .find(|inverter| inverter.serial == self.serial)
+            .ok_or_else(|| PowerlogError::BadResponse {
+                endpoint: LIVE_DATA,
+                reason: format!("unknown inverter {}", self.serial),
+            })?;
+        if !inverter.reachable {
+            return Err(PowerlogError::InverterOffline(
+                format!("inverter {} is not reachable by the DTU", self.serial).into(),
+            ));
+        }
+        Ok(inverter)
+    }
+}
+
+impl InverterDriver for OpenDtu {
+    fn name(&self) -> &'static str {
+        "opendtu"
+    }
+
+    fn output_data(&self) -> BoxFuture<'_, Result<OutputData>> {
+        Box::pin(async {
+            let inverter = self.inverter().await?;
+            let channels = inverter
+                .dc
+                .values()
+                .map(|dc| {
+                    Ok(OutputChannel {
+                        power: dc.power.watts()?,
+                        energy_generation_startup: dc.yield_day.kilowatt_hours()?,
+                        energy_generation_lifetime: dc.yield_total.kilowatt_hours()?,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            if channels.is_empty() {
+                return Err(PowerlogError::BadResponse {
+                    endpoint: LIVE_DATA,
+                    reason: "no channels".to_string(),
+                });
+            }
+            Ok(OutputData {
+                device_id: inverter.serial,
+                channels,
+            })
+        })
+    }
+
+    fn max_power(&self) -> BoxFuture<'_, Result<f64>> {
+        Box::pin(async {
+            let limits: BTreeMap<String, Limit> = super::get(self.request(LIMIT), LIMIT).await?;
+            let limit = limits
+                .get(&self.serial)
+                .ok_or_else(|| PowerlogError::BadResponse {
+                    endpoint: LIMIT,
+                    reason: format!("unknown inverter {}", self.serial),
+                })?;
+            Ok(limit.max_power * limit.limit_relative / 100.0)
+        })
+    }
+
+    /// OpenDTU doesn't report the power switch, an inverter that doesn't produce is off
+    fn on_off(&self) -> BoxFuture<'_, Result<Status>> {
+        Box::pin(async {
+            Ok(match self.inverter().await?.producing {
+                true => Status::On,
+                false => Status::Off,
+            })
+        })
+    }
+
+    fn device_info(&self) -> BoxFuture<'_, Result<Model>> {
+        Box::pin(async {
+            let info: DeviceInfo = super::get(self.request(DEVICE_INFO), DEVICE_INFO).await?;
+            Ok(Model {
+                device_id: self.serial.clone(),
+                manufacturer: "Hoymiles".to_string(),
+                name: info.hw_model_name,
+                firmware: match info.fw_build_version {
+                    serde_json::Value::String(version) => version,
+                    version => version.to_string(),
+                },
+                min_power: 0.0,
+                max_power: info.max_power,
+            })
+        })
+    }
+}
@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Shelly Plus PM devices measuring the output of an inverter via the Gen2 RPC API, see
+//! https://shelly-api-docs.shelly.cloud/gen2/
+use std::collections::BTreeMap;
+
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+use super::InverterDriver;
+use crate::error::{PowerlogError, Result};
+use crate::inverter::{Model, OutputChannel, OutputData, Status};
+
+pub const STATUS: &str = "rpc/Shelly.GetStatus";
+pub const DEVICE_INFO: &str = "rpc/Shelly.GetDeviceInfo";
+
+/// A meter only sees the sum of all panels and has no daily counter, the inverter is thus
+/// reported with a single channel and zero energy today
+pub struct ShellyPlusPm {
+    pub client: reqwest::Client,
+    pub url: String,
+    /// id of the switch or power meter component, usually 0
+    pub id: u32,
+    /// whether the meter counts the output of the inverter as returned energy
+    pub returned: bool,
+    /// a meter can't limit the inverter, this is reported as its max power instead
+    pub max_power: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct Energy {
+    /// in Wh
+    total: f64,
+}
+
+/// The status of a `switch` or `pm1` component
+#[derive(Deserialize, Debug)]
+struct Meter {
+    apower: f64,
+    aenergy: Energy,
+    #[serde(default)]
+    ret_aenergy: Option<Energy>,
+    /// only switches have an output
+    #[serde(default)]
+    output: Option<bool>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Sys {
+    mac: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct DeviceInfo {
+    mac: String,
+    app: String,
+    ver: String,
+}
+
+fn decode<T: serde::de::DeserializeOwned>(value: serde_json::Value) -> Result<T> {
+    serde_json::from_value(value).map_err(|err| PowerlogError::BadResponse {
+        endpoint: STATUS,
+        reason: err.to_string(),
+    })
+}
+
+impl ShellyPlusPm {
+    async fn status(&self) -> Result<(Sys, Meter)> {
+        let request = self.client.get(format!("{}/{STATUS}", self.url));
+        let mut status: BTreeMap<String, serde_json::Value> = super::get(request, STATUS).await?;
+        let component = [format!("switch:{}", self.id), format!("pm1:{}", self.id)]
+            .into_iter()
+            .find_map(|key| status.remove(&key))
+            .ok_or_else(|| PowerlogError::BadResponse {
+                endpoint: STATUS,
+                reason: format!("no switch or power meter with id {}", self.id),
+            })?;
+        let sys = status.remove("sys").unwrap_or_default();
+        Ok((decode(sys)?, decode(component)?))
+    }
+}
+
+impl InverterDriver for ShellyPlusPm {
+    fn name(&self) -> &'static str {
+        "shelly"
+    }
+
+    fn output_data(&self) -> BoxFuture<'_, Result<OutputData>> {
+        Box::pin(async {
+            let (sys, meter) = self.status().await?;
+            let energy = match self.returned {
+                true => meter
+                    .ret_aenergy
+                    .ok_or_else(|| PowerlogError::BadResponse {
+                        endpoint: STATUS,
+                        reason: "no returned energy".to_string(),
+                    })?,
+                false => meter.aenergy,
+            };
+            Ok(OutputData {
+                device_id: sys.mac,
+                channels: vec![OutputChannel {
+                    power: meter.apower.abs(),
+                    energy_generation_startup: 0.0,
+                    energy_generation_lifetime: energy.total / 1000.0,
+                }],
+            })
+        })
+    }
+
+    fn max_power(&self) -> BoxFuture<'_, Result<f64>> {
+        Box::pin(async { Ok(self.max_power) })
+    }
+
+    /// A switch can cut off the inverter, a plain power meter can't
+    fn on_off(&self) -> BoxFuture<'_, Result<Status>> {
+        Box::pin(async {
+            let (_, meter) = self.status().await?;
+            Ok(match meter.output {
+                Some(false) => Status::Off,
+                Some(true) | None => Status::On,
+            })
+        })
+    }
+
+    fn device_info(&self) -> BoxFuture<'_, Result<Model>> {
+        Box::pin(async {
+            let request = self.client.get(format!("{}/{DEVICE_INFO}", self.url));
+            let info: DeviceInfo = super::get(request, DEVICE_INFO).await?;
+            Ok(Model {
+                device_id: info.mac,
+                manufacturer: "Shelly".to_string(),
+                name: info.app,
+                firmware: info.ver,
+                min_power: 0.0,
+                max_power: self.max_power,
+            })
+        })
+    }
+}
@@ -0,0 +1,416 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! SunSpec compatible inverters via Modbus TCP, see https://sunspec.org/specifications/
+use std::io;
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use super::InverterDriver;
+use crate::error::{PowerlogError, Result};
+use crate::inverter::{Model, OutputChannel, OutputData, Status};
+
+/// The SunSpec map usually starts here with the `SunS` marker
+pub const BASE_ADDRESS: u16 = 40000;
+pub const MARKER: [u16; 2] = [0x5375, 0x6e53];
+/// id of the end marker after the last model
+pub const END: u16 = 0xffff;
+
+pub const COMMON: u16 = 1;
+/// single phase, split phase and three phase inverters
+pub const INVERTERS: [u16; 3] = [101, 102, 103];
+pub const NAMEPLATE: u16 = 120;
+pub const CONTROLS: u16 = 123;
+pub const MPPT: u16 = 160;
+
+/// operating states of the inverter models
+pub const STATE_OFF: u16 = 1;
+pub const STATE_SLEEPING: u16 = 2;
+pub const STATE_MPPT: u16 = 4;
+
+pub const READ_HOLDING_REGISTERS: u8 = 0x03;
+/// maximum number of registers of a single read request
+pub const MAX_READ: u16 = 125;
+/// a device shouldn't have more models than this, guards against broken maps
+const MAX_MODELS: usize = 64;
+
+const ENDPOINT: &str = "SunSpec";
+
+pub struct SunSpec {
+    /// host and port, e.g. `192.168.1.4:502`
+    pub address: String,
+    pub unit_id: u8,
+    pub connect_timeout: Duration,
+    pub read_timeout: Duration,
+    /// kept open between requests and locked while reading, many devices only accept a single
+    /// Modbus TCP client and the collector queries the driver concurrently
+    connection: tokio::sync::Mutex<Option<Connection>>,
+}
+
+/// Encode a Modbus TCP frame around `pdu`
+pub fn frame(transaction: u16, unit_id: u8, pdu: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(7 + pdu.len());
+    frame.extend(transaction.to_be_bytes());
+    // protocol identifier
+    frame.extend(0_u16.to_be_bytes());
+    frame.extend((pdu.len() as u16 + 1).to_be_bytes());
+    frame.push(unit_id);
+    frame.extend(pdu);
+    frame
+}
+
+/// Read a Modbus TCP frame, returns its transaction id, unit id and PDU
+pub async fn read_frame(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<(u16, u8, Vec<u8>)> {
+    let mut header = [0; 7];
+    stream.read_exact(&mut header).await?;
+    let length = u16::from_be_bytes([header[4], header[5]]) as usize;
+    if !(2..=254).contains(&length) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid frame length {length}"),
+        ));
+    }
+    let mut pdu = vec![0; length - 1];
+    stream.read_exact(&mut pdu).await?;
+    Ok((u16::from_be_bytes([header[0], header[1]]), header[6], pdu))
+}
+
+fn io_error(err: io::Error) -> PowerlogError {
+    use io::ErrorKind::*;
+    match err.kind() {
+        ConnectionRefused | TimedOut | HostUnreachable | NetworkUnreachable => {
+            PowerlogError::InverterOffline(err.into())
+        }
+        _ => PowerlogError::InverterRequest(err.into()),
+    }
+}
+
+fn bad_response(reason: String) -> PowerlogError {
+    PowerlogError::BadResponse {
+        endpoint: ENDPOINT,
+        reason,
+    }
+}
+
+struct Connection {
+    stream: TcpStream,
+    unit_id: u8,
+    transaction: u16,
+    read_timeout: Duration,
+}
+
+impl Connection {
+    async fn read(&mut self, address: u16, count: u16) -> Result<Vec<u16>> {
+        self.transaction = self.transaction.wrapping_add(1);
+        let mut pdu = vec![READ_HOLDING_REGISTERS];
+        pdu.extend(address.to_be_bytes());
+        pdu.extend(count.to_be_bytes());
+        let request = frame(self.transaction, self.unit_id, &pdu);
+        self.stream.write_all(&request).await.map_err(io_error)?;
+
+        let (transaction, _, pdu) =
+            tokio::time::timeout(self.read_timeout, read_frame(&mut self.stream))
+                .await
+                .map_err(|_| io_error(io::ErrorKind::TimedOut.into()))?
+                .map_err(io_error)?;
+        if transaction != self.transaction {
+            return Err(bad_response(format!(
+                "unexpected transaction {transaction}"
+            )));
+        }
+        match pdu.as_slice() {
+            [READ_HOLDING_REGISTERS, length, data @ ..]
+                if *length as usize == data.len() && data.len() == 2 * count as usize =>
+            {
+                Ok(data
+                    .chunks(2)
+                    .map(|word| u16::from_be_bytes([word[0], word[1]]))
+                    .collect())
+            }
+            [function, code, ..] if *function == READ_HOLDING_REGISTERS | 0x80 => Err(
+                bad_response(format!("exception {code} reading register {address}")),
+            ),
+            _ => Err(bad_response(format!(
+                "invalid response reading register {address}"
+            ))),
+        }
+    }
+
+    /// Read the whole SunSpec map
+    async fn models(&mut self) -> Result<Models> {
+        if self.read(BASE_ADDRESS, 2).await? != MARKER {
+            return Err(bad_response(format!(
+                "no SunSpec marker at register {BASE_ADDRESS}"
+            )));
+        }
+
+        let mut models = Vec::new();
+        let mut address = BASE_ADDRESS + 2;
+        while models.len() < MAX_MODELS {
+            let header = self.read(address, 2).await?;
+            let (id, length) = (header[0], header[1]);
+            if id == END {
+                return Ok(Models(models));
+            }
+            // the model must end within the register space, which also bounds every read below
+            let (start, end) = address
+                .checked_add(2)
+                .and_then(|start| Some((start, start.checked_add(length)?)))
+                .ok_or_else(|| bad_response(format!("model {id} exceeds the map")))?;
+            let mut registers = Vec::with_capacity(length as usize);
+            while registers.len() < length as usize {
+                let read = registers.len() as u16;
+                let count = (length - read).min(MAX_READ);
+                registers.extend(self.read(start + read, count).await?);
+            }
+            models.push((id, registers));
+            address = end;
+        }
+        Err(bad_response("no end marker".to_string()))
+    }
+}
+
+/// The registers of a model without its id and length
+struct Block<'a> {
+    id: u16,
+    registers: &'a [u16],
+}
+
+impl Block<'_> {
+    fn get(&self, offset: usize) -> Result<u16> {
+        self.registers
+            .get(offset)
+            .copied()
+            .ok_or_else(|| bad_response(format!("model {} has no register {offset}", self.id)))
+    }
+
+    /// `value` scaled by the scale factor at `offset`
+    fn scaled(&self, value: f64, offset: usize) -> Result<f64> {
+        Ok(value * 10_f64.powi(self.get(offset)? as i16 as i32))
+    }
+
+    fn uint16(&self, offset: usize) -> Result<f64> {
+        Ok(self.get(offset)? as f64)
+    }
+
+    fn int16(&self, offset: usize) -> Result<f64> {
+        Ok(self.get(offset)? as i16 as f64)
+    }
+
+    fn acc32(&self, offset: usize) -> Result<f64> {
+        let high = self.get(offset)? as u32;
+        let low = self.get(offset + 1)? as u32;
+        Ok((high << 16 | low) as f64)
+    }
+
+    fn string(&self, offset: usize, length: usize) -> Result<String> {
+        let mut bytes = Vec::with_capacity(2 * length);
+        for offset in offset..offset + length {
+            bytes.extend(self.get(offset)?.to_be_bytes());
+        }
+        let string = String::from_utf8_lossy(&bytes);
+        Ok(string.trim_end_matches('\0').trim().to_string())
+    }
+}
+
+/// All models of a device in the order of its map
+struct Models(Vec<(u16, Vec<u16>)>);
+
+impl Models {
+    fn find(&self, ids: &[u16]) -> Option<Block<'_>> {
+        self.0
+            .iter()
+            .find(|(id, _)| ids.contains(id))
+            .map(|(id, registers)| Block { id: *id, registers })
+    }
+
+    fn get(&self, ids: &[u16]) -> Result<Block<'_>> {
+        self.find(ids)
+            .ok_or_else(|| bad_response(format!("no model {ids:?}")))
+    }
+
+    fn serial_number(&self) -> Result<String> {
+        self.get(&[COMMON])?.string(48, 16)
+    }
+
+    /// The rated power of the inverter in W
+    fn rating(&self) -> Result<f64> {
+        let nameplate = self.get(&[NAMEPLATE])?;
+        nameplate.scaled(nameplate.uint16(1)?, 2)
+    }
+}
+
+impl SunSpec {
+    pub fn new(
+        address: String,
+        unit_id: u8,
+        connect_timeout: Duration,
+        read_timeout: Duration,
+    ) -> Self {
+        Self {
+            address,
+            unit_id,
+            connect_timeout,
+            read_timeout,
+            connection: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    async fn connect(&self) -> Result<Connection> {
+        let stream = tokio::time::timeout(self.connect_timeout, TcpStream::connect(&self.address))
+            .await
+            .map_err(|_| io_error(io::ErrorKind::TimedOut.into()))?
+            .map_err(io_error)?;
+        Ok(Connection {
+            stream,
+            unit_id: self.unit_id,
+            transaction: 0,
+            read_timeout: self.read_timeout,
+        })
+    }
+
+    async fn models(&self) -> Result<Models> {
+        let mut connection = self.connection.lock().await;
+        if let Some(open) = connection.as_mut() {
+            match open.models().await {
+                Ok(models) => return Ok(models),
+                // the device may have closed the idle connection, try again with a new one
+                Err(PowerlogError::InverterRequest(_)) => {}
+                Err(err) => {
+                    *connection = None;
+                    return Err(err);
+                }
+            }
+        }
+        let models = connection.insert(self.connect().await?).models().await;
+        if models.is_err() {
+            // don't read stale responses of a timed out request later on
+            *connection = None;
+        }
+        models
+    }
+}
+
+impl InverterDriver for SunSpec {
+    fn name(&self) -> &'static str {
+        "sunspec"
+    }
+
+    /// Per panel input if the inverter has the multiple MPPT model, otherwise a single
+    /// channel. SunSpec has no daily counters, the energy today is always zero.
+    fn output_data(&self) -> BoxFuture<'_, Result<OutputData>> {
+        Box::pin(async {
+            let models = self.models().await?;
+            let channels = match models.find(&[MPPT]) {
+                Some(mppt) => {
+                    let modules = mppt.get(6)? as usize;
+                    (0..modules)
+                        .map(|module| {
+                            let offset = 8 + 20 * module;
+                            Ok(OutputChannel {
+                                power: mppt.scaled(mppt.uint16(offset + 11)?, 2)?,
+                                energy_generation_startup: 0.0,
+                                energy_generation_lifetime: mppt
+                                    .scaled(mppt.acc32(offset + 12)?, 3)?
+                                    / 1000.0,
+                            })
+                        })
+                        .collect::<Result<Vec<_>>>()?
+                }
+                None => {
+                    let inverter = models.get(&INVERTERS)?;
+                    vec![OutputChannel {
+                        power: inverter.scaled(inverter.int16(12)?, 13)?,
+                        energy_generation_startup: 0.0,
+                        energy_generation_lifetime: inverter.scaled(inverter.acc32(22)?, 24)?
+                            / 1000.0,
+                    }]
+                }
+            };
+            if channels.is_empty() {
+                return Err(bad_response("no channels".to_string()));
+            }
+            Ok(OutputData {
+                device_id: models.serial_number()?,
+                channels,
+            })
+        })
+    }
+
+    /// The rated power, reduced by the limit of the immediate controls if enabled
+    fn max_power(&self) -> BoxFuture<'_, Result<f64>> {
+        Box::pin(async {
+            let models = self.models().await?;
+            let rating = models.rating()?;
+            match models.find(&[CONTROLS]) {
+                Some(controls) if controls.get(7)? == 1 => {
+                    let percent = controls.scaled(controls.uint16(3)?, 21)?;
+                    Ok(rating * percent / 100.0)
+                }
+                _ => Ok(rating),
+            }
+        })
+    }
+
+    /// A sleeping inverter is treated as offline, like one that doesn't answer at night
+    fn on_off(&self) -> BoxFuture<'_, Result<Status>> {
+        Box::pin(async {
+            let models = self.models().await?;
+            let state = models.get(&INVERTERS)?.get(36)?;
+            if state == STATE_SLEEPING {
+                return Err(PowerlogError::InverterOffline(
+                    "inverter is sleeping".into(),
+                ));
+            }
+            let connected = match models.find(&[CONTROLS]) {
+                Some(controls) => controls.get(2)? == 1,
+                None => state != STATE_OFF,
+            };
+            Ok(match connected {
+                true => Status::On,
+                false => Status::Off,
+            })
+        })
+    }
+
+    fn device_info(&self) -> BoxFuture<'_, Result<Model>> {
+        Box::pin(async {
+            let models = self.models().await?;
+            let common = models.get(&[COMMON])?;
+            Ok(Model {
+                device_id: models.serial_number()?,
+                manufacturer: common.string(0, 16)?,
+                name: common.string(16, 16)?,
+                firmware: common.string(40, 8)?,
+                min_power: 0.0,
+                max_power: models.rating()?,
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Block, frame};
+
+    #[test]
+    fn decode_registers() {
+        let registers = [0x4142, 0x4300, 0, 0xfffe, 1234, 0x0001, 0x0002];
+        let block = Block {
+            id: 1,
+            registers: &registers,
+        };
+        assert_eq!(block.string(0, 3).unwrap(), "ABC");
+        assert_eq!(block.scaled(1234.0, 3).unwrap(), 12.34);
+        assert_eq!(block.int16(3).unwrap(), -2.0);
+        assert_eq!(block.acc32(5).unwrap(), 65538.0);
+        assert!(block.get(7).is_err());
+
+        assert_eq!(
+            frame(1, 2, &[3, 0x9c, 0x40, 0, 2]),
+            [0, 1, 0, 0, 0, 6, 2, 3, 0x9c, 0x40, 0, 2]
+        );
+    }
+}
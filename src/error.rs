@@ -0,0 +1,92 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+/// The underlying error of an inverter driver, e.g. of HTTP or Modbus requests
+pub type Source = Box<dyn std::error::Error + Send + Sync>;
+
+/// Errors of the inverter, weather and database layers
+#[derive(thiserror::Error, Debug)]
+pub enum PowerlogError {
+    #[error("inverter is offline")]
+    InverterOffline(#[source] Source),
+    #[error("inverter request failed")]
+    InverterRequest(#[source] Source),
+    #[error("bad response from {endpoint}: {reason}")]
+    BadResponse {
+        endpoint: &'static str,
+        reason: String,
+    },
+    #[error("weather data unavailable")]
+    WeatherUnavailable(#[source] reqwest::Error),
+    #[error("consumption meter unavailable")]
+    MeterUnavailable(#[source] Source),
+    #[error("database error")]
+    Database(#[from] sea_orm::DbErr),
+    #[error("invalid query parameter {parameter}: {reason}")]
+    InvalidQuery { parameter: String, reason: String },
+    #[error("invalid configuration: {context}")]
+    Configuration {
+        context: String,
+        #[source]
+        source: Source,
+    },
+}
+
+impl PowerlogError {
+    /// The HTTP status that best describes this error to API clients
+    pub fn status_code(&self) -> axum::http::StatusCode {
+        use axum::http::StatusCode;
+        match self {
+            Self::InverterOffline(_) | Self::WeatherUnavailable(_) | Self::MeterUnavailable(_) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            Self::InverterRequest(_) | Self::BadResponse { .. } => StatusCode::BAD_GATEWAY,
+            Self::Database(_) | Self::Configuration { .. } => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::InvalidQuery { .. } => StatusCode::BAD_REQUEST,
+        }
+    }
+
+    /// Whether repeating the failed request might succeed
+    pub fn is_transient(&self) -> bool {
+        match self {
+            Self::InverterOffline(_) | Self::InverterRequest(_) | Self::MeterUnavailable(_) => true,
+            // a response we can't decode won't get any better
+            Self::WeatherUnavailable(err) => match err.status() {
+                Some(status) => {
+                    status == reqwest::StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+                }
+                None => !err.is_decode(),
+            },
+            Self::BadResponse { .. }
+            | Self::Database(_)
+            | Self::InvalidQuery { .. }
+            | Self::Configuration { .. } => false,
+        }
+    }
+}
+
+pub type Result<T, E = PowerlogError> = std::result::Result<T, E>;
+
+#[cfg(test)]
+mod tests {
+    use super::PowerlogError;
+    use axum::http::StatusCode;
+
+    #[test]
+    fn status_codes() {
+        let error = PowerlogError::InvalidQuery {
+            parameter: "day".to_string(),
+            reason: "not a date".to_string(),
+        };
+        assert_eq!(error.status_code(), StatusCode::BAD_REQUEST);
+        assert_eq!(error.to_string(), "invalid query parameter day: not a date");
+
+        let error = PowerlogError::BadResponse {
+            endpoint: "getOutputData",
+            reason: "message FAILED".to_string(),
+        };
+        assert_eq!(error.status_code(), StatusCode::BAD_GATEWAY);
+
+        let error = PowerlogError::Database(sea_orm::DbErr::Custom("broken".to_string()));
+        assert_eq!(error.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
@@ -0,0 +1,345 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Savings and feed-in revenue of the installation and when it pays for itself, see
+//! `config::IMPORT_PRICES`, `config::FEED_IN_RATES` and `config::HARDWARE_COST`
+use serde::Serialize;
+
+use crate::db::EnergyByDay;
+
+/// A price per kWh valid from a day up to, but excluding, another one
+#[derive(Clone, Copy, Debug)]
+pub struct Rate {
+    pub from: time::Date,
+    /// `None` while the rate is still valid
+    pub until: Option<time::Date>,
+    pub per_kwh: f64,
+}
+
+/// A date for use in constants, panics at compile time when invalid
+pub const fn date(year: i32, month: time::Month, day: u8) -> time::Date {
+    match time::Date::from_calendar_date(year, month, day) {
+        Ok(date) => date,
+        Err(_) => panic!("invalid date"),
+    }
+}
+
+/// The first of `rates` valid on `date`
+pub fn rate(rates: &[Rate], date: time::Date) -> Option<f64> {
+    rates
+        .iter()
+        .find(|rate| rate.from <= date && rate.until.is_none_or(|until| date < until))
+        .map(|rate| rate.per_kwh)
+}
+
+/// Tariffs and costs of the installation
+pub struct Config {
+    pub import_prices: &'static [Rate],
+    pub feed_in_rates: &'static [Rate],
+    pub hardware_cost: f64,
+    /// share of the generated energy consumed by the household on days without meter
+    /// readings, the rest is considered fed in
+    pub assumed_self_consumption: f64,
+}
+
+impl Config {
+    pub const fn from_config() -> Self {
+        use crate::config;
+        Self {
+            import_prices: config::IMPORT_PRICES,
+            feed_in_rates: config::FEED_IN_RATES,
+            hardware_cost: config::HARDWARE_COST,
+            assumed_self_consumption: config::ASSUMED_SELF_CONSUMPTION,
+        }
+    }
+}
+
+/// The financial result of a day, amounts in the currency of the rates. Days without a valid
+/// rate earn nothing for the respective energy.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Day {
+    pub date: time::Date,
+    pub generated: f64,
+    pub self_consumed: f64,
+    pub feed_in: f64,
+    /// whether feed-in was assumed for lack of meter readings
+    pub estimated: bool,
+    /// the import costs avoided by consuming the generated energy
+    pub savings: f64,
+    pub revenue: f64,
+}
+
+impl Day {
+    pub fn total(&self) -> f64 {
+        self.savings + self.revenue
+    }
+}
+
+pub fn days(config: &Config, energy: &[EnergyByDay]) -> Vec<Day> {
+    energy
+        .iter()
+        .map(|day| {
+            let generated = day.generated.max(0.0);
+            let (feed_in, estimated) = match day.feed_in {
+                Some(feed_in) => (feed_in.clamp(0.0, generated), false),
+                None => (generated * (1.0 - config.assumed_self_consumption), true),
+            };
+            let self_consumed = generated - feed_in;
+            Day {
+                date: day.date,
+                generated,
+                self_consumed,
+                feed_in,
+                estimated,
+                savings: self_consumed * rate(config.import_prices, day.date).unwrap_or(0.0),
+                revenue: feed_in * rate(config.feed_in_rates, day.date).unwrap_or(0.0),
+            }
+        })
+        .collect()
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Period {
+    Day,
+    Month,
+    Year,
+}
+
+impl std::str::FromStr for Period {
+    type Err = String;
+
+    fn from_str(period: &str) -> Result<Self, Self::Err> {
+        match period {
+            "day" => Ok(Period::Day),
+            "month" => Ok(Period::Month),
+            "year" => Ok(Period::Year),
+            _ => Err("expected day, month or year".to_string()),
+        }
+    }
+}
+
+impl Period {
+    /// The first day of the period containing `date` and the one of the next period
+    pub fn range(&self, date: time::Date) -> (time::Date, time::Date) {
+        let first = |year, month| time::Date::from_calendar_date(year, month, 1).unwrap();
+        match self {
+            Period::Day => (date, date.next_day().unwrap()),
+            Period::Month => (
+                first(date.year(), date.month()),
+                match date.month() {
+                    time::Month::December => first(date.year() + 1, time::Month::January),
+                    month => first(date.year(), month.next()),
+                },
+            ),
+            Period::Year => (
+                first(date.year(), time::Month::January),
+                first(date.year() + 1, time::Month::January),
+            ),
+        }
+    }
+
+    pub(crate) fn label(&self, date: time::Date) -> String {
+        match self {
+            Period::Day => date.to_string(),
+            Period::Month => format!("{}-{:02}", date.year(), date.month() as u8),
+            Period::Year => date.year().to_string(),
+        }
+    }
+}
+
+/// Savings over a period, including all periods before in `cumulative`
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Savings {
+    pub period: String,
+    pub generated: f64,
+    pub self_consumed: f64,
+    pub feed_in: f64,
+    pub savings: f64,
+    pub revenue: f64,
+    pub total: f64,
+    pub cumulative: f64,
+}
+
+/// `days` in chronological order summed up per `period`
+pub fn savings(days: &[Day], period: Period) -> Vec<Savings> {
+    let mut result: Vec<Savings> = Vec::new();
+    let mut cumulative = 0.0;
+    for day in days {
+        cumulative += day.total();
+        let label = period.label(day.date);
+        let sum = match result.last_mut() {
+            Some(sum) if sum.period == label => sum,
+            _ => {
+                result.push(Savings {
+                    period: label,
+                    generated: 0.0,
+                    self_consumed: 0.0,
+                    feed_in: 0.0,
+                    savings: 0.0,
+                    revenue: 0.0,
+                    total: 0.0,
+                    cumulative: 0.0,
+                });
+                result.last_mut().unwrap()
+            }
+        };
+        sum.generated += day.generated;
+        sum.self_consumed += day.self_consumed;
+        sum.feed_in += day.feed_in;
+        sum.savings += day.savings;
+        sum.revenue += day.revenue;
+        sum.total += day.total();
+        sum.cumulative = cumulative;
+    }
+    result
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct Amortization {
+    pub hardware_cost: f64,
+    pub cumulative: f64,
+    pub remaining: f64,
+    /// the day the cumulative savings reached the hardware cost
+    #[serde(serialize_with = "optional_date")]
+    pub amortized_on: Option<time::Date>,
+    /// extrapolated from the average of the last year until `today`, `None` without savings
+    #[serde(serialize_with = "optional_date")]
+    pub projected: Option<time::Date>,
+}
+
+/// As `YYYY-MM-DD` like the dates of the other routes
+fn optional_date<S: serde::Serializer>(
+    date: &Option<time::Date>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match date {
+        Some(date) => serializer.serialize_str(&date.to_string()),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Days of history the projection is based on, a full year to cover all seasons
+const PROJECTION_BASIS: i64 = 365;
+
+pub fn amortization(config: &Config, days: &[Day], today: time::Date) -> Amortization {
+    let mut cumulative = 0.0;
+    let mut amortized_on = None;
+    for day in days {
+        cumulative += day.total();
+        if amortized_on.is_none() && cumulative >= config.hardware_cost {
+            amortized_on = Some(day.date);
+        }
+    }
+    let remaining = (config.hardware_cost - cumulative).max(0.0);
+
+    let projected = match amortized_on {
+        Some(date) => Some(date),
+        None => {
+            let since = today - time::Duration::days(PROJECTION_BASIS);
+            let recent = days.iter().filter(|day| day.date > since);
+            let first = recent.clone().map(|day| day.date).min();
+            let total: f64 = recent.map(Day::total).sum();
+            first.and_then(|first| {
+                let span = (today - first).whole_days().max(1) as f64;
+                let per_day = total / span;
+                (per_day > 0.0)
+                    .then(|| today + time::Duration::days((remaining / per_day).ceil() as i64))
+            })
+        }
+    };
+
+    Amortization {
+        hardware_cost: config.hardware_cost,
+        cumulative,
+        remaining,
+        amortized_on,
+        projected,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use time::Month::{December, January, June};
+
+    use crate::db::EnergyByDay;
+    use crate::finance::{Config, Period, Rate, amortization, date, days, rate, savings};
+
+    const IMPORT_PRICES: &[Rate] = &[
+        Rate {
+            from: date(2024, January, 1),
+            until: Some(date(2025, January, 1)),
+            per_kwh: 0.40,
+        },
+        Rate {
+            from: date(2025, January, 1),
+            until: None,
+            per_kwh: 0.30,
+        },
+    ];
+    const FEED_IN_RATES: &[Rate] = &[Rate {
+        from: date(2024, June, 1),
+        until: None,
+        per_kwh: 0.10,
+    }];
+    const CONFIG: Config = Config {
+        import_prices: IMPORT_PRICES,
+        feed_in_rates: FEED_IN_RATES,
+        hardware_cost: 10.0,
+        assumed_self_consumption: 0.75,
+    };
+
+    fn energy(date: time::Date, generated: f64, feed_in: Option<f64>) -> EnergyByDay {
+        EnergyByDay {
+            date,
+            generated,
+            feed_in,
+        }
+    }
+
+    #[test]
+    fn rates_by_date() {
+        assert_eq!(rate(IMPORT_PRICES, date(2023, December, 31)), None);
+        assert_eq!(rate(IMPORT_PRICES, date(2024, December, 31)), Some(0.40));
+        assert_eq!(rate(IMPORT_PRICES, date(2025, January, 1)), Some(0.30));
+    }
+
+    #[test]
+    fn savings_and_amortization() {
+        let energy = [
+            // before the feed-in rate, without meter readings
+            energy(date(2024, January, 31), 4.0, None),
+            energy(date(2024, June, 30), 10.0, Some(2.0)),
+            energy(date(2024, December, 31), 1.0, Some(0.0)),
+            energy(date(2025, January, 1), 20.0, Some(30.0)),
+        ];
+        let days = days(&CONFIG, &energy);
+        assert!(days[0].estimated);
+        assert_eq!((days[0].self_consumed, days[0].feed_in), (3.0, 1.0));
+        assert_eq!(days[0].revenue, 0.0);
+        // the meter can't have fed in more than was generated
+        assert_eq!(days[3].feed_in, 20.0);
+
+        let by_year = savings(&days, Period::Year);
+        assert_eq!(by_year.len(), 2);
+        assert_eq!(by_year[0].period, "2024");
+        let expected_2024 = 3.0 * 0.40 + 8.0 * 0.40 + 2.0 * 0.10 + 1.0 * 0.40;
+        assert!((by_year[0].total - expected_2024).abs() < 1e-9);
+        assert!((by_year[1].cumulative - (expected_2024 + 2.0)).abs() < 1e-9);
+        let by_month = savings(&days, Period::Month);
+        assert_eq!(by_month[1].period, "2024-06");
+
+        let pending = amortization(&CONFIG, &days, date(2025, January, 1));
+        assert_eq!(pending.amortized_on, None);
+        assert!((pending.remaining - (10.0 - expected_2024 - 2.0)).abs() < 1e-9);
+        // 7 earned over the last 365 days
+        let projected = pending.projected.unwrap();
+        assert!(projected > date(2025, January, 1) && projected < date(2025, June, 1));
+
+        let cheap = Config {
+            hardware_cost: 6.0,
+            ..CONFIG
+        };
+        let done = amortization(&cheap, &days, date(2025, January, 1));
+        assert_eq!(done.amortized_on, Some(date(2025, January, 1)));
+        assert_eq!(done.remaining, 0.0);
+    }
+}
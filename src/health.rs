@@ -0,0 +1,90 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Collector freshness and database statistics for `/health` and `/status`
+use crate::error::Result;
+use serde::Serialize;
+use std::sync::Arc;
+
+/// Source of the current time, replaced by a virtual clock in tests and the simulator
+pub type Clock = Arc<dyn Fn() -> time::OffsetDateTime + Send + Sync>;
+
+pub fn system_clock() -> Clock {
+    Arc::new(time::OffsetDateTime::now_utc)
+}
+
+#[derive(Serialize, Debug)]
+pub struct Status {
+    #[serde(with = "time::serde::iso8601::option")]
+    pub last_sample: Option<time::OffsetDateTime>,
+    pub last_sample_age_seconds: Option<i64>,
+    pub expected_interval_seconds: u64,
+    pub stale: bool,
+    pub daylight: bool,
+    pub last_run: Option<crate::db::CollectorRun>,
+    /// whether the inverter answered during the last collector run
+    pub inverter_reachable: Option<bool>,
+    /// share of the weather queries of the last 24h that succeeded
+    pub weather_success_rate: Option<f64>,
+    pub database_size_bytes: i64,
+    pub database_rows: i64,
+    pub version: &'static str,
+}
+
+impl Status {
+    /// Stale data is only a problem while the sun is up, the inverter is off at night
+    pub fn healthy(&self) -> bool {
+        !(self.stale && self.daylight)
+    }
+}
+
+pub fn is_daylight(now: time::OffsetDateTime) -> bool {
+    crate::sun::position(now).altitude > crate::config::DAYLIGHT_SUN_ALTITUDE
+}
+
+pub fn is_stale(now: time::OffsetDateTime, last_sample: Option<time::OffsetDateTime>) -> bool {
+    match last_sample {
+        Some(last_sample) => now - last_sample > crate::config::STALE_AFTER,
+        None => true,
+    }
+}
+
+pub async fn status(db: &sea_orm::DatabaseConnection, now: time::OffsetDateTime) -> Result<Status> {
+    let last_sample = crate::db::select_latest(db)
+        .await?
+        .map(|latest| latest.time);
+    let stale = is_stale(now, last_sample);
+    let stats = crate::db::database_stats(db).await?;
+    let last_run = crate::db::select_last_collector_run(db).await?;
+    let inverter = crate::collector::InverterStatus::Online.as_str();
+    Ok(Status {
+        last_sample,
+        last_sample_age_seconds: last_sample.map(|time| (now - time).whole_seconds()),
+        expected_interval_seconds: crate::config::COLLECT_INTERVAL.as_secs(),
+        stale,
+        daylight: is_daylight(now),
+        inverter_reachable: last_run.as_ref().map(|run| run.inverter == inverter),
+        last_run,
+        weather_success_rate: crate::db::weather_success_rate(db, now).await?,
+        database_size_bytes: stats.size_bytes,
+        database_rows: stats.rows,
+        version: env!("CARGO_PKG_VERSION"),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn stale_only_matters_during_daylight() {
+        // 2024-06-21 11:00 and 23:00 UTC
+        let noon = time::OffsetDateTime::from_unix_timestamp(1718967600).unwrap();
+        let midnight = time::OffsetDateTime::from_unix_timestamp(1719010800).unwrap();
+        assert!(crate::health::is_daylight(noon));
+        assert!(!crate::health::is_daylight(midnight));
+
+        let recent = Some(noon - time::Duration::minutes(4));
+        let old = Some(noon - time::Duration::hours(1));
+        assert!(!crate::health::is_stale(noon, recent));
+        assert!(crate::health::is_stale(noon, old));
+        assert!(crate::health::is_stale(noon, None));
+    }
+}
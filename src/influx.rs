@@ -0,0 +1,158 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Write samples as InfluxDB line protocol, see
+//! https://docs.influxdata.com/influxdb/v2/reference/syntax/line-protocol/
+use anyhow::Result;
+use std::fmt::{Display, Write};
+
+pub enum Output {
+    /// InfluxDB v2 write API, e.g. `http://localhost:8086`
+    Http {
+        url: &'static str,
+        org: &'static str,
+        bucket: &'static str,
+        token: &'static str,
+    },
+    /// Append to a local file
+    File(&'static str),
+    /// Send datagrams to a UDP listener, e.g. `localhost:8089`
+    Udp(&'static str),
+}
+
+const MEASUREMENT: &str = "powerlog";
+
+fn escape_tag(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+fn line(out: &mut String, tags: &[(&str, &str)], fields: &[(&str, &dyn Display)], time: i128) {
+    out.push_str(MEASUREMENT);
+    for (key, value) in tags {
+        write!(out, ",{key}={}", escape_tag(value)).unwrap();
+    }
+    for (i, (key, value)) in fields.iter().enumerate() {
+        let separator = if i == 0 { ' ' } else { ',' };
+        write!(out, "{separator}{key}={value}").unwrap();
+    }
+    writeln!(out, " {time}").unwrap();
+}
+
+fn lines(sample: &crate::sample::Sample) -> String {
+    let mut out = String::new();
+    let time = sample.time.unix_timestamp_nanos();
+    let device = sample.device_id();
+    let output_data = &sample.output_data;
+    let sunpos = &sample.sunpos;
+
+    for (i, data) in output_data.channels.iter().enumerate() {
+        line(
+            &mut out,
+            &[("device", device), ("channel", &(i + 1).to_string())],
+            &[
+                ("power", &data.power),
+                ("energy_today", &data.energy_generation_startup),
+                ("energy_lifetime", &data.energy_generation_lifetime),
+            ],
+            time,
+        );
+    }
+
+    let on = sample.on_off == crate::inverter::Status::On;
+    let mut fields: Vec<(&str, &dyn Display)> = vec![
+        ("max_power", &sample.max_power),
+        ("on", &on),
+        ("sun_azimuth", &sunpos.azimuth),
+        ("sun_altitude", &sunpos.altitude),
+    ];
+    let cloud_cover;
+    if let Some(weather) = &sample.weather {
+        cloud_cover = weather.cloud_cover / 100.0;
+        fields.extend([
+            ("cloud_cover", &cloud_cover as &dyn Display),
+            (
+                "terrestrial_radiation",
+                &weather.terrestrial_radiation_instant,
+            ),
+            ("direct_radiation", &weather.direct_radiation_instant),
+            ("diffuse_radiation", &weather.diffuse_radiation_instant),
+            ("shortwave_radiation", &weather.shortwave_radiation_instant),
+            (
+                "direct_normal_irradiance",
+                &weather.direct_normal_irradiance_instant,
+            ),
+            (
+                "global_tilted_irradiance",
+                &weather.global_tilted_irradiance_instant,
+            ),
+        ]);
+    }
+    line(&mut out, &[("device", device)], &fields, time);
+
+    out
+}
+
+pub async fn write(
+    output: &Output,
+    client: &reqwest::Client,
+    sample: &crate::sample::Sample,
+) -> Result<()> {
+    let lines = lines(sample);
+
+    match output {
+        Output::Http {
+            url,
+            org,
+            bucket,
+            token,
+        } => {
+            client
+                .post(format!("{url}/api/v2/write"))
+                .query(&[("org", org), ("bucket", bucket), ("precision", &"ns")])
+                .header(reqwest::header::AUTHORIZATION, format!("Token {token}"))
+                .body(lines)
+                .send()
+                .await?
+                .error_for_status()?;
+        }
+        Output::File(path) => {
+            use tokio::io::AsyncWriteExt;
+            let mut file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .await?;
+            file.write_all(lines.as_bytes()).await?;
+        }
+        Output::Udp(address) => {
+            let socket = tokio::net::UdpSocket::bind("0.0.0.0:0").await?;
+            socket.send_to(lines.as_bytes(), address).await?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn escape_tag() {
+        assert_eq!(crate::influx::escape_tag("a b,c=d"), r"a\ b\,c\=d");
+    }
+
+    #[test]
+    fn lines_without_weather() {
+        let mut sample = crate::sample::tests::sample();
+        sample.on_off = crate::inverter::Status::Off;
+        let lines = crate::influx::lines(&sample);
+        assert_eq!(
+            lines,
+            "powerlog,device=E07000000001,channel=1 power=1.5,energy_today=2,energy_lifetime=3 1713259800123456789\n\
+                 powerlog,device=E07000000001,channel=2 power=4,energy_today=5,energy_lifetime=6 1713259800123456789\n\
+                 powerlog,device=E07000000001 max_power=600,on=false,sun_azimuth=0.25,sun_altitude=0.5 1713259800123456789\n"
+        );
+    }
+}
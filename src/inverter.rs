@@ -0,0 +1,419 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Client of the EZ1 local API, all functions take the base URL like `config::INVERTER_URL`
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+use crate::error::{PowerlogError, Result};
+
+/// The envelope around the data of every response of the local API
+#[derive(Deserialize, Debug)]
+#[allow(non_snake_case)]
+struct Response<T> {
+    data: T,
+    message: String,
+    deviceId: String,
+}
+
+fn check_envelope<T>(endpoint: &'static str, response: Response<T>) -> Result<Response<T>> {
+    if response.message != "SUCCESS" {
+        return Err(PowerlogError::BadResponse {
+            endpoint,
+            reason: format!("unexpected message {:?}", response.message),
+        });
+    }
+    Ok(response)
+}
+
+pub(crate) fn request_error(err: reqwest::Error) -> PowerlogError {
+    if err.is_connect() || err.is_timeout() {
+        PowerlogError::InverterOffline(err.into())
+    } else {
+        PowerlogError::InverterRequest(err.into())
+    }
+}
+
+async fn get<T: DeserializeOwned>(
+    client: &reqwest::Client,
+    url: &str,
+    endpoint: &'static str,
+) -> Result<Response<T>> {
+    let body = client
+        .get(format!("{url}/{endpoint}"))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(request_error)?
+        .bytes()
+        .await
+        .map_err(request_error)?;
+    let response = serde_json::from_slice(&body).map_err(|err| PowerlogError::BadResponse {
+        endpoint,
+        reason: err.to_string(),
+    })?;
+    check_envelope(endpoint, response)
+}
+
+/// Firmware versions differ in the JSON types they use, accept numbers also as strings
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Number {
+    Number(f64),
+    String(String),
+}
+
+pub(crate) fn number<'de, D: serde::Deserializer<'de>>(
+    deserializer: D,
+) -> std::result::Result<f64, D::Error> {
+    match Number::deserialize(deserializer)? {
+        Number::Number(number) => Ok(number),
+        Number::String(string) => string
+            .trim()
+            .parse()
+            .map_err(|err| serde::de::Error::custom(format!("invalid number {string:?}: {err}"))),
+    }
+}
+
+/// Fields that a newer firmware may have added, they are logged but otherwise ignored
+type Unknown = std::collections::BTreeMap<String, serde_json::Value>;
+
+fn log_unknown(endpoint: &str, unknown: &Unknown) {
+    if !unknown.is_empty() {
+        let names: Vec<_> = unknown.keys().map(String::as_str).collect();
+        eprintln!("{endpoint}: ignoring unknown fields {}", names.join(", "));
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct OutputChannel {
+    pub power: f64,
+    pub energy_generation_startup: f64,
+    pub energy_generation_lifetime: f64,
+}
+
+#[derive(Debug)]
+pub struct OutputData {
+    pub device_id: String,
+    /// one entry per panel input, e.g. one for single panel setups and four for a QT2
+    pub channels: Vec<OutputChannel>,
+}
+
+impl OutputData {
+    /// The readings of the 1-based `channel`, all zero if the inverter doesn't have it
+    pub fn channel(&self, channel: usize) -> &OutputChannel {
+        static MISSING: OutputChannel = OutputChannel {
+            power: 0.0,
+            energy_generation_startup: 0.0,
+            energy_generation_lifetime: 0.0,
+        };
+        channel
+            .checked_sub(1)
+            .and_then(|index| self.channels.get(index))
+            .unwrap_or(&MISSING)
+    }
+}
+
+/// `p1`, `e1`, `te1`, `p2`, ... for as many channels as the inverter has
+#[derive(Deserialize, Debug)]
+struct RawOutputData(std::collections::BTreeMap<String, serde_json::Value>);
+
+type OutputDataResponse = Response<RawOutputData>;
+
+fn to_output_data(response: OutputDataResponse) -> Result<OutputData> {
+    let bad_response = |reason: String| PowerlogError::BadResponse {
+        endpoint: "getOutputData",
+        reason,
+    };
+    let mut fields = response.data.0;
+    let mut take = |key: String| -> Result<Option<f64>> {
+        fields
+            .remove(&key)
+            .map(|value| number(value).map_err(|err| bad_response(format!("{key}: {err}"))))
+            .transpose()
+    };
+
+    let mut channels = Vec::new();
+    for channel in 1.. {
+        let power = take(format!("p{channel}"))?;
+        let startup = take(format!("e{channel}"))?;
+        let lifetime = take(format!("te{channel}"))?;
+        match (power, startup, lifetime) {
+            (Some(power), Some(startup), Some(lifetime)) => channels.push(OutputChannel {
+                power,
+                energy_generation_startup: startup,
+                energy_generation_lifetime: lifetime,
+            }),
+            (None, None, None) => break,
+            _ => return Err(bad_response(format!("incomplete channel {channel}"))),
+        }
+    }
+    if channels.is_empty() {
+        return Err(bad_response("no channels".to_string()));
+    }
+    log_unknown("getOutputData", &fields);
+
+    Ok(OutputData {
+        device_id: response.deviceId,
+        channels,
+    })
+}
+
+pub async fn output_data(client: &reqwest::Client, url: &str) -> Result<OutputData> {
+    let response = get::<RawOutputData>(client, url, "getOutputData").await?;
+
+    to_output_data(response)
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(non_snake_case)]
+struct RawMaxPower {
+    #[serde(deserialize_with = "number")]
+    maxPower: f64,
+    #[serde(flatten)]
+    unknown: Unknown,
+}
+
+#[cfg(test)]
+type MaxPowerResponse = Response<RawMaxPower>;
+
+pub async fn max_power(client: &reqwest::Client, url: &str) -> Result<f64> {
+    let data = get::<RawMaxPower>(client, url, "getMaxPower").await?.data;
+    log_unknown("getMaxPower", &data.unknown);
+
+    Ok(data.maxPower)
+}
+
+#[derive(Debug, Eq, PartialEq)]
+pub enum Status {
+    On,
+    Off,
+}
+
+/// The status is `"0"` or `"1"`, some firmware versions send it as an integer instead
+impl<'de> Deserialize<'de> for Status {
+    fn deserialize<D: serde::Deserializer<'de>>(
+        deserializer: D,
+    ) -> std::result::Result<Self, D::Error> {
+        match number(deserializer)? {
+            0.0 => Ok(Status::On),
+            1.0 => Ok(Status::Off),
+            status => Err(serde::de::Error::custom(format!("unknown status {status}"))),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct OnOff {
+    status: Status,
+    #[serde(flatten)]
+    unknown: Unknown,
+}
+
+#[cfg(test)]
+type OnOffResponse = Response<OnOff>;
+
+pub async fn on_off(client: &reqwest::Client, url: &str) -> Result<Status> {
+    let data = get::<OnOff>(client, url, "getOnOff").await?.data;
+    log_unknown("getOnOff", &data.unknown);
+
+    Ok(data.status)
+}
+
+/// What the inverter reports about itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct Model {
+    pub device_id: String,
+    pub manufacturer: String,
+    /// e.g. `EZ1` or `DS3`
+    pub name: String,
+    pub firmware: String,
+    /// range the output power can be limited to
+    pub min_power: f64,
+    pub max_power: f64,
+}
+
+#[derive(Deserialize, Debug)]
+#[allow(non_snake_case)]
+struct RawDeviceInfo {
+    deviceId: String,
+    /// model and firmware version, e.g. `EZ1 1.6.0`
+    devVer: String,
+    #[serde(deserialize_with = "number")]
+    minPower: f64,
+    #[serde(deserialize_with = "number")]
+    maxPower: f64,
+    #[serde(flatten)]
+    unknown: Unknown,
+}
+
+#[cfg(test)]
+type DeviceInfoResponse = Response<RawDeviceInfo>;
+
+fn to_model(mut data: RawDeviceInfo) -> Model {
+    // the network settings are of no interest
+    data.unknown.remove("ssid");
+    data.unknown.remove("ipAddr");
+    log_unknown("getDeviceInfo", &data.unknown);
+    let (name, firmware) = match data.devVer.trim().split_once(' ') {
+        Some((name, firmware)) => (name.to_string(), firmware.trim().to_string()),
+        None => (data.devVer.trim().to_string(), String::new()),
+    };
+    Model {
+        device_id: data.deviceId,
+        manufacturer: "APsystems".to_string(),
+        name,
+        firmware,
+        min_power: data.minPower,
+        max_power: data.maxPower,
+    }
+}
+
+pub async fn device_info(client: &reqwest::Client, url: &str) -> Result<Model> {
+    let data = get::<RawDeviceInfo>(client, url, "getDeviceInfo")
+        .await?
+        .data;
+
+    Ok(to_model(data))
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_output_data() {
+        let response = r#"
+{
+    "data": {
+        "p1": 1,
+        "e1": 2,
+        "te1": 3,
+        "p2": 4,
+        "e2": 5,
+        "te2": 6
+    },
+    "message": "SUCCESS",
+    "deviceId":"E07000000001"
+}
+            "#;
+
+        let response: crate::inverter::OutputDataResponse = serde_json::from_str(response).unwrap();
+        let data = crate::inverter::to_output_data(response).unwrap();
+        assert_eq!(data.device_id, "E07000000001");
+        assert_eq!(data.channels.len(), 2);
+        assert_eq!(data.channel(1).power, 1_f64);
+        assert_eq!(data.channel(1).energy_generation_startup, 2_f64);
+        assert_eq!(data.channel(1).energy_generation_lifetime, 3_f64);
+        assert_eq!(data.channel(2).power, 4_f64);
+        assert_eq!(data.channel(2).energy_generation_startup, 5_f64);
+        assert_eq!(data.channel(2).energy_generation_lifetime, 6_f64);
+        assert_eq!(data.channel(3).power, 0_f64);
+        assert_eq!(data.channel(0).power, 0_f64);
+    }
+
+    #[test]
+    fn reject_incomplete_channel() {
+        let response = r#"
+{
+    "data": {
+        "p1": 1,
+        "e1": 2,
+        "te1": 3,
+        "p2": 4
+    },
+    "message": "SUCCESS",
+    "deviceId":"E07000000001"
+}
+            "#;
+
+        let response: crate::inverter::OutputDataResponse = serde_json::from_str(response).unwrap();
+        let err = crate::inverter::to_output_data(response).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            crate::error::PowerlogError::BadResponse {
+                endpoint: "getOutputData",
+                reason: "incomplete channel 2".to_string()
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn parse_device_info() {
+        let response = r#"
+{
+    "data": {
+        "deviceId": "E07000000001",
+        "devVer": "EZ1 1.6.0",
+        "ssid": "home",
+        "ipAddr": "192.168.1.100",
+        "minPower": "30",
+        "maxPower": "800"
+    },
+    "message": "SUCCESS",
+    "deviceId":"E07000000001"
+}
+            "#;
+
+        let response: crate::inverter::DeviceInfoResponse = serde_json::from_str(response).unwrap();
+        let model = crate::inverter::to_model(response.data);
+        assert_eq!(model.device_id, "E07000000001");
+        assert_eq!(model.name, "EZ1");
+        assert_eq!(model.firmware, "1.6.0");
+        assert_eq!(model.min_power, 30_f64);
+        assert_eq!(model.max_power, 800_f64);
+    }
+
+    #[test]
+    fn parse_max_power() {
+        let response = r#"
+{
+    "data": {
+        "maxPower": "600"
+    },
+    "message": "SUCCESS",
+    "deviceId":"E07000000001"
+}
+            "#;
+
+        let response: crate::inverter::MaxPowerResponse = serde_json::from_str(response).unwrap();
+        assert_eq!(response.data.maxPower, 600_f64);
+    }
+
+    #[test]
+    fn parse_on_off() {
+        let response = r#"
+{
+    "data": {
+        "status": "0"
+    },
+    "message": "SUCCESS",
+    "deviceId":"E07000000001"
+}
+            "#;
+
+        let response: crate::inverter::OnOffResponse = serde_json::from_str(response).unwrap();
+        assert_eq!(response.data.status, crate::inverter::Status::On);
+    }
+
+    #[test]
+    fn reject_failed_envelope() {
+        let response = r#"
+{
+    "data": {
+        "status": "0"
+    },
+    "message": "FAILED",
+    "deviceId":"E07000000001"
+}
+            "#;
+
+        let response: crate::inverter::OnOffResponse = serde_json::from_str(response).unwrap();
+        let err = crate::inverter::check_envelope("getOnOff", response).unwrap_err();
+        assert!(matches!(
+            err,
+            crate::error::PowerlogError::BadResponse {
+                endpoint: "getOnOff",
+                ..
+            }
+        ));
+    }
+}
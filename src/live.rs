@@ -0,0 +1,164 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Feed of new samples to stream to clients as soon as they are collected
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::broadcast;
+
+use crate::db::PowerToday;
+
+pub struct Feed {
+    recent: Mutex<VecDeque<Arc<PowerToday>>>,
+    replay: usize,
+    sender: broadcast::Sender<Arc<PowerToday>>,
+}
+
+impl Feed {
+    /// Create a feed that replays the last `replay` samples to new subscribers
+    pub fn new(replay: u32) -> Self {
+        let replay = replay as usize;
+        Feed {
+            recent: Mutex::new(VecDeque::with_capacity(replay)),
+            replay,
+            sender: broadcast::channel(replay.max(16)).0,
+        }
+    }
+
+    pub fn publish(&self, sample: PowerToday) {
+        let sample = Arc::new(sample);
+        // keep the lock while sending, so subscribers never miss or duplicate a sample
+        let mut recent = self.recent.lock().unwrap();
+        if recent.len() == self.replay {
+            recent.pop_front();
+        }
+        if self.replay > 0 {
+            recent.push_back(sample.clone());
+        }
+        // there may be no subscribers, which is fine
+        let _ = self.sender.send(sample);
+    }
+
+    /// The recent samples newer than `after` and a receiver for all samples published later on
+    pub fn subscribe(
+        &self,
+        after: Option<i32>,
+    ) -> (Vec<Arc<PowerToday>>, broadcast::Receiver<Arc<PowerToday>>) {
+        let recent = self.recent.lock().unwrap();
+        let replay = recent
+            .iter()
+            .filter(|sample| after.is_none_or(|after| sample.id > after))
+            .cloned()
+            .collect();
+        (replay, self.sender.subscribe())
+    }
+}
+
+/// Publish samples inserted into the database by the collector, checking every `interval`
+pub async fn follow_db(feed: Arc<Feed>, db: sea_orm::DatabaseConnection, interval: Duration) {
+    let mut last_id = 0;
+    let mut initial = true;
+    loop {
+        // the newest sample is needed to continue after it, even if none are replayed
+        let samples = if initial {
+            crate::db::select_recent(&db, feed.replay.max(1) as u32).await
+        } else {
+            crate::db::select_after(&db, last_id).await
+        };
+        match samples {
+            Ok(samples) => {
+                let skip = match initial {
+                    true => samples.len().saturating_sub(feed.replay),
+                    false => 0,
+                };
+                initial = false;
+                if let Some(last) = samples.last() {
+                    last_id = last.id;
+                }
+                for sample in samples.into_iter().skip(skip) {
+                    feed.publish(sample);
+                }
+            }
+            Err(err) => eprintln!("failed to query new samples: {err:?}"),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::PowerToday;
+
+    fn sample(id: i32) -> PowerToday {
+        PowerToday {
+            id,
+            time: time::OffsetDateTime::UNIX_EPOCH,
+            power_ch1: 0.0,
+            power_ch2: 0.0,
+            energy_today_ch1: 0.0,
+            energy_today_ch2: 0.0,
+            energy_total_ch1: 0.0,
+            energy_total_ch2: 0.0,
+            max_power: 0.0,
+            cloud_cover: None,
+            terrestrial_radiation: None,
+            direct_radiation: None,
+            diffuse_radiation: None,
+            shortwave_radiation: None,
+            direct_normal_irradiance: None,
+            global_tilted_irradiance: None,
+            sun_azimuth: 0.0,
+            sun_altitude: 0.0,
+            on_off: None,
+            grid_import_total: None,
+            grid_export_total: None,
+            grid_power: None,
+        }
+    }
+
+    #[test]
+    fn replay_recent_samples() {
+        let feed = crate::live::Feed::new(2);
+        for id in 1..=3 {
+            feed.publish(sample(id));
+        }
+
+        let ids = |replay: Vec<std::sync::Arc<PowerToday>>| {
+            replay.iter().map(|sample| sample.id).collect::<Vec<_>>()
+        };
+        assert_eq!(ids(feed.subscribe(None).0), [2, 3]);
+        assert_eq!(ids(feed.subscribe(Some(2)).0), [3]);
+
+        let (_, mut receiver) = feed.subscribe(Some(3));
+        feed.publish(sample(4));
+        assert_eq!(receiver.try_recv().unwrap().id, 4);
+    }
+
+    #[tokio::test]
+    async fn follow_without_replay() {
+        let db = crate::db::connect("sqlite::memory:").await.unwrap();
+        let mut sample = crate::sample::tests::sample();
+        for _ in 0..3 {
+            crate::db::insert(&db, &sample).await.unwrap();
+        }
+
+        let feed = std::sync::Arc::new(crate::live::Feed::new(0));
+        let (_, mut receiver) = feed.subscribe(None);
+        let follow = tokio::spawn(crate::live::follow_db(
+            feed.clone(),
+            db.clone(),
+            std::time::Duration::from_millis(10),
+        ));
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        sample.time += time::Duration::minutes(5);
+        crate::db::insert(&db, &sample).await.unwrap();
+
+        // only the new sample, not the history
+        let new = tokio::time::timeout(std::time::Duration::from_secs(5), receiver.recv())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(new.id, 4);
+        follow.abort();
+    }
+}
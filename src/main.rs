@@ -1,93 +1,112 @@
 // SPDX-License-Identifier: GPL-3.0-or-later
 
-use anyhow::Result;
-use std::time::Duration;
+use anyhow::{Context, Result, bail};
+use std::process::ExitCode;
 
-use powerlog::db;
-use powerlog::inverter;
-use powerlog::sun;
-use powerlog::weather;
+use powerlog::collector::{Collector, Outcome};
+use powerlog::finance::Period;
+use powerlog::report::{self, Format};
+use powerlog::{config, db, notify};
 
-#[tokio::main]
-async fn main() -> Result<()> {
-    env_logger::init();
-
-    let time = time::OffsetDateTime::now_utc();
+const REPORT_USAGE: &str = "usage: powerlog report [day|month|year] [--date <YYYY-MM-DD>]
+                       [--format text|markdown|html] [--output <directory>] [--notify]
 
-    let db = db::setup();
+Summarizes the previous day, month or year (UTC), or the one containing the given
+date. The report is printed unless it is written to a directory or sent to the
+notifiers of config::NOTIFIERS.";
 
-    // setup http clients
-    let client = reqwest::Client::builder()
-        .timeout(Duration::new(10, 0))
-        .gzip(true)
-        .brotli(true)
-        .deflate(true)
-        .build()?;
+#[tokio::main]
+async fn main() -> ExitCode {
+    env_logger::init();
 
-    // fail early when the inverter is offline
-    let on_off = match inverter::on_off(&client).await {
-        Err(e) => {
-            if let Some(e) = e.downcast_ref::<reqwest::Error>() {
-                if e.is_connect() || e.is_timeout() {
-                    println!("inverter is offline: {:?}", e);
-                    return Ok(());
-                }
+    let args = std::env::args().skip(1).collect::<Vec<_>>();
+    if args.first().is_some_and(|command| command == "report") {
+        return match report_command(&args[1..]).await {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(err) => {
+                eprintln!("Error: {err:?}");
+                ExitCode::FAILURE
             }
-            panic!("inverter request failure: {:?}", e);
-        }
-        Ok(on_off) => on_off,
-    };
-
-    // access inverter API
-    let client_copy = client.clone();
-    let inverter_requests = tokio::spawn(async move {
-        use futures::join;
-        join!(
-            inverter::output_data(&client_copy),
-            inverter::max_power(&client_copy)
-        )
-    });
-
-    // access weather API
-    let client_copy = client.clone();
-    let weather_request = tokio::spawn(async move { weather::query(&client_copy).await });
+        };
+    }
 
-    // await all requests
-
-    // gracefully handle failures of weather api access
-    let weather = match weather_request.await? {
-        Ok(weather) => Some(weather),
+    let outcome = match record_run().await {
+        Ok(outcome) => outcome,
         Err(err) => {
-            eprintln!("{:?}", err);
-            None
+            eprintln!("Error: {err:?}");
+            Outcome::Fatal
         }
     };
+    ExitCode::from(outcome.exit_code())
+}
 
-    // don't do anything if inverter read outs fail which happens at night time when the device is off
-    let (output_data, max_power) = inverter_requests.await?;
+async fn record_run() -> Result<Outcome> {
+    let time = time::OffsetDateTime::now_utc();
 
-    // handle accumulated data
-    let output_data = output_data?;
-    let max_power = max_power?;
-    let sunpos = sun::position(time);
-    println!(
-        "weather: {weather:?}, output data: {output_data:?}, max power: {max_power} on/off: {on_off:?}, sun: {sunpos:?}"
-    );
+    let db = db::setup().await?;
+    let collector = Collector::from_config(db)?;
+    collector.run(time).await
+}
 
-    // Fixup bogus data, the channel counter reset at 2025-08-31T15:00:01.561151476Z in the hardware for some reason
-    // and now reports new values starting at zero again, breaking our analysis as we expect monotonically increasing
-    // values. We now manually add some value here below and I fixed the database manually via
-    //
-    //   UPDATE powerlog SET energy_total_ch2 = energy_total_ch2 + 540.606323242188 WHERE time > '2025-08-31T15:00:01' AND energy_total_ch2 < 500;
-    let output_data = {
-        let mut fixed = output_data;
-        fixed.channel2.energy_generation_lifetime += 540.606323242188;
-        fixed
+async fn report_command(args: &[String]) -> Result<()> {
+    let mut period = Period::Day;
+    let mut date = None;
+    let mut format = Format::Text;
+    let mut output = None;
+    let mut send = false;
+    let mut args = args.iter().map(String::as_str);
+    while let Some(arg) = args.next() {
+        match arg {
+            "day" | "month" | "year" => period = arg.parse().map_err(anyhow::Error::msg)?,
+            "--date" => match args.next() {
+                Some(value) => {
+                    let format = time::format_description::well_known::Iso8601::DATE;
+                    date = Some(
+                        time::Date::parse(value, &format)
+                            .with_context(|| format!("invalid date {value:?}"))?,
+                    );
+                }
+                None => bail!(REPORT_USAGE),
+            },
+            "--format" => match args.next() {
+                Some(value) => format = value.parse().map_err(anyhow::Error::msg)?,
+                None => bail!(REPORT_USAGE),
+            },
+            "--output" => match args.next() {
+                Some(directory) => output = Some(directory.to_string()),
+                None => bail!(REPORT_USAGE),
+            },
+            "--notify" => send = true,
+            "--help" | "-h" => {
+                println!("{REPORT_USAGE}");
+                return Ok(());
+            }
+            _ => bail!(REPORT_USAGE),
+        }
+    }
+
+    // the last day of the previous period
+    let date = match date {
+        Some(date) => date,
+        None => {
+            let today = time::OffsetDateTime::now_utc().date();
+            let (start, _) = period.range(today);
+            start.previous_day().unwrap()
+        }
     };
-
-    // insert data
-    let db = db.await?;
-    db::insert(&db, weather, sunpos, output_data, max_power, time).await?;
-
+    let report = report::load(&db::setup().await?, period, date).await?;
+    if let Some(directory) = &output {
+        let path = report.write(format, directory.as_ref()).await?;
+        println!("wrote {}", path.display());
+    }
+    if send {
+        let client = reqwest::Client::builder()
+            .timeout(config::NOTIFY_TIMEOUT)
+            .build()?;
+        report.send(format, &notify::configured(client)).await?;
+    }
+    if !send && output.is_none() {
+        print!("{}", report.render(format));
+    }
     Ok(())
 }
@@ -0,0 +1,289 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Household meters reporting the energy imported from and exported to the grid, see
+//! `config::METER`
+use futures::future::BoxFuture;
+use serde::Deserialize;
+
+use crate::error::{PowerlogError, Result};
+
+/// The counters of a meter at a given point in time
+#[derive(Clone, Debug, PartialEq)]
+pub struct Reading {
+    /// in kWh
+    pub import_total: f64,
+    /// in kWh
+    pub export_total: f64,
+    /// in W, positive while importing and negative while exporting
+    pub power: f64,
+}
+
+pub trait Meter: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn read(&self) -> BoxFuture<'_, Result<Reading>>;
+}
+
+/// Where to find a value in a JSON document, e.g. `&["SML", "Total_in"]`
+pub type Path = &'static [&'static str];
+
+/// Where a JSON document contains the counters in kWh and the current power in W
+pub struct Fields {
+    pub import: Path,
+    pub export: Path,
+    pub power: Path,
+}
+
+/// The supported meters and how to reach them
+pub enum Config {
+    /// Tasmota smart meter reader with an SML script, e.g. `http://192.168.1.5`, the fields
+    /// are looked up in `StatusSNS` of the `Status 10` command
+    Tasmota { url: &'static str, fields: Fields },
+    /// Shelly 3EM, the counters of all phases are summed up
+    Shelly3Em { url: &'static str },
+    /// JSON messages on an MQTT topic, e.g. `tele/<topic>/SENSOR` of a Tasmota reader. Make
+    /// sure the device publishes more often than `config::METER_MQTT_TIMEOUT`.
+    Mqtt {
+        broker: (&'static str, u16),
+        topic: &'static str,
+        fields: Fields,
+    },
+}
+
+impl Config {
+    /// The configured meter, `url` overrides the configured URL of HTTP meters
+    pub fn meter(&'static self, client: reqwest::Client, url: Option<String>) -> Box<dyn Meter> {
+        let url = |configured: &str| url.unwrap_or_else(|| configured.to_string());
+        match self {
+            Config::Tasmota {
+                url: configured,
+                fields,
+            } => Box::new(Tasmota {
+                client,
+                url: url(configured),
+                fields,
+            }),
+            Config::Shelly3Em { url: configured } => Box::new(Shelly3Em {
+                client,
+                url: url(configured),
+            }),
+            Config::Mqtt {
+                broker,
+                topic,
+                fields,
+            } => Box::new(Mqtt {
+                broker: *broker,
+                topic,
+                fields,
+            }),
+        }
+    }
+}
+
+fn unavailable(err: impl Into<crate::error::Source>) -> PowerlogError {
+    PowerlogError::MeterUnavailable(err.into())
+}
+
+fn bad_response(endpoint: &'static str, reason: impl ToString) -> PowerlogError {
+    PowerlogError::BadResponse {
+        endpoint,
+        reason: reason.to_string(),
+    }
+}
+
+/// The number at `path` in `document`, which may also be sent as a string
+fn lookup(endpoint: &'static str, document: &serde_json::Value, path: Path) -> Result<f64> {
+    let value = path
+        .iter()
+        .try_fold(document, |value, key| value.get(key))
+        .ok_or_else(|| bad_response(endpoint, format!("no value at {}", path.join("."))))?;
+    crate::inverter::number(value).map_err(|err| bad_response(endpoint, err))
+}
+
+fn reading(
+    endpoint: &'static str,
+    document: &serde_json::Value,
+    fields: &Fields,
+) -> Result<Reading> {
+    Ok(Reading {
+        import_total: lookup(endpoint, document, fields.import)?,
+        export_total: lookup(endpoint, document, fields.export)?,
+        power: lookup(endpoint, document, fields.power)?,
+    })
+}
+
+async fn get<T: serde::de::DeserializeOwned>(
+    endpoint: &'static str,
+    request: reqwest::RequestBuilder,
+) -> Result<T> {
+    let body = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(unavailable)?
+        .bytes()
+        .await
+        .map_err(unavailable)?;
+    serde_json::from_slice(&body).map_err(|err| bad_response(endpoint, err))
+}
+
+pub struct Tasmota {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub fields: &'static Fields,
+}
+
+impl Meter for Tasmota {
+    fn name(&self) -> &'static str {
+        "tasmota"
+    }
+
+    fn read(&self) -> BoxFuture<'_, Result<Reading>> {
+        Box::pin(async {
+            const ENDPOINT: &str = "Status 10";
+            let request = self
+                .client
+                .get(format!("{}/cm", self.url))
+                .query(&[("cmnd", ENDPOINT)]);
+            let status: serde_json::Value = get(ENDPOINT, request).await?;
+            let sensors = status
+                .get("StatusSNS")
+                .ok_or_else(|| bad_response(ENDPOINT, "no StatusSNS"))?;
+            reading(ENDPOINT, sensors, self.fields)
+        })
+    }
+}
+
+pub struct Shelly3Em {
+    pub client: reqwest::Client,
+    pub url: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct Emeter {
+    power: f64,
+    /// in Wh
+    total: f64,
+    /// in Wh
+    total_returned: f64,
+}
+
+#[derive(Deserialize, Debug)]
+struct Shelly3EmStatus {
+    emeters: Vec<Emeter>,
+}
+
+impl Meter for Shelly3Em {
+    fn name(&self) -> &'static str {
+        "shelly3em"
+    }
+
+    /// The phases are counted separately, so energy exported on one phase while importing
+    /// on another shows up in both counters unlike on a balancing smart meter
+    fn read(&self) -> BoxFuture<'_, Result<Reading>> {
+        Box::pin(async {
+            let request = self.client.get(format!("{}/status", self.url));
+            let status: Shelly3EmStatus = get("status", request).await?;
+            if status.emeters.is_empty() {
+                return Err(bad_response("status", "no emeters"));
+            }
+            let sum = |value: fn(&Emeter) -> f64| status.emeters.iter().map(value).sum::<f64>();
+            Ok(Reading {
+                import_total: sum(|emeter| emeter.total) / 1000.0,
+                export_total: sum(|emeter| emeter.total_returned) / 1000.0,
+                power: sum(|emeter| emeter.power),
+            })
+        })
+    }
+}
+
+pub struct Mqtt {
+    pub broker: (&'static str, u16),
+    pub topic: &'static str,
+    pub fields: &'static Fields,
+}
+
+impl Mqtt {
+    /// Wait for the next message on the topic, retained messages arrive right away
+    async fn receive(&self) -> Result<Vec<u8>> {
+        use rumqttc::{AsyncClient, Event, MqttOptions, Packet, QoS};
+
+        let (host, port) = self.broker;
+        let mut options =
+            MqttOptions::new(format!("powerlog-meter-{}", std::process::id()), host, port);
+        options.set_keep_alive(std::time::Duration::from_secs(10));
+        let (client, mut eventloop) = AsyncClient::new(options, 10);
+        client
+            .subscribe(self.topic, QoS::AtMostOnce)
+            .await
+            .map_err(unavailable)?;
+
+        let receive = async {
+            loop {
+                match eventloop.poll().await.map_err(unavailable)? {
+                    Event::Incoming(Packet::Publish(publish)) => {
+                        return Ok(publish.payload.to_vec());
+                    }
+                    _ => continue,
+                }
+            }
+        };
+        let payload = tokio::time::timeout(crate::config::METER_MQTT_TIMEOUT, receive)
+            .await
+            .map_err(|_| unavailable(format!("no message on {} in time", self.topic)))?;
+        // best effort, the broker cleans up after us anyway
+        let _ = client.try_disconnect();
+        payload
+    }
+}
+
+impl Meter for Mqtt {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn read(&self) -> BoxFuture<'_, Result<Reading>> {
+        Box::pin(async {
+            const ENDPOINT: &str = "MQTT";
+            let payload = self.receive().await?;
+            let document: serde_json::Value =
+                serde_json::from_slice(&payload).map_err(|err| bad_response(ENDPOINT, err))?;
+            reading(ENDPOINT, &document, self.fields)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use crate::meter::{Fields, Reading, reading};
+
+    #[test]
+    fn lookup_fields() {
+        const FIELDS: Fields = Fields {
+            import: &["SML", "Total_in"],
+            export: &["SML", "Total_out"],
+            power: &["SML", "Power_curr"],
+        };
+        let document = json!({
+            "Time": "2024-04-16T11:30:00",
+            "SML": { "Total_in": 4012.3451, "Total_out": "812.5", "Power_curr": -312 },
+        });
+        assert_eq!(
+            reading("MQTT", &document, &FIELDS).unwrap(),
+            Reading {
+                import_total: 4012.3451,
+                export_total: 812.5,
+                power: -312.0,
+            }
+        );
+
+        let document = json!({ "SML": { "Total_in": 1.0, "Power_curr": 0 } });
+        let err = reading("MQTT", &document, &FIELDS).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "bad response from MQTT: no value at SML.Total_out"
+        );
+    }
+}
@@ -0,0 +1,251 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Prometheus text exposition of the latest sample and the collector health
+use std::fmt::{Display, Write};
+
+pub const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+#[derive(Default)]
+struct Exposition(String);
+
+impl Exposition {
+    fn family(&mut self, name: &str, kind: &str, help: &str) -> &mut Self {
+        writeln!(self.0, "# HELP {name} {help}").unwrap();
+        writeln!(self.0, "# TYPE {name} {kind}").unwrap();
+        self
+    }
+
+    fn sample(&mut self, name: &str, labels: &str, value: impl Display) -> &mut Self {
+        if labels.is_empty() {
+            writeln!(self.0, "{name} {value}").unwrap();
+        } else {
+            writeln!(self.0, "{name}{{{labels}}} {value}").unwrap();
+        }
+        self
+    }
+
+    fn gauge(&mut self, name: &str, help: &str, value: impl Display) -> &mut Self {
+        self.family(name, "gauge", help).sample(name, "", value)
+    }
+
+    fn per_channel(
+        &mut self,
+        name: &str,
+        help: &str,
+        channels: &[crate::db::ChannelReading],
+        value: impl Fn(&crate::db::ChannelReading) -> f32,
+    ) -> &mut Self {
+        self.family(name, "gauge", help);
+        for channel in channels {
+            let labels = format!(r#"channel="{}""#, channel.channel);
+            self.sample(name, &labels, value(channel));
+        }
+        self
+    }
+}
+
+pub fn render(
+    latest: Option<&crate::db::PowerToday>,
+    channels: &[crate::db::ChannelReading],
+    collector_errors: u64,
+) -> String {
+    let mut out = Exposition::default();
+
+    out.family(
+        "powerlog_collector_errors_total",
+        "counter",
+        "Number of errors encountered by the collector.",
+    )
+    .sample(
+        "powerlog_collector_errors_total",
+        "",
+        collector_errors as f64,
+    );
+
+    let Some(latest) = latest else {
+        return out.0;
+    };
+
+    out.gauge(
+        "powerlog_last_sample_timestamp_seconds",
+        "Unix time of the last successfully collected sample.",
+        latest.time.unix_timestamp(),
+    )
+    .per_channel(
+        "powerlog_power_watts",
+        "Current output power per channel.",
+        channels,
+        |channel| channel.power,
+    )
+    .per_channel(
+        "powerlog_energy_today_kilowatt_hours",
+        "Energy generated since the inverter started up today per channel.",
+        channels,
+        |channel| channel.energy_today,
+    )
+    .per_channel(
+        "powerlog_energy_lifetime_kilowatt_hours",
+        "Energy generated over the lifetime of the inverter per channel.",
+        channels,
+        |channel| channel.energy_total,
+    )
+    .gauge(
+        "powerlog_max_power_watts",
+        "Configured maximum output power of the inverter.",
+        latest.max_power,
+    )
+    .gauge(
+        "powerlog_sun_altitude_radians",
+        "Altitude of the sun above the horizon.",
+        latest.sun_altitude,
+    )
+    .gauge(
+        "powerlog_sun_azimuth_radians",
+        "Azimuth of the sun.",
+        latest.sun_azimuth,
+    );
+
+    if let Some(on_off) = latest.on_off {
+        out.gauge(
+            "powerlog_inverter_on",
+            "Whether the inverter is switched on (1) or off (0).",
+            u8::from(on_off),
+        );
+    }
+
+    if let Some(cloud_cover) = latest.cloud_cover {
+        out.gauge(
+            "powerlog_cloud_cover_ratio",
+            "Cloud cover as reported by the weather API.",
+            cloud_cover,
+        );
+    }
+
+    if let Some(grid_power) = latest.grid_power {
+        out.gauge(
+            "powerlog_grid_power_watts",
+            "Power drawn from the grid, negative while feeding in.",
+            grid_power,
+        );
+    }
+    let grid = [
+        (
+            "powerlog_grid_import_kilowatt_hours",
+            "imported from",
+            latest.grid_import_total,
+        ),
+        (
+            "powerlog_grid_export_kilowatt_hours",
+            "exported to",
+            latest.grid_export_total,
+        ),
+    ];
+    for (name, direction, total) in grid {
+        if let Some(total) = total {
+            out.gauge(
+                name,
+                &format!("Energy {direction} the grid as counted by the meter."),
+                total,
+            );
+        }
+    }
+
+    let irradiance = [
+        ("terrestrial", latest.terrestrial_radiation),
+        ("direct", latest.direct_radiation),
+        ("diffuse", latest.diffuse_radiation),
+        ("shortwave", latest.shortwave_radiation),
+        ("direct_normal", latest.direct_normal_irradiance),
+        ("global_tilted", latest.global_tilted_irradiance),
+    ];
+    if irradiance.iter().any(|(_, value)| value.is_some()) {
+        let name = "powerlog_irradiance_watts_per_square_meter";
+        out.family(
+            name,
+            "gauge",
+            "Solar irradiance as reported by the weather API.",
+        );
+        for (kind, value) in irradiance {
+            if let Some(value) = value {
+                out.sample(name, &format!(r#"kind="{kind}""#), value);
+            }
+        }
+    }
+
+    out.0
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn render_without_samples() {
+        let metrics = crate::metrics::render(None, &[], 3);
+        assert_eq!(
+            metrics,
+            "# HELP powerlog_collector_errors_total Number of errors encountered by the collector.\n\
+                 # TYPE powerlog_collector_errors_total counter\n\
+                 powerlog_collector_errors_total 3\n"
+        );
+    }
+
+    #[test]
+    fn render_latest_sample() {
+        let latest = crate::db::PowerToday {
+            id: 1,
+            time: time::OffsetDateTime::from_unix_timestamp(1713259800).unwrap(),
+            power_ch1: 100.5,
+            power_ch2: 200.0,
+            energy_today_ch1: 1.0,
+            energy_today_ch2: 2.0,
+            energy_total_ch1: 10.0,
+            energy_total_ch2: 20.0,
+            max_power: 600.0,
+            cloud_cover: Some(0.5),
+            terrestrial_radiation: None,
+            direct_radiation: Some(123.5),
+            diffuse_radiation: None,
+            shortwave_radiation: None,
+            direct_normal_irradiance: None,
+            global_tilted_irradiance: None,
+            sun_azimuth: 0.25,
+            sun_altitude: 0.5,
+            on_off: Some(true),
+            grid_import_total: Some(1234.5),
+            grid_export_total: None,
+            grid_power: Some(-150.0),
+        };
+        let channel = |channel, power, energy_today, energy_total| crate::db::ChannelReading {
+            time: latest.time,
+            channel,
+            power,
+            energy_today,
+            energy_total,
+        };
+        let channels = [
+            channel(1, 100.5, 1.0, 10.0),
+            channel(2, 200.0, 2.0, 20.0),
+            channel(3, 50.0, 0.5, 5.0),
+        ];
+        let metrics = crate::metrics::render(Some(&latest), &channels, 0);
+        for line in [
+            "powerlog_collector_errors_total 0",
+            "powerlog_last_sample_timestamp_seconds 1713259800",
+            r#"powerlog_power_watts{channel="1"} 100.5"#,
+            r#"powerlog_power_watts{channel="2"} 200"#,
+            r#"powerlog_energy_today_kilowatt_hours{channel="2"} 2"#,
+            r#"powerlog_energy_lifetime_kilowatt_hours{channel="1"} 10"#,
+            r#"powerlog_power_watts{channel="3"} 50"#,
+            "powerlog_max_power_watts 600",
+            "powerlog_inverter_on 1",
+            "powerlog_cloud_cover_ratio 0.5",
+            r#"powerlog_irradiance_watts_per_square_meter{kind="direct"} 123.5"#,
+            "powerlog_sun_altitude_radians 0.5",
+            "powerlog_grid_power_watts -150",
+            "powerlog_grid_import_kilowatt_hours 1234.5",
+        ] {
+            assert!(metrics.lines().any(|l| l == line), "missing {line}");
+        }
+        assert!(!metrics.contains(r#"kind="diffuse""#));
+        assert!(!metrics.contains("powerlog_grid_export"));
+    }
+}
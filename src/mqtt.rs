@@ -0,0 +1,356 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Publish samples to an MQTT broker, including Home Assistant discovery configs
+use anyhow::{Result, bail};
+use rumqttc::{AsyncClient, Event, MqttOptions, Outgoing, Packet, QoS};
+use serde_json::json;
+use std::borrow::Cow;
+use std::time::Duration;
+
+#[derive(Clone)]
+struct Sensor {
+    key: Cow<'static, str>,
+    name: Cow<'static, str>,
+    component: &'static str,
+    device_class: Option<&'static str>,
+    state_class: Option<&'static str>,
+    unit: Option<&'static str>,
+}
+
+const fn sensor(
+    key: &'static str,
+    name: &'static str,
+    device_class: &'static str,
+    state_class: &'static str,
+    unit: &'static str,
+) -> Sensor {
+    Sensor {
+        key: Cow::Borrowed(key),
+        name: Cow::Borrowed(name),
+        component: "sensor",
+        device_class: Some(device_class),
+        state_class: Some(state_class),
+        unit: Some(unit),
+    }
+}
+
+/// The sensors of the 1-based `channel`
+fn channel_sensors(channel: usize) -> [Sensor; 3] {
+    let channel_sensor = |key: &str, name: &str, device_class, state_class, unit| Sensor {
+        key: Cow::Owned(format!("{key}_ch{channel}")),
+        name: Cow::Owned(format!("{name} Channel {channel}")),
+        ..sensor("", "", device_class, state_class, unit)
+    };
+    [
+        channel_sensor("power", "Power", "power", "measurement", "W"),
+        channel_sensor(
+            "energy_today",
+            "Energy Today",
+            "energy",
+            "total_increasing",
+            "kWh",
+        ),
+        channel_sensor(
+            "energy_total",
+            "Energy Lifetime",
+            "energy",
+            "total_increasing",
+            "kWh",
+        ),
+    ]
+}
+
+/// Sensors that don't depend on the number of channels
+const SENSORS: &[Sensor] = &[
+    sensor("max_power", "Max Power", "power", "measurement", "W"),
+    Sensor {
+        key: Cow::Borrowed("on_off"),
+        name: Cow::Borrowed("Status"),
+        component: "binary_sensor",
+        device_class: Some("power"),
+        state_class: None,
+        unit: None,
+    },
+    Sensor {
+        key: Cow::Borrowed("cloud_cover"),
+        name: Cow::Borrowed("Cloud Cover"),
+        component: "sensor",
+        device_class: None,
+        state_class: Some("measurement"),
+        unit: Some("%"),
+    },
+    sensor(
+        "terrestrial_radiation",
+        "Terrestrial Radiation",
+        "irradiance",
+        "measurement",
+        "W/m²",
+    ),
+    sensor(
+        "direct_radiation",
+        "Direct Radiation",
+        "irradiance",
+        "measurement",
+        "W/m²",
+    ),
+    sensor(
+        "diffuse_radiation",
+        "Diffuse Radiation",
+        "irradiance",
+        "measurement",
+        "W/m²",
+    ),
+    sensor(
+        "shortwave_radiation",
+        "Shortwave Radiation",
+        "irradiance",
+        "measurement",
+        "W/m²",
+    ),
+    sensor(
+        "direct_normal_irradiance",
+        "Direct Normal Irradiance",
+        "irradiance",
+        "measurement",
+        "W/m²",
+    ),
+    sensor(
+        "global_tilted_irradiance",
+        "Global Tilted Irradiance",
+        "irradiance",
+        "measurement",
+        "W/m²",
+    ),
+    Sensor {
+        key: Cow::Borrowed("sun_azimuth"),
+        name: Cow::Borrowed("Sun Azimuth"),
+        component: "sensor",
+        device_class: None,
+        state_class: Some("measurement"),
+        unit: Some("°"),
+    },
+    Sensor {
+        key: Cow::Borrowed("sun_altitude"),
+        name: Cow::Borrowed("Sun Altitude"),
+        component: "sensor",
+        device_class: None,
+        state_class: Some("measurement"),
+        unit: Some("°"),
+    },
+];
+
+fn state_topic(device_id: &str, key: &str) -> String {
+    format!("{}/{device_id}/{key}", crate::config::MQTT_TOPIC_PREFIX)
+}
+
+/// All sensors of an inverter with `channels` channels
+fn sensors(channels: usize) -> Vec<Sensor> {
+    (1..=channels)
+        .flat_map(channel_sensors)
+        .chain(SENSORS.iter().cloned())
+        .collect()
+}
+
+fn discovery_config(
+    device_id: &str,
+    model: Option<&crate::inverter::Model>,
+    sensor: &Sensor,
+) -> (String, serde_json::Value) {
+    let name = model.map_or("EZ1", |model| model.name.as_str());
+    let manufacturer = model.map_or("APsystems", |model| model.manufacturer.as_str());
+    let topic = format!(
+        "{}/{}/{device_id}/{}/config",
+        crate::config::MQTT_DISCOVERY_PREFIX,
+        sensor.component,
+        sensor.key
+    );
+    let mut config = json!({
+        "name": sensor.name,
+        "unique_id": format!("{device_id}_{}", sensor.key),
+        "state_topic": state_topic(device_id, &sensor.key),
+        "device": {
+            "identifiers": [device_id],
+            "name": format!("{manufacturer} {name} {device_id}"),
+            "manufacturer": manufacturer,
+            "model": name,
+        },
+    });
+    if let Some(model) = model {
+        config["device"]["sw_version"] = model.firmware.as_str().into();
+    }
+    let optional = [
+        ("device_class", sensor.device_class),
+        ("state_class", sensor.state_class),
+        ("unit_of_measurement", sensor.unit),
+    ];
+    for (key, value) in optional {
+        if let Some(value) = value {
+            config[key] = value.into();
+        }
+    }
+    (topic, config)
+}
+
+fn states(sample: &crate::sample::Sample) -> Vec<(String, String)> {
+    let output_data = &sample.output_data;
+    let sunpos = &sample.sunpos;
+    let mut channel_states = Vec::new();
+    for (i, channel) in output_data.channels.iter().enumerate() {
+        let [power, energy_today, energy_total] = channel_sensors(i + 1).map(|sensor| sensor.key);
+        channel_states.extend([
+            (power, channel.power.to_string()),
+            (energy_today, channel.energy_generation_startup.to_string()),
+            (energy_total, channel.energy_generation_lifetime.to_string()),
+        ]);
+    }
+
+    let mut states = vec![
+        ("max_power", sample.max_power.to_string()),
+        (
+            "on_off",
+            match sample.on_off {
+                crate::inverter::Status::On => "ON",
+                crate::inverter::Status::Off => "OFF",
+            }
+            .to_string(),
+        ),
+        ("sun_azimuth", sunpos.azimuth.to_degrees().to_string()),
+        ("sun_altitude", sunpos.altitude.to_degrees().to_string()),
+    ];
+
+    if let Some(weather) = &sample.weather {
+        states.extend([
+            ("cloud_cover", weather.cloud_cover.to_string()),
+            (
+                "terrestrial_radiation",
+                weather.terrestrial_radiation_instant.to_string(),
+            ),
+            (
+                "direct_radiation",
+                weather.direct_radiation_instant.to_string(),
+            ),
+            (
+                "diffuse_radiation",
+                weather.diffuse_radiation_instant.to_string(),
+            ),
+            (
+                "shortwave_radiation",
+                weather.shortwave_radiation_instant.to_string(),
+            ),
+            (
+                "direct_normal_irradiance",
+                weather.direct_normal_irradiance_instant.to_string(),
+            ),
+            (
+                "global_tilted_irradiance",
+                weather.global_tilted_irradiance_instant.to_string(),
+            ),
+        ]);
+    }
+
+    channel_states
+        .into_iter()
+        .chain(states.into_iter().map(|(key, value)| (key.into(), value)))
+        .map(|(key, value)| (state_topic(sample.device_id(), &key), value))
+        .collect()
+}
+
+pub async fn publish(broker: (&str, u16), sample: &crate::sample::Sample) -> Result<()> {
+    let device_id = sample.device_id();
+
+    // all messages are retained, so Home Assistant picks them up whenever it (re)connects
+    let model = sample.model.as_ref();
+    let mut messages = sensors(sample.output_data.channels.len())
+        .iter()
+        .map(|sensor| {
+            let (topic, config) = discovery_config(device_id, model, sensor);
+            (topic, config.to_string())
+        })
+        .collect::<Vec<_>>();
+    messages.extend(states(sample));
+
+    let mut options = MqttOptions::new(format!("powerlog-{device_id}"), broker.0, broker.1);
+    options.set_keep_alive(Duration::from_secs(10));
+    let (client, mut eventloop) = AsyncClient::new(options, messages.len() + 1);
+
+    let pending = messages.len();
+    for (topic, payload) in messages {
+        client
+            .publish(topic, QoS::AtLeastOnce, true, payload)
+            .await?;
+    }
+
+    let deliver = async {
+        let mut acked = 0;
+        loop {
+            match eventloop.poll().await? {
+                Event::Incoming(Packet::PubAck(_)) => {
+                    acked += 1;
+                    if acked == pending {
+                        client.disconnect().await?;
+                    }
+                }
+                Event::Outgoing(Outgoing::Disconnect) => return Ok(()),
+                _ => {}
+            }
+        }
+    };
+
+    match tokio::time::timeout(Duration::from_secs(10), deliver).await {
+        Ok(result) => result,
+        Err(_) => bail!(
+            "timeout while publishing to MQTT broker {}:{}",
+            broker.0,
+            broker.1
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn lifetime_energy_discovery_config() {
+        let sensors = crate::mqtt::sensors(2);
+        let sensor = sensors
+            .iter()
+            .find(|sensor| sensor.key == "energy_total_ch2")
+            .unwrap();
+        let model = crate::sample::tests::sample().model;
+        let (topic, config) = crate::mqtt::discovery_config("E07000000001", model.as_ref(), sensor);
+        assert_eq!(
+            topic,
+            "homeassistant/sensor/E07000000001/energy_total_ch2/config"
+        );
+        assert_eq!(
+            config["state_topic"],
+            "powerlog/E07000000001/energy_total_ch2"
+        );
+        assert_eq!(config["unique_id"], "E07000000001_energy_total_ch2");
+        assert_eq!(config["device_class"], "energy");
+        assert_eq!(config["state_class"], "total_increasing");
+        assert_eq!(config["unit_of_measurement"], "kWh");
+        assert_eq!(config["device"]["identifiers"][0], "E07000000001");
+        assert_eq!(config["device"]["model"], "EZ1");
+        assert_eq!(config["device"]["sw_version"], "1.6.0");
+    }
+
+    #[test]
+    fn states_without_weather() {
+        let mut sample = crate::sample::tests::sample();
+        sample.sunpos.altitude = std::f64::consts::FRAC_PI_2;
+        let states = crate::mqtt::states(&sample);
+        let state = |key: &str| {
+            let topic = format!("powerlog/E07000000001/{key}");
+            states
+                .iter()
+                .find(|(t, _)| *t == topic)
+                .map(|(_, v)| v.as_str())
+        };
+        assert_eq!(state("power_ch1"), Some("1.5"));
+        assert_eq!(state("energy_total_ch2"), Some("6"));
+        assert_eq!(state("max_power"), Some("600"));
+        assert_eq!(state("on_off"), Some("ON"));
+        assert_eq!(state("sun_altitude"), Some("90"));
+        assert_eq!(state("cloud_cover"), None);
+    }
+}
@@ -0,0 +1,347 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Destinations of alerts and reports, see `config::NOTIFIERS`
+use anyhow::{Context, Result, bail};
+use futures::future::BoxFuture;
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::report::Format;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Firing,
+    Resolved,
+}
+
+impl Status {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Status::Firing => "firing",
+            Status::Resolved => "resolved",
+        }
+    }
+}
+
+/// The alert a [`Notification`] is about
+#[derive(Clone, Debug, Serialize)]
+pub struct AlertChange {
+    pub rule: &'static str,
+    pub status: Status,
+}
+
+/// Sent as JSON by the [`Webhook`]
+#[derive(Clone, Debug, Serialize)]
+pub struct Notification {
+    pub title: String,
+    pub message: String,
+    /// of `message`
+    pub format: Format,
+    #[serde(with = "time::serde::iso8601")]
+    pub time: time::OffsetDateTime,
+    /// `None` e.g. for reports
+    #[serde(flatten)]
+    pub alert: Option<AlertChange>,
+}
+
+impl Notification {
+    pub fn alert(
+        rule: &'static str,
+        status: Status,
+        message: String,
+        time: time::OffsetDateTime,
+    ) -> Self {
+        Self {
+            title: format!("powerlog: {rule} {}", status.as_str()),
+            message,
+            format: Format::Text,
+            time,
+            alert: Some(AlertChange { rule, status }),
+        }
+    }
+
+    pub fn status(&self) -> Option<Status> {
+        self.alert.as_ref().map(|alert| alert.status)
+    }
+}
+
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>>;
+}
+
+/// How to reach a notifier, URLs are complete including e.g. the ntfy topic
+pub enum Config {
+    Webhook {
+        url: &'static str,
+    },
+    Ntfy {
+        url: &'static str,
+        token: Option<&'static str>,
+    },
+    Gotify {
+        url: &'static str,
+        token: &'static str,
+    },
+    /// plain SMTP without authentication or TLS, meant for a relay on the local network
+    Smtp {
+        address: &'static str,
+        from: &'static str,
+        to: &'static [&'static str],
+    },
+}
+
+impl Config {
+    pub fn notifier(&self, client: reqwest::Client) -> Box<dyn Notifier> {
+        match *self {
+            Config::Webhook { url } => Box::new(Webhook {
+                client,
+                url: url.to_string(),
+            }),
+            Config::Ntfy { url, token } => Box::new(Ntfy {
+                client,
+                url: url.to_string(),
+                token: token.map(str::to_string),
+            }),
+            Config::Gotify { url, token } => Box::new(Gotify {
+                client,
+                url: url.to_string(),
+                token: token.to_string(),
+            }),
+            Config::Smtp { address, from, to } => Box::new(Smtp {
+                address: address.to_string(),
+                from: from.to_string(),
+                to: to.iter().map(|to| to.to_string()).collect(),
+                timeout: crate::config::NOTIFY_TIMEOUT,
+            }),
+        }
+    }
+}
+
+pub fn configured(client: reqwest::Client) -> Vec<Box<dyn Notifier>> {
+    crate::config::NOTIFIERS
+        .iter()
+        .map(|config| config.notifier(client.clone()))
+        .collect()
+}
+
+/// Send `notification` via all `notifiers` concurrently, like [`crate::sink::fan_out`]
+pub async fn send_all(
+    notifiers: &[Box<dyn Notifier>],
+    notification: &Notification,
+) -> Vec<(&'static str, anyhow::Error)> {
+    let results =
+        futures::future::join_all(notifiers.iter().map(|notifier| notifier.send(notification)))
+            .await;
+    notifiers
+        .iter()
+        .zip(results)
+        .filter_map(|(notifier, result)| result.err().map(|err| (notifier.name(), err)))
+        .collect()
+}
+
+/// POSTs the notification as JSON
+pub struct Webhook {
+    pub client: reqwest::Client,
+    pub url: String,
+}
+
+impl Notifier for Webhook {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            self.client
+                .post(&self.url)
+                .json(notification)
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        })
+    }
+}
+
+/// Publishes to the topic at `url` of an ntfy server, see https://docs.ntfy.sh/publish/
+pub struct Ntfy {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub token: Option<String>,
+}
+
+impl Notifier for Ntfy {
+    fn name(&self) -> &'static str {
+        "ntfy"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let (priority, tags) = match notification.status() {
+                Some(Status::Firing) => ("high", "warning"),
+                Some(Status::Resolved) => ("default", "white_check_mark"),
+                None => ("default", "bar_chart"),
+            };
+            let mut request = self
+                .client
+                .post(&self.url)
+                .header("Title", &notification.title)
+                .header("Priority", priority)
+                .header("Tags", tags)
+                .body(notification.message.clone());
+            // HTML is shown as is
+            if notification.format == Format::Markdown {
+                request = request.header("Markdown", "yes");
+            }
+            if let Some(token) = &self.token {
+                request = request.bearer_auth(token);
+            }
+            request.send().await?.error_for_status()?;
+            Ok(())
+        })
+    }
+}
+
+/// Pushes to a Gotify server with the token of an application
+pub struct Gotify {
+    pub client: reqwest::Client,
+    pub url: String,
+    pub token: String,
+}
+
+impl Notifier for Gotify {
+    fn name(&self) -> &'static str {
+        "gotify"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let priority = match notification.status() {
+                Some(Status::Firing) => 8,
+                Some(Status::Resolved) => 4,
+                None => 2,
+            };
+            let content_type = match notification.format {
+                Format::Markdown => "text/markdown",
+                Format::Text | Format::Html => "text/plain",
+            };
+            self.client
+                .post(format!("{}/message", self.url.trim_end_matches('/')))
+                .header("X-Gotify-Key", &self.token)
+                .json(&serde_json::json!({
+                    "title": notification.title,
+                    "message": notification.message,
+                    "priority": priority,
+                    "extras": { "client::display": { "contentType": content_type } },
+                }))
+                .send()
+                .await?
+                .error_for_status()?;
+            Ok(())
+        })
+    }
+}
+
+/// Sends a mail to all recipients via an SMTP relay, an HTML one for HTML messages
+pub struct Smtp {
+    /// host and port of the relay
+    pub address: String,
+    pub from: String,
+    pub to: Vec<String>,
+    /// for the whole dialog with the relay
+    pub timeout: std::time::Duration,
+}
+
+impl Notifier for Smtp {
+    fn name(&self) -> &'static str {
+        "smtp"
+    }
+
+    fn send<'a>(&'a self, notification: &'a Notification) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async move {
+            let mail = self.mail(notification)?;
+            tokio::time::timeout(self.timeout, self.deliver(&mail))
+                .await
+                .with_context(|| format!("timeout talking to {}", self.address))?
+        })
+    }
+}
+
+impl Smtp {
+    fn mail(&self, notification: &Notification) -> Result<String> {
+        let date = notification
+            .time
+            .format(&time::format_description::well_known::Rfc2822)?;
+        let content_type = match notification.format {
+            Format::Html => "text/html",
+            Format::Text | Format::Markdown => "text/plain",
+        };
+        let headers = format!(
+            "From: {}\r\nTo: {}\r\nSubject: {}\r\nDate: {date}\r\nContent-Type: {content_type}; charset=utf-8\r\nContent-Transfer-Encoding: 8bit\r\n\r\n",
+            self.from,
+            self.to.join(", "),
+            notification.title,
+        );
+        // lines starting with a dot need another one, see RFC 5321 section 4.5.2
+        let body: String = notification
+            .message
+            .lines()
+            .map(|line| match line.starts_with('.') {
+                true => format!(".{line}\r\n"),
+                false => format!("{line}\r\n"),
+            })
+            .collect();
+        Ok(headers + &body)
+    }
+
+    async fn deliver(&self, mail: &str) -> Result<()> {
+        let stream = tokio::net::TcpStream::connect(&self.address)
+            .await
+            .with_context(|| format!("failed to connect to {}", self.address))?;
+        let (reader, mut writer) = stream.into_split();
+        let mut reader = BufReader::new(reader);
+
+        expect(&mut reader, 220).await?;
+        let mut commands = vec![
+            ("EHLO powerlog".to_string(), 250),
+            (format!("MAIL FROM:<{}>", self.from), 250),
+        ];
+        commands.extend(self.to.iter().map(|to| (format!("RCPT TO:<{to}>"), 250)));
+        commands.extend([
+            ("DATA".to_string(), 354),
+            (format!("{mail}."), 250),
+            ("QUIT".to_string(), 221),
+        ]);
+        for (command, code) in commands {
+            writer
+                .write_all(format!("{command}\r\n").as_bytes())
+                .await?;
+            expect(&mut reader, code).await?;
+        }
+        Ok(())
+    }
+}
+
+/// Read a possibly multiline reply and check its code
+async fn expect(reader: &mut BufReader<tokio::net::tcp::OwnedReadHalf>, code: u16) -> Result<()> {
+    loop {
+        let mut line = String::new();
+        if reader.read_line(&mut line).await? == 0 {
+            bail!("connection closed by SMTP server");
+        }
+        let Some(reply) = line.get(..3).and_then(|reply| reply.parse::<u16>().ok()) else {
+            bail!("invalid SMTP reply {line:?}");
+        };
+        // continuation lines are marked by a dash after the code
+        if line.as_bytes().get(3) == Some(&b'-') {
+            continue;
+        }
+        if reply != code {
+            bail!("unexpected SMTP reply {:?}", line.trim_end());
+        }
+        return Ok(());
+    }
+}
@@ -0,0 +1,480 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Summaries of the samples of a day, month or year, see `powerlog report` and
+//! `config::REPORTS`
+use anyhow::{Context, Result, bail};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+use crate::db::{self, ChannelReading, EnergyByDay, Totals};
+use crate::finance::Period;
+use crate::notify::{self, Notification, Notifier};
+use crate::{alert, config};
+
+/// output of at least this share of the max power counts as clipped
+const CLIPPING: f64 = 0.98;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Format {
+    Text,
+    Markdown,
+    Html,
+}
+
+impl std::str::FromStr for Format {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "text" => Ok(Format::Text),
+            "markdown" => Ok(Format::Markdown),
+            "html" => Ok(Format::Html),
+            _ => Err("expected text, markdown or html".to_string()),
+        }
+    }
+}
+
+impl Format {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Text => "txt",
+            Format::Markdown => "md",
+            Format::Html => "html",
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Report {
+    pub period: Period,
+    /// like `2024-06` for a month
+    pub label: String,
+    /// days with samples
+    pub days: usize,
+    /// in kWh
+    pub generated: f64,
+    /// channel number and kWh
+    pub channels: Vec<(i32, f64)>,
+    /// in W
+    pub peak_power: Option<(f64, time::OffsetDateTime)>,
+    /// from the first to the last sample producing power, summed up over all days
+    pub production_hours: f64,
+    /// mean ratio while producing
+    pub cloud_cover: Option<f64>,
+    /// kWh expected from the global tilted irradiance of the weather model stored with the
+    /// samples, see `config::PEAK_POWER`. This is not a day-ahead forecast.
+    pub expected: Option<f64>,
+    /// daily kWh of the 30 days before the period
+    pub mean_30_days: Option<f64>,
+    /// kWh estimated from the irradiance while the output was at its max power
+    pub clipping_loss: f64,
+}
+
+/// Sum up the chronological `totals` and `channels` of the period of `date`, the energy `days`
+/// before it are used for the 30-day mean
+pub fn summarize(
+    period: Period,
+    date: time::Date,
+    totals: &[Totals],
+    channels: &[ChannelReading],
+    days: &[EnergyByDay],
+) -> Report {
+    // a reset counter starts from zero again
+    let mut energy: BTreeMap<i32, (f32, f64)> = BTreeMap::new();
+    for reading in channels {
+        let (last, sum) = energy
+            .entry(reading.channel)
+            .or_insert((reading.energy_total, 0.0));
+        *sum += match reading.energy_total >= *last {
+            true => (reading.energy_total - *last) as f64,
+            false => reading.energy_total as f64,
+        };
+        *last = reading.energy_total;
+    }
+    let channels: Vec<(i32, f64)> = energy
+        .into_iter()
+        .map(|(channel, (_, sum))| (channel, sum))
+        .collect();
+
+    let producing: Vec<&Totals> = totals.iter().filter(|sample| sample.power > 0.0).collect();
+    let cloud_cover: Vec<f64> = producing.iter().filter_map(|s| s.cloud_cover).collect();
+
+    let mut report = Report {
+        period,
+        label: period.label(date),
+        days: 0,
+        generated: channels.iter().map(|(_, energy)| energy).sum(),
+        channels,
+        peak_power: producing
+            .iter()
+            .max_by(|a, b| a.power.total_cmp(&b.power))
+            .map(|peak| (peak.power, peak.time)),
+        production_hours: 0.0,
+        cloud_cover: (!cloud_cover.is_empty())
+            .then(|| cloud_cover.iter().sum::<f64>() / cloud_cover.len() as f64),
+        expected: totals
+            .iter()
+            .any(|sample| sample.global_tilted_irradiance.is_some())
+            .then_some(0.0),
+        mean_30_days: None,
+        clipping_loss: 0.0,
+    };
+
+    for day in totals.chunk_by(|a, b| a.time.date() == b.time.date()) {
+        report.days += 1;
+        let mut producing = day.iter().filter(|sample| sample.power > 0.0);
+        if let (Some(first), Some(last)) = (producing.next(), producing.next_back()) {
+            report.production_hours += (last.time - first.time).as_seconds_f64() / 3600.0;
+        }
+        if let Some(expected) = &mut report.expected {
+            *expected += alert::expected_energy(day, config::PEAK_POWER, config::PERFORMANCE_RATIO);
+        }
+        for pair in day.windows(2) {
+            let sample = &pair[1];
+            let expected =
+                alert::expected_power(sample, config::PEAK_POWER, config::PERFORMANCE_RATIO);
+            if let Some(expected) = expected
+                && sample.power >= CLIPPING * sample.max_power
+            {
+                let hours = (sample.time - pair[0].time).as_seconds_f64() / 3600.0;
+                report.clipping_loss += (expected - sample.power).max(0.0) / 1000.0 * hours;
+            }
+        }
+    }
+
+    let (start, _) = period.range(date);
+    let before: Vec<f64> = days
+        .iter()
+        .filter(|day| day.date < start && day.date >= start - time::Duration::days(30))
+        .map(|day| day.generated)
+        .collect();
+    report.mean_30_days =
+        (!before.is_empty()).then(|| before.iter().sum::<f64>() / before.len() as f64);
+    report
+}
+
+/// The report of the period containing `date`, days start at midnight UTC
+pub async fn load(
+    db: &sea_orm::DatabaseConnection,
+    period: Period,
+    date: time::Date,
+) -> Result<Report> {
+    let (start, end) = period.range(date);
+    let (start, end) = (start.midnight().assume_utc(), end.midnight().assume_utc());
+    Ok(summarize(
+        period,
+        date,
+        &db::select_totals(db, start, end).await?,
+        &db::select_channels_between(db, start, end).await?,
+        &db::select_energy_by_day(db).await?,
+    ))
+}
+
+fn percent(part: f64, total: f64) -> String {
+    match total > 0.0 {
+        true => format!("{:.0} %", part / total * 100.0),
+        false => "-".to_string(),
+    }
+}
+
+impl Report {
+    pub fn title(&self) -> String {
+        format!("powerlog report for {}", self.label)
+    }
+
+    /// Label and value of each line of the report
+    fn rows(&self) -> Vec<(String, String)> {
+        let mut rows = vec![(
+            "Generated".to_string(),
+            format!("{:.2} kWh", self.generated),
+        )];
+        for (channel, energy) in &self.channels {
+            rows.push((format!("Channel {channel}"), format!("{energy:.2} kWh")));
+        }
+        rows.push((
+            "Peak power".to_string(),
+            match self.peak_power {
+                Some((power, time)) if self.period == Period::Day => {
+                    format!(
+                        "{power:.0} W at {:02}:{:02} UTC",
+                        time.hour(),
+                        time.minute()
+                    )
+                }
+                Some((power, time)) => format!(
+                    "{power:.0} W at {} {:02}:{:02} UTC",
+                    time.date(),
+                    time.hour(),
+                    time.minute()
+                ),
+                None => "-".to_string(),
+            },
+        ));
+        rows.push((
+            "Production".to_string(),
+            format!("{:.1} h", self.production_hours),
+        ));
+        rows.push((
+            "Cloud cover".to_string(),
+            match self.cloud_cover {
+                Some(cloud_cover) => percent(cloud_cover, 1.0),
+                None => "-".to_string(),
+            },
+        ));
+        rows.push((
+            "Expected".to_string(),
+            match self.expected {
+                Some(expected) => format!(
+                    "{expected:.2} kWh from the modelled irradiance, {} reached",
+                    percent(self.generated, expected)
+                ),
+                None => "-".to_string(),
+            },
+        ));
+        let daily = self.generated / self.days.max(1) as f64;
+        rows.push((
+            "30-day mean".to_string(),
+            match self.mean_30_days {
+                Some(mean) => {
+                    format!("{mean:.2} kWh per day, {} reached", percent(daily, mean))
+                }
+                None => "-".to_string(),
+            },
+        ));
+        rows.push((
+            "Clipping loss".to_string(),
+            format!("{:.2} kWh", self.clipping_loss),
+        ));
+        rows
+    }
+
+    pub fn render(&self, format: Format) -> String {
+        let title = self.title();
+        let rows = self.rows();
+        let mut out = String::new();
+        match format {
+            Format::Text => {
+                let width = rows
+                    .iter()
+                    .map(|(label, _)| label.len() + 1)
+                    .max()
+                    .unwrap_or(0);
+                writeln!(out, "{title}\n{}\n", "=".repeat(title.len())).unwrap();
+                for (label, value) in rows {
+                    writeln!(out, "{:width$}  {value}", format!("{label}:")).unwrap();
+                }
+            }
+            Format::Markdown => {
+                writeln!(out, "# {title}\n\n| | |\n|---|---|").unwrap();
+                for (label, value) in rows {
+                    writeln!(out, "| {label} | {value} |").unwrap();
+                }
+            }
+            Format::Html => {
+                writeln!(
+                    out,
+                    "<!DOCTYPE html>\n<html>\n<head><meta charset=\"utf-8\"><title>{title}</title></head>\n<body>\n<h1>{title}</h1>\n<table>"
+                )
+                .unwrap();
+                for (label, value) in rows {
+                    writeln!(out, "<tr><th>{label}</th><td>{value}</td></tr>").unwrap();
+                }
+                writeln!(out, "</table>\n</body>\n</html>").unwrap();
+            }
+        }
+        out
+    }
+
+    pub fn notification(&self, format: Format, time: time::OffsetDateTime) -> Notification {
+        Notification {
+            title: self.title(),
+            message: self.render(format),
+            format,
+            time,
+            alert: None,
+        }
+    }
+
+    /// Write the report to `powerlog-<label>.<extension>` in `directory`
+    pub async fn write(
+        &self,
+        format: Format,
+        directory: &std::path::Path,
+    ) -> Result<std::path::PathBuf> {
+        let path = directory.join(format!("powerlog-{}.{}", self.label, format.extension()));
+        tokio::fs::write(&path, self.render(format))
+            .await
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        Ok(path)
+    }
+
+    pub async fn send(&self, format: Format, notifiers: &[Box<dyn Notifier>]) -> Result<()> {
+        if notifiers.is_empty() {
+            bail!("no notifiers configured");
+        }
+        let notification = self.notification(format, time::OffsetDateTime::now_utc());
+        let errors = notify::send_all(notifiers, &notification).await;
+        if !errors.is_empty() {
+            let errors: Vec<_> = errors
+                .iter()
+                .map(|(name, err)| format!("{name}: {err:#}"))
+                .collect();
+            bail!("failed to send report: {}", errors.join("; "));
+        }
+        Ok(())
+    }
+}
+
+pub enum Delivery {
+    /// to all of `config::NOTIFIERS`
+    Notify,
+    /// into the given directory
+    Directory(&'static str),
+}
+
+/// A report of the previous period, delivered at `at` (UTC) on the first day of a period
+pub struct Schedule {
+    pub period: Period,
+    pub at: time::Time,
+    pub format: Format,
+    pub delivery: Delivery,
+}
+
+impl Schedule {
+    /// The first time after `now` the schedule is due
+    pub fn next(&self, now: time::OffsetDateTime) -> time::OffsetDateTime {
+        let (start, end) = self.period.range(now.date());
+        let due = start.with_time(self.at).assume_utc();
+        match due > now {
+            true => due,
+            false => end.with_time(self.at).assume_utc(),
+        }
+    }
+}
+
+/// Deliver the reports of `schedules` while the process is running, failures are logged
+pub async fn run_schedules(
+    db: sea_orm::DatabaseConnection,
+    schedules: &[Schedule],
+    notifiers: Vec<Box<dyn Notifier>>,
+) -> Result<()> {
+    if schedules.is_empty() {
+        return futures::future::pending().await;
+    }
+    loop {
+        let now = time::OffsetDateTime::now_utc();
+        let (due, schedule) = schedules
+            .iter()
+            .map(|schedule| (schedule.next(now), schedule))
+            .min_by_key(|(due, _)| *due)
+            .unwrap();
+        tokio::time::sleep((due - now).try_into().unwrap_or_default()).await;
+
+        // the day before the first day of the new period
+        let date = due.date().previous_day().unwrap();
+        let result = match load(&db, schedule.period, date).await {
+            Ok(report) => match schedule.delivery {
+                Delivery::Notify => report.send(schedule.format, &notifiers).await,
+                Delivery::Directory(directory) => report
+                    .write(schedule.format, directory.as_ref())
+                    .await
+                    .map(|path| println!("wrote {}", path.display())),
+            },
+            Err(err) => Err(err),
+        };
+        if let Err(err) = result {
+            eprintln!("Error: report for {date}: {err:?}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::db::{ChannelReading, EnergyByDay, Totals};
+    use crate::finance::{Period, date};
+    use crate::report::{Format, summarize};
+    use time::Month::{December, January, June, May};
+
+    #[test]
+    fn summarize_a_day() {
+        let start = date(2024, June, 21)
+            .with_hms(10, 0, 0)
+            .unwrap()
+            .assume_utc();
+        let at = |minutes| start + time::Duration::minutes(minutes);
+        // power, irradiance and cloud cover every half hour, clipped at 11:00
+        let totals: Vec<_> = [
+            (0.0, 500.0, None),
+            (400.0, 500.0, Some(0.2)),
+            (800.0, 1500.0, Some(0.4)),
+            (300.0, 500.0, None),
+        ]
+        .into_iter()
+        .enumerate()
+        .map(|(i, (power, irradiance, cloud_cover))| Totals {
+            time: at(30 * i as i64),
+            generated: 0.0,
+            power,
+            max_power: 800.0,
+            cloud_cover,
+            global_tilted_irradiance: Some(irradiance),
+        })
+        .collect();
+        // the counter of channel 2 gets reset
+        let channels: Vec<_> = [[5.0, 5.0], [5.25, 5.25], [5.5, 0.25], [5.75, 0.5]]
+            .into_iter()
+            .enumerate()
+            .flat_map(|(i, energy)| {
+                (0..2).map(move |channel| ChannelReading {
+                    time: at(30 * i as i64),
+                    channel: channel + 1,
+                    power: 0.0,
+                    energy_today: 0.0,
+                    energy_total: energy[channel as usize],
+                })
+            })
+            .collect();
+        let days = [
+            (date(2024, May, 1), 100.0),
+            (date(2024, June, 19), 2.0),
+            (date(2024, June, 20), 4.0),
+        ]
+        .map(|(date, generated)| EnergyByDay {
+            date,
+            generated,
+            feed_in: None,
+        });
+
+        let report = summarize(Period::Day, start.date(), &totals, &channels, &days);
+        assert_eq!(report.label, "2024-06-21");
+        assert_eq!(report.channels, [(1, 0.75), (2, 0.75)]);
+        assert_eq!(report.generated, 1.5);
+        assert_eq!(report.peak_power, Some((800.0, at(60))));
+        assert_eq!(report.production_hours, 1.0);
+        assert!((report.cloud_cover.unwrap() - 0.3).abs() < 1e-9);
+        assert!((report.expected.unwrap() - 0.8).abs() < 1e-9);
+        assert_eq!(report.mean_30_days, Some(3.0));
+        assert!((report.clipping_loss - 0.08).abs() < 1e-9);
+
+        let text = report.render(Format::Text);
+        assert!(text.starts_with("powerlog report for 2024-06-21\n====="));
+        assert!(text.contains("\nPeak power:     800 W at 11:00 UTC\n"));
+        assert!(
+            text.contains(
+                "\nExpected:       0.80 kWh from the modelled irradiance, 188 % reached\n"
+            )
+        );
+        assert!(text.contains("\n30-day mean:    3.00 kWh per day, 50 % reached\n"));
+        let markdown = report.render(Format::Markdown);
+        assert!(markdown.contains("\n| Clipping loss | 0.08 kWh |\n"));
+        let html = report.render(Format::Html);
+        assert!(html.contains("<tr><th>Channel 2</th><td>0.75 kWh</td></tr>"));
+
+        assert_eq!(
+            Period::Month.range(date(2024, December, 15)),
+            (date(2024, December, 1), date(2025, January, 1))
+        );
+    }
+}
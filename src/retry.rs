@@ -0,0 +1,105 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Repeating flaky requests with exponential backoff and jitter
+use std::time::Duration;
+
+use crate::error::Result;
+
+#[derive(Clone, Copy, Debug)]
+pub struct Policy {
+    /// total number of attempts, including the first one
+    pub attempts: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Policy {
+    /// Delay before the given retry, starting at 1. The backoff doubles with every retry up
+    /// to `max_backoff`, of which `jitter` in `[0, 1)` selects a share between half and all.
+    pub fn backoff(&self, retry: u32, jitter: f64) -> Duration {
+        let exponential = self
+            .initial_backoff
+            .saturating_mul(1 << retry.saturating_sub(1).min(16));
+        exponential
+            .min(self.max_backoff)
+            .mul_f64(0.5 + jitter / 2.0)
+    }
+}
+
+fn jitter() -> f64 {
+    let mut bytes = [0u8; 4];
+    // without randomness we merely lose the spreading of retries
+    if getrandom::getrandom(&mut bytes).is_err() {
+        return 0.0;
+    }
+    u32::from_le_bytes(bytes) as f64 / (u32::MAX as f64 + 1.0)
+}
+
+/// The result of an operation together with how often it was retried
+pub struct Retried<T> {
+    pub result: Result<T>,
+    pub retries: u32,
+}
+
+/// Run `operation` until it succeeds, fails permanently or the `policy` is exhausted
+pub async fn retry<T, F, Fut>(policy: &Policy, mut operation: F) -> Retried<T>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    let mut retries = 0;
+    loop {
+        let result = operation().await;
+        match &result {
+            Err(err) if err.is_transient() && retries + 1 < policy.attempts => {
+                retries += 1;
+                let delay = policy.backoff(retries, jitter());
+                eprintln!("retrying in {delay:?}: {err}");
+                tokio::time::sleep(delay).await;
+            }
+            _ => return Retried { result, retries },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use crate::error::PowerlogError;
+    use crate::retry::Policy;
+
+    const POLICY: Policy = Policy {
+        attempts: 3,
+        initial_backoff: Duration::from_secs(1),
+        max_backoff: Duration::from_secs(3),
+    };
+
+    #[test]
+    fn backoff() {
+        assert_eq!(POLICY.backoff(1, 0.0), Duration::from_millis(500));
+        assert_eq!(POLICY.backoff(2, 0.0), Duration::from_secs(1));
+        assert_eq!(POLICY.backoff(2, 0.5), Duration::from_millis(1500));
+        // capped by max_backoff
+        assert_eq!(POLICY.backoff(3, 0.0), Duration::from_millis(1500));
+        assert_eq!(POLICY.backoff(30, 0.0), Duration::from_millis(1500));
+    }
+
+    #[tokio::test]
+    async fn permanent_errors_are_not_retried() {
+        let mut calls = 0;
+        let retried = crate::retry::retry(&POLICY, || {
+            calls += 1;
+            async {
+                Err::<(), _>(PowerlogError::BadResponse {
+                    endpoint: "getOutputData",
+                    reason: "message FAILED".to_string(),
+                })
+            }
+        })
+        .await;
+        assert!(retried.result.is_err());
+        assert_eq!(retried.retries, 0);
+        assert_eq!(calls, 1);
+    }
+}
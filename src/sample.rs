@@ -0,0 +1,73 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+/// Everything the collector gathered at a given point in time
+#[derive(Debug)]
+pub struct Sample {
+    pub time: time::OffsetDateTime,
+    pub output_data: crate::inverter::OutputData,
+    /// `None` if the device info couldn't be read
+    pub model: Option<crate::inverter::Model>,
+    pub max_power: f64,
+    pub on_off: crate::inverter::Status,
+    pub weather: Option<crate::weather::CurrentWeather>,
+    /// `None` without a configured meter or if it couldn't be read
+    pub meter: Option<crate::meter::Reading>,
+    pub sunpos: sun::Position,
+    pub retries: Retries,
+}
+
+/// How often requests had to be repeated to gather a [`Sample`]
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Retries {
+    pub inverter: u32,
+    pub weather: u32,
+}
+
+impl Sample {
+    pub fn device_id(&self) -> &str {
+        &self.output_data.device_id
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use crate::inverter::{Model, OutputChannel, OutputData, Status};
+
+    pub fn sample() -> crate::sample::Sample {
+        crate::sample::Sample {
+            time: time::OffsetDateTime::from_unix_timestamp_nanos(1713259800123456789).unwrap(),
+            output_data: OutputData {
+                device_id: "E07000000001".to_string(),
+                channels: vec![
+                    OutputChannel {
+                        power: 1.5,
+                        energy_generation_startup: 2.0,
+                        energy_generation_lifetime: 3.0,
+                    },
+                    OutputChannel {
+                        power: 4.0,
+                        energy_generation_startup: 5.0,
+                        energy_generation_lifetime: 6.0,
+                    },
+                ],
+            },
+            model: Some(Model {
+                device_id: "E07000000001".to_string(),
+                manufacturer: "APsystems".to_string(),
+                name: "EZ1".to_string(),
+                firmware: "1.6.0".to_string(),
+                min_power: 30.0,
+                max_power: 800.0,
+            }),
+            max_power: 600.0,
+            on_off: Status::On,
+            weather: None,
+            meter: None,
+            sunpos: sun::Position {
+                azimuth: 0.25,
+                altitude: 0.5,
+            },
+            retries: crate::sample::Retries::default(),
+        }
+    }
+}
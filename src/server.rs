@@ -0,0 +1,203 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Serve the api on plain TCP, TLS and Unix domain socket listeners
+use anyhow::{Context, Result};
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto::Builder;
+use hyper_util::service::TowerToHyperService;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio_rustls::rustls;
+
+pub enum Listen {
+    /// Plain HTTP, e.g. `127.0.0.1:4334` or `[::1]:4334`
+    Tcp(&'static str),
+    /// HTTPS, the PEM encoded certificate chain and key get reloaded when the files change
+    Tls {
+        address: &'static str,
+        cert: &'static str,
+        key: &'static str,
+    },
+    /// Plain HTTP on a Unix domain socket, e.g. for a reverse proxy on the same host
+    Unix(&'static str),
+}
+
+const CERT_RELOAD_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Serve `app` on all `listen` addresses, only returns on failure
+pub async fn serve(listen: &[Listen], app: axum::Router) -> Result<()> {
+    let mut servers = tokio::task::JoinSet::new();
+
+    // bind everything upfront, so configuration errors surface immediately
+    for listen in listen {
+        let app = app.clone();
+        match *listen {
+            Listen::Tcp(address) => {
+                let listener = tokio::net::TcpListener::bind(address)
+                    .await
+                    .with_context(|| format!("failed to bind {address}"))?;
+                servers.spawn(async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((stream, _)) => serve_connection(stream, app.clone()),
+                            Err(err) => accept_failed(err).await,
+                        }
+                    }
+                });
+            }
+            Listen::Tls { address, cert, key } => {
+                let resolver = Arc::new(ReloadingCert::new(cert, key)?);
+                let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(
+                    rustls::crypto::ring::default_provider(),
+                ))
+                .with_safe_default_protocol_versions()?
+                .with_no_client_auth()
+                .with_cert_resolver(resolver.clone());
+                config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+                let acceptor = tokio_rustls::TlsAcceptor::from(Arc::new(config));
+
+                let listener = tokio::net::TcpListener::bind(address)
+                    .await
+                    .with_context(|| format!("failed to bind {address}"))?;
+                tokio::spawn(resolver.reload_periodically());
+                servers.spawn(async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((stream, _)) => {
+                                let acceptor = acceptor.clone();
+                                let app = app.clone();
+                                tokio::spawn(async move {
+                                    // failed handshakes are the client's problem
+                                    if let Ok(stream) = acceptor.accept(stream).await {
+                                        serve_connection(stream, app);
+                                    }
+                                });
+                            }
+                            Err(err) => accept_failed(err).await,
+                        }
+                    }
+                });
+            }
+            Listen::Unix(path) => {
+                remove_stale_socket(path)?;
+                let listener = tokio::net::UnixListener::bind(path)
+                    .with_context(|| format!("failed to bind {path}"))?;
+                servers.spawn(async move {
+                    loop {
+                        match listener.accept().await {
+                            Ok((stream, _)) => serve_connection(stream, app.clone()),
+                            Err(err) => accept_failed(err).await,
+                        }
+                    }
+                });
+            }
+        }
+    }
+
+    // the accept loops never return, so this only yields when one of them panicked
+    if let Some(result) = servers.join_next().await {
+        result?;
+    }
+    Ok(())
+}
+
+fn serve_connection<S>(stream: S, app: axum::Router)
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    tokio::spawn(async move {
+        // like `axum::serve`, ignore errors of individual connections, e.g. when clients go away
+        let _ = Builder::new(TokioExecutor::new())
+            .serve_connection_with_upgrades(TokioIo::new(stream), TowerToHyperService::new(app))
+            .await;
+    });
+}
+
+// accepting fails e.g. when we run out of file descriptors, back off for a bit like `axum::serve`
+async fn accept_failed(err: std::io::Error) {
+    eprintln!("failed to accept connection: {err:?}");
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
+
+// a previous instance leaves its socket behind, but never remove anything else
+fn remove_stale_socket(path: &str) -> Result<()> {
+    use std::os::unix::fs::FileTypeExt;
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => Ok(std::fs::remove_file(path)?),
+        _ => Ok(()),
+    }
+}
+
+#[derive(Debug)]
+struct ReloadingCert {
+    cert: &'static str,
+    key: &'static str,
+    current: RwLock<(SystemTime, Arc<rustls::sign::CertifiedKey>)>,
+}
+
+impl ReloadingCert {
+    fn new(cert: &'static str, key: &'static str) -> Result<Self> {
+        let modified = Self::modified(cert, key)?;
+        let certified_key = Self::load(cert, key)?;
+        Ok(ReloadingCert {
+            cert,
+            key,
+            current: RwLock::new((modified, certified_key)),
+        })
+    }
+
+    fn modified(cert: &str, key: &str) -> Result<SystemTime> {
+        let modified = |path| -> Result<SystemTime> {
+            Ok(std::fs::metadata(path)
+                .with_context(|| format!("failed to read {path}"))?
+                .modified()?)
+        };
+        Ok(modified(cert)?.max(modified(key)?))
+    }
+
+    fn load(cert: &str, key: &str) -> Result<Arc<rustls::sign::CertifiedKey>> {
+        let certs = rustls_pemfile::certs(&mut std::io::BufReader::new(
+            std::fs::File::open(cert).with_context(|| format!("failed to open {cert}"))?,
+        ))
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("failed to parse {cert}"))?;
+        let key = rustls_pemfile::private_key(&mut std::io::BufReader::new(
+            std::fs::File::open(key).with_context(|| format!("failed to open {key}"))?,
+        ))
+        .with_context(|| format!("failed to parse {key}"))?
+        .with_context(|| format!("no private key found in {key}"))?;
+        let key = rustls::crypto::ring::sign::any_supported_type(&key)?;
+        Ok(Arc::new(rustls::sign::CertifiedKey::new(certs, key)))
+    }
+
+    fn reload_if_modified(&self) -> Result<()> {
+        let modified = Self::modified(self.cert, self.key)?;
+        if modified == self.current.read().unwrap().0 {
+            return Ok(());
+        }
+        let certified_key = Self::load(self.cert, self.key)?;
+        *self.current.write().unwrap() = (modified, certified_key);
+        println!("reloaded TLS certificate {}", self.cert);
+        Ok(())
+    }
+
+    async fn reload_periodically(self: Arc<Self>) {
+        loop {
+            tokio::time::sleep(CERT_RELOAD_INTERVAL).await;
+            // keep serving the previous certificate, e.g. while a renewal is only half written
+            if let Err(err) = self.reload_if_modified() {
+                eprintln!("failed to reload TLS certificate: {err:?}");
+            }
+        }
+    }
+}
+
+impl rustls::server::ResolvesServerCert for ReloadingCert {
+    fn resolve(
+        &self,
+        _client_hello: rustls::server::ClientHello<'_>,
+    ) -> Option<Arc<rustls::sign::CertifiedKey>> {
+        Some(self.current.read().unwrap().1.clone())
+    }
+}
@@ -0,0 +1,23 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Stand-ins for the devices and services the collector talks to, for tests and demos
+
+pub mod broker;
+pub mod ez1;
+pub mod household;
+pub mod inbox;
+pub mod open_meteo;
+pub mod opendtu;
+pub mod plant;
+pub mod shelly;
+pub mod sunspec;
+
+pub use crate::health::{Clock, system_clock};
+
+/// Serve `app` on an ephemeral port of the loopback interface in the background
+pub async fn spawn(app: axum::Router) -> std::io::Result<std::net::SocketAddr> {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(async move { axum::serve(listener, app).await });
+    Ok(address)
+}
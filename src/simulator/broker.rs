@@ -0,0 +1,120 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! A minimal MQTT 3.1.1 broker recording what gets published, without subscriptions
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const CONNECT: u8 = 1;
+const CONNACK: u8 = 2;
+const PUBLISH: u8 = 3;
+const PUBACK: u8 = 4;
+const PINGREQ: u8 = 12;
+const PINGRESP: u8 = 13;
+const DISCONNECT: u8 = 14;
+
+#[derive(Clone, Debug)]
+pub struct Message {
+    pub topic: String,
+    pub payload: String,
+    pub qos: u8,
+    pub retain: bool,
+}
+
+#[derive(Default)]
+pub struct Broker {
+    messages: Mutex<Vec<Message>>,
+}
+
+fn invalid(reason: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, reason)
+}
+
+/// Read a control packet, returns the first byte of its fixed header and the rest of it
+async fn read_packet(stream: &mut (impl AsyncRead + Unpin)) -> io::Result<(u8, Vec<u8>)> {
+    let header = stream.read_u8().await?;
+    let mut length = 0;
+    for shift in (0..4).map(|i| 7 * i) {
+        let byte = stream.read_u8().await?;
+        length |= ((byte & 0x7f) as usize) << shift;
+        if byte & 0x80 == 0 {
+            let mut body = vec![0; length];
+            stream.read_exact(&mut body).await?;
+            return Ok((header, body));
+        }
+    }
+    Err(invalid("remaining length exceeds 4 bytes"))
+}
+
+/// Decode a PUBLISH packet, returns the message and its packet id if it has to be acknowledged
+fn publish(header: u8, body: &[u8]) -> io::Result<(Message, Option<[u8; 2]>)> {
+    let qos = (header >> 1) & 0b11;
+    let (length, rest) = body
+        .split_first_chunk::<2>()
+        .ok_or_else(|| invalid("truncated topic"))?;
+    let (topic, rest) = rest
+        .split_at_checked(u16::from_be_bytes(*length) as usize)
+        .ok_or_else(|| invalid("truncated topic"))?;
+    let (id, payload) = match qos {
+        0 => (None, rest),
+        _ => {
+            let (id, payload) = rest
+                .split_first_chunk::<2>()
+                .ok_or_else(|| invalid("missing packet id"))?;
+            (Some(*id), payload)
+        }
+    };
+    let message = Message {
+        topic: String::from_utf8_lossy(topic).into_owned(),
+        payload: String::from_utf8_lossy(payload).into_owned(),
+        qos,
+        retain: header & 1 == 1,
+    };
+    Ok((message, id))
+}
+
+impl Broker {
+    pub fn messages(&self) -> Vec<Message> {
+        self.messages.lock().unwrap().clone()
+    }
+
+    /// Accept clients on `listener` until it fails
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tokio::spawn(self.clone().session(stream));
+        }
+    }
+
+    async fn session(self: Arc<Self>, mut stream: TcpStream) -> io::Result<()> {
+        loop {
+            let (header, body) = read_packet(&mut stream).await?;
+            match header >> 4 {
+                // session not present, accepted
+                CONNECT => stream.write_all(&[CONNACK << 4, 2, 0, 0]).await?,
+                PUBLISH => {
+                    let (message, id) = publish(header, &body)?;
+                    println!("received MQTT message {message:?}");
+                    self.messages.lock().unwrap().push(message);
+                    // QoS 2 isn't supported, the client will time out
+                    if let Some([high, low]) = id {
+                        stream.write_all(&[PUBACK << 4, 2, high, low]).await?;
+                    }
+                }
+                PINGREQ => stream.write_all(&[PINGRESP << 4, 0]).await?,
+                DISCONNECT => return Ok(()),
+                kind => return Err(invalid(&format!("unsupported packet type {kind}"))),
+            }
+        }
+    }
+}
+
+/// Serve `broker` on an ephemeral port of the loopback interface in the background
+pub async fn spawn(broker: Arc<Broker>) -> io::Result<std::net::SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(broker.serve(listener));
+    Ok(address)
+}
@@ -0,0 +1,266 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! The local API of an APsystems EZ1 microinverter reporting on a simulated [`Plant`]
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use axum::{
+    Json, Router,
+    extract::{Query, Request, State},
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{get, post},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use super::Clock;
+use super::plant::{Plant, RATED_POWER};
+
+pub const DEVICE_ID: &str = "E07000000001";
+pub const MIN_POWER: u32 = 30;
+
+/// Misbehavior of the simulated inverter, see `POST /simulator/faults`
+#[derive(Clone, Debug, Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct Faults {
+    /// never answer, like a device that has switched off, which happens every night
+    pub offline: bool,
+    /// delay every response by this many milliseconds
+    pub delay_ms: u64,
+    /// answer with truncated JSON
+    pub malformed: bool,
+    /// answer with `message: "FAILED"`
+    pub failed: bool,
+    /// raise all flags of getAlarm
+    pub alarm: bool,
+}
+
+pub struct Ez1 {
+    plant: Arc<Plant>,
+    faults: Mutex<Faults>,
+}
+
+impl Ez1 {
+    pub fn new(clock: Clock) -> Self {
+        Self::with_plant(Arc::new(Plant::new(clock)))
+    }
+
+    /// An EZ1 reporting on `plant`, which other simulated devices may observe as well
+    pub fn with_plant(plant: Arc<Plant>) -> Self {
+        Self {
+            plant,
+            faults: Mutex::new(Faults::default()),
+        }
+    }
+
+    pub fn plant(&self) -> &Arc<Plant> {
+        &self.plant
+    }
+
+    pub fn faults(&self) -> Faults {
+        self.faults.lock().unwrap().clone()
+    }
+
+    pub fn set_faults(&self, faults: Faults) {
+        *self.faults.lock().unwrap() = faults;
+    }
+
+    fn envelope(&self, data: Value) -> Json<Value> {
+        let message = match self.faults().failed {
+            true => "FAILED",
+            false => "SUCCESS",
+        };
+        Json(json!({ "data": data, "message": message, "deviceId": DEVICE_ID }))
+    }
+
+    /// The local API on the usual paths plus `/simulator/*` to control faults
+    pub fn router(self: Arc<Self>) -> Router {
+        Router::new()
+            .route("/getOutputData", get(output_data))
+            .route("/getMaxPower", get(max_power))
+            .route("/getOnOff", get(on_off))
+            .route("/getDeviceInfo", get(device_info))
+            .route("/getAlarm", get(alarm))
+            .route("/setMaxPower", get(set_max_power))
+            .route("/setOnOff", get(set_on_off))
+            .layer(middleware::from_fn_with_state(self.clone(), inject_faults))
+            .route("/simulator/faults", get(faults).post(set_faults))
+            .route("/simulator/resetCounters", post(reset_counters))
+            .with_state(self)
+    }
+}
+
+async fn inject_faults(State(ez1): State<Arc<Ez1>>, request: Request, next: Next) -> Response {
+    let faults = ez1.faults();
+    if faults.offline || !ez1.plant.awake() {
+        std::future::pending::<()>().await;
+    }
+    tokio::time::sleep(Duration::from_millis(faults.delay_ms)).await;
+    let response = next.run(request).await;
+    if !faults.malformed {
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(body) => (parts, body.slice(..body.len() / 2)).into_response(),
+        Err(_) => StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    }
+}
+
+async fn output_data(State(ez1): State<Arc<Ez1>>) -> Json<Value> {
+    let readings = ez1.plant.readings();
+    ez1.envelope(json!({
+        "p1": readings.power[0].round(),
+        "e1": readings.energy_today[0],
+        "te1": readings.energy_lifetime[0],
+        "p2": readings.power[1].round(),
+        "e2": readings.energy_today[1],
+        "te2": readings.energy_lifetime[1],
+    }))
+}
+
+async fn max_power(State(ez1): State<Arc<Ez1>>) -> Json<Value> {
+    let max_power = ez1.plant.max_power();
+    ez1.envelope(json!({ "maxPower": max_power.to_string() }))
+}
+
+fn status(on: bool) -> &'static str {
+    if on { "0" } else { "1" }
+}
+
+async fn on_off(State(ez1): State<Arc<Ez1>>) -> Json<Value> {
+    let on = ez1.plant.on();
+    ez1.envelope(json!({ "status": status(on) }))
+}
+
+async fn device_info(State(ez1): State<Arc<Ez1>>) -> Json<Value> {
+    ez1.envelope(json!({
+        "deviceId": DEVICE_ID,
+        "devVer": "EZ1 1.7.0",
+        "ssid": "powerlog",
+        "ipAddr": "127.0.0.1",
+        "minPower": MIN_POWER.to_string(),
+        "maxPower": RATED_POWER.to_string(),
+    }))
+}
+
+async fn alarm(State(ez1): State<Arc<Ez1>>) -> Json<Value> {
+    let flag = if ez1.faults().alarm { "1" } else { "0" };
+    ez1.envelope(json!({ "og": flag, "isce1": flag, "isce2": flag, "oe": flag }))
+}
+
+#[derive(Deserialize)]
+struct SetMaxPower {
+    p: u32,
+}
+
+async fn set_max_power(
+    State(ez1): State<Arc<Ez1>>,
+    Query(query): Query<SetMaxPower>,
+) -> Json<Value> {
+    if !(MIN_POWER..=RATED_POWER).contains(&query.p) {
+        return Json(json!({ "data": {}, "message": "FAILED", "deviceId": DEVICE_ID }));
+    }
+    ez1.plant.set_max_power(query.p);
+    ez1.envelope(json!({ "maxPower": query.p.to_string() }))
+}
+
+#[derive(Deserialize)]
+struct SetOnOff {
+    status: String,
+}
+
+async fn set_on_off(State(ez1): State<Arc<Ez1>>, Query(query): Query<SetOnOff>) -> Json<Value> {
+    let on = match query.status.as_str() {
+        "0" => true,
+        "1" => false,
+        _ => {
+            return Json(json!({ "data": {}, "message": "FAILED", "deviceId": DEVICE_ID }));
+        }
+    };
+    ez1.plant.set_on(on);
+    ez1.envelope(json!({ "status": status(on) }))
+}
+
+async fn faults(State(ez1): State<Arc<Ez1>>) -> Json<Faults> {
+    Json(ez1.faults())
+}
+
+async fn set_faults(State(ez1): State<Arc<Ez1>>, Json(faults): Json<Faults>) -> StatusCode {
+    ez1.set_faults(faults);
+    StatusCode::NO_CONTENT
+}
+
+async fn reset_counters(State(ez1): State<Arc<Ez1>>) -> StatusCode {
+    ez1.plant.reset_counters();
+    StatusCode::NO_CONTENT
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::error::PowerlogError;
+    use crate::simulator::ez1::{Ez1, Faults};
+    use crate::simulator::plant::tests::virtual_clock;
+
+    #[tokio::test]
+    async fn serve_local_api() {
+        let (now, clock) = virtual_clock();
+        *now.lock().unwrap() += time::Duration::hours(11);
+        let ez1 = Arc::new(Ez1::new(clock));
+        let address = crate::simulator::spawn(ez1.clone().router()).await.unwrap();
+        let url = format!("http://{address}");
+        let client = reqwest::Client::new();
+
+        *now.lock().unwrap() += time::Duration::hours(1);
+        let output_data = crate::inverter::output_data(&client, &url).await.unwrap();
+        assert_eq!(output_data.device_id, crate::simulator::ez1::DEVICE_ID);
+        assert!(output_data.channel(1).power > 0.0);
+        assert!(output_data.channel(2).energy_generation_lifetime > 0.0);
+        let max_power = crate::inverter::max_power(&client, &url).await.unwrap();
+        assert_eq!(max_power, 800.0);
+        let model = crate::inverter::device_info(&client, &url).await.unwrap();
+        assert_eq!(model.name, "EZ1");
+        assert_eq!(model.max_power, 800.0);
+        let on_off = crate::inverter::on_off(&client, &url).await.unwrap();
+        assert_eq!(on_off, crate::inverter::Status::On);
+
+        client
+            .get(format!("{url}/setMaxPower?p=100"))
+            .send()
+            .await
+            .unwrap();
+        let output_data = crate::inverter::output_data(&client, &url).await.unwrap();
+        assert!(output_data.channel(1).power <= 50.0);
+
+        ez1.set_faults(Faults {
+            failed: true,
+            ..Faults::default()
+        });
+        let err = crate::inverter::on_off(&client, &url).await.unwrap_err();
+        assert!(matches!(err, PowerlogError::BadResponse { .. }));
+
+        ez1.set_faults(Faults {
+            malformed: true,
+            ..Faults::default()
+        });
+        let err = crate::inverter::output_data(&client, &url)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, PowerlogError::BadResponse { .. }));
+
+        ez1.set_faults(Faults {
+            offline: true,
+            ..Faults::default()
+        });
+        let client = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_millis(100))
+            .build()
+            .unwrap();
+        let err = crate::inverter::on_off(&client, &url).await.unwrap_err();
+        assert!(matches!(err, PowerlogError::InverterOffline(_)));
+    }
+}
@@ -0,0 +1,129 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! A household consuming the output of a simulated [`Plant`], metered by a Tasmota SML
+//! reader and a Shelly 3EM
+use std::sync::{Arc, Mutex};
+
+use axum::{Json, Router, extract::State, routing::get};
+use serde_json::{Value, json};
+
+use super::plant::{self, Plant};
+use crate::meter::{Fields, Reading};
+
+/// Where the simulated Tasmota reports the counters, like a typical SML script
+pub const TASMOTA_FIELDS: Fields = Fields {
+    import: &["SML", "Total_in"],
+    export: &["SML", "Total_out"],
+    power: &["SML", "Power_curr"],
+};
+/// the consumption is integrated in steps of this length
+const STEP: time::Duration = time::Duration::minutes(1);
+const PHASES: usize = 3;
+
+/// Consumption in W, a base load plus breakfast, lunch and the evening
+pub fn load(time: time::OffsetDateTime) -> f64 {
+    let hour = time.hour() as f64 + time.minute() as f64 / 60.0;
+    let peak =
+        |center: f64, width: f64, power: f64| power * (-((hour - center) / width).powi(2)).exp();
+    150.0 + peak(7.5, 0.5, 600.0) + peak(12.5, 0.5, 1200.0) + peak(19.0, 1.5, 800.0)
+}
+
+struct Counters {
+    updated: time::OffsetDateTime,
+    /// in kWh
+    import_total: f64,
+    export_total: f64,
+}
+
+pub struct Household {
+    plant: Arc<Plant>,
+    counters: Mutex<Counters>,
+}
+
+impl Household {
+    pub fn new(plant: Arc<Plant>) -> Self {
+        let updated = plant.now();
+        Self {
+            plant,
+            counters: Mutex::new(Counters {
+                updated,
+                import_total: 1234.5,
+                export_total: 321.0,
+            }),
+        }
+    }
+
+    /// The grid balance in W at `time`, positive while importing
+    fn balance(&self, time: time::OffsetDateTime, on: bool, max_power: u32) -> f64 {
+        let production: f64 = match on {
+            true => plant::power(time, max_power).iter().sum(),
+            false => 0.0,
+        };
+        load(time) - production
+    }
+
+    /// The meter readings, integrated up to now
+    pub fn reading(&self) -> Reading {
+        let now = self.plant.now();
+        let (on, max_power) = (self.plant.on(), self.plant.max_power());
+        let mut counters = self.counters.lock().unwrap();
+        while counters.updated < now {
+            let step = STEP.min(now - counters.updated);
+            let next = counters.updated + step;
+            let energy =
+                self.balance(next, on, max_power) * step.as_seconds_f64() / 3600.0 / 1000.0;
+            match energy > 0.0 {
+                true => counters.import_total += energy,
+                false => counters.export_total -= energy,
+            }
+            counters.updated = next;
+        }
+        Reading {
+            import_total: counters.import_total,
+            export_total: counters.export_total,
+            power: self.balance(now, on, max_power),
+        }
+    }
+
+    /// `GET /cm?cmnd=Status 10` of Tasmota and `GET /status` of a Shelly 3EM
+    pub fn router(self: Arc<Self>) -> Router {
+        Router::new()
+            .route("/cm", get(tasmota))
+            .route("/status", get(shelly_3em))
+            .with_state(self)
+    }
+}
+
+async fn tasmota(State(household): State<Arc<Household>>) -> Json<Value> {
+    let reading = household.reading();
+    Json(json!({
+        "StatusSNS": {
+            "Time": household.plant.now().to_string(),
+            "SML": {
+                "Total_in": reading.import_total,
+                "Total_out": reading.export_total,
+                "Power_curr": reading.power.round(),
+            },
+        },
+    }))
+}
+
+/// The counters are spread evenly across the phases
+async fn shelly_3em(State(household): State<Arc<Household>>) -> Json<Value> {
+    let reading = household.reading();
+    let phase = |value: f64| value / PHASES as f64;
+    let emeter = json!({
+        "power": phase(reading.power),
+        "pf": 0.95,
+        "current": phase(reading.power).abs() / 230.0,
+        "voltage": 230.0,
+        "is_valid": true,
+        "total": phase(reading.import_total * 1000.0),
+        "total_returned": phase(reading.export_total * 1000.0),
+    });
+    Json(json!({
+        "emeters": vec![emeter; PHASES],
+        "total_power": reading.power,
+        "unixtime": household.plant.now().unix_timestamp(),
+    }))
+}
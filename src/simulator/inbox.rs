@@ -0,0 +1,153 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Records notifications sent to a webhook, ntfy, Gotify or an SMTP relay
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    Router,
+    extract::State,
+    http::{HeaderMap, Uri},
+    routing::post,
+};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+#[derive(Clone, Debug)]
+pub struct Request {
+    /// including the query
+    pub path: String,
+    /// with lowercase names
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        let name = name.to_ascii_lowercase();
+        self.headers
+            .iter()
+            .find(|(header, _)| *header == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Mail {
+    pub from: String,
+    pub to: Vec<String>,
+    /// headers and body with the dot-stuffing removed
+    pub data: String,
+}
+
+#[derive(Default)]
+pub struct Inbox {
+    requests: Mutex<Vec<Request>>,
+    mails: Mutex<Vec<Mail>>,
+}
+
+impl Inbox {
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn mails(&self) -> Vec<Mail> {
+        self.mails.lock().unwrap().clone()
+    }
+
+    /// Accepts any `POST` below `/notify`, e.g. `/notify/message` for Gotify
+    pub fn router(self: Arc<Self>) -> Router {
+        Router::new()
+            .route("/notify/*path", post(receive))
+            .with_state(self)
+    }
+
+    /// A minimal SMTP server accepting every mail
+    pub async fn serve_smtp(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            tokio::spawn(self.clone().smtp_session(stream));
+        }
+    }
+
+    async fn smtp_session(self: Arc<Self>, stream: TcpStream) -> std::io::Result<()> {
+        let (reader, mut writer) = stream.into_split();
+        let mut lines = BufReader::new(reader).lines();
+        let mut mail = Mail::default();
+        writer.write_all(b"220 powerlog simulator\r\n").await?;
+        while let Some(line) = lines.next_line().await? {
+            let command = line.get(..4).unwrap_or_default().to_ascii_uppercase();
+            let argument = |prefix: &str| {
+                line.get(prefix.len()..)
+                    .unwrap_or_default()
+                    .trim_matches(['<', '>'])
+                    .to_string()
+            };
+            let reply: &[u8] = match command.as_str() {
+                "EHLO" | "HELO" | "NOOP" => b"250 OK\r\n",
+                "RSET" => {
+                    mail = Mail::default();
+                    b"250 OK\r\n"
+                }
+                "MAIL" => {
+                    mail.from = argument("MAIL FROM:");
+                    b"250 OK\r\n"
+                }
+                "RCPT" => {
+                    mail.to.push(argument("RCPT TO:"));
+                    b"250 OK\r\n"
+                }
+                "DATA" => {
+                    writer
+                        .write_all(b"354 end data with <CR><LF>.<CR><LF>\r\n")
+                        .await?;
+                    while let Some(line) = lines.next_line().await? {
+                        if line == "." {
+                            break;
+                        }
+                        let line = line.strip_prefix('.').unwrap_or(&line);
+                        mail.data.push_str(line);
+                        mail.data.push('\n');
+                    }
+                    println!("received mail {mail:?}");
+                    self.mails.lock().unwrap().push(std::mem::take(&mut mail));
+                    b"250 OK\r\n"
+                }
+                "QUIT" => {
+                    writer.write_all(b"221 bye\r\n").await?;
+                    return Ok(());
+                }
+                _ => b"500 unknown command\r\n",
+            };
+            writer.write_all(reply).await?;
+        }
+        Ok(())
+    }
+}
+
+async fn receive(State(inbox): State<Arc<Inbox>>, uri: Uri, headers: HeaderMap, body: String) {
+    let request = Request {
+        path: uri
+            .path_and_query()
+            .map_or_else(|| uri.path().to_string(), |path| path.to_string()),
+        headers: headers
+            .iter()
+            .map(|(name, value)| {
+                (
+                    name.as_str().to_string(),
+                    String::from_utf8_lossy(value.as_bytes()).into_owned(),
+                )
+            })
+            .collect(),
+        body,
+    };
+    println!("received notification {request:?}");
+    inbox.requests.lock().unwrap().push(request);
+}
+
+/// Serve the SMTP server on an ephemeral port of the loopback interface in the background
+pub async fn spawn_smtp(inbox: Arc<Inbox>) -> std::io::Result<std::net::SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(inbox.serve_smtp(listener));
+    Ok(address)
+}
@@ -0,0 +1,77 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Replays recorded responses of the Open-Meteo DWD ICON API, see `fixtures/open-meteo`
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use axum::{
+    Router,
+    extract::State,
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+
+#[derive(Clone, Debug)]
+pub struct Reply {
+    pub status: StatusCode,
+    pub body: String,
+}
+
+impl Reply {
+    pub fn ok(body: impl Into<String>) -> Self {
+        Self {
+            status: StatusCode::OK,
+            body: body.into(),
+        }
+    }
+
+    /// A successful response with the contents of a fixture file
+    pub fn fixture(path: impl AsRef<std::path::Path>) -> std::io::Result<Self> {
+        Ok(Self::ok(std::fs::read_to_string(path)?))
+    }
+}
+
+/// Answers requests with the configured replies in turn
+pub struct OpenMeteo {
+    replies: Mutex<Vec<Reply>>,
+    requests: AtomicUsize,
+}
+
+impl OpenMeteo {
+    pub fn new(replies: Vec<Reply>) -> Self {
+        Self {
+            replies: Mutex::new(replies),
+            requests: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn set_replies(&self, replies: Vec<Reply>) {
+        *self.replies.lock().unwrap() = replies;
+    }
+
+    /// Number of requests served so far
+    pub fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+
+    pub fn router(self: Arc<Self>) -> Router {
+        Router::new()
+            .route("/v1/dwd-icon", get(dwd_icon))
+            .with_state(self)
+    }
+}
+
+async fn dwd_icon(State(open_meteo): State<Arc<OpenMeteo>>) -> Response {
+    let request = open_meteo.requests.fetch_add(1, Ordering::SeqCst);
+    let replies = open_meteo.replies.lock().unwrap();
+    let Some(reply) = replies.get(request % replies.len().max(1)) else {
+        return StatusCode::NOT_FOUND.into_response();
+    };
+    (
+        reply.status,
+        [(header::CONTENT_TYPE, "application/json; charset=utf-8")],
+        reply.body.clone(),
+    )
+        .into_response()
+}
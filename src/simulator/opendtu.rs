@@ -0,0 +1,98 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! The web API of an OpenDTU polling a Hoymiles inverter on a simulated [`Plant`]
+use std::sync::Arc;
+
+use axum::{Json, Router, extract::State, routing::get};
+use serde_json::{Value, json};
+
+use super::plant::{Plant, RATED_POWER};
+use crate::driver::opendtu::{DEVICE_INFO, LIMIT, LIVE_DATA};
+
+pub const SERIAL: &str = "116180000001";
+
+/// Round like OpenDTU does for the given number of `decimals`
+fn value(v: f64, unit: &str, decimals: i32) -> Value {
+    let factor = 10_f64.powi(decimals);
+    json!({ "v": (v * factor).round() / factor, "u": unit, "d": decimals })
+}
+
+pub struct OpenDtu {
+    plant: Arc<Plant>,
+}
+
+impl OpenDtu {
+    pub fn new(plant: Arc<Plant>) -> Self {
+        Self { plant }
+    }
+
+    pub fn router(self: Arc<Self>) -> Router {
+        Router::new()
+            .route(&format!("/{LIVE_DATA}"), get(live_data))
+            .route(&format!("/{LIMIT}"), get(limit))
+            .route(&format!("/{DEVICE_INFO}"), get(device_info))
+            .with_state(self)
+    }
+
+    fn limit_relative(&self) -> f64 {
+        self.plant.max_power() as f64 * 100.0 / RATED_POWER as f64
+    }
+}
+
+/// The DTU itself stays online at night, it just can't reach the inverter
+async fn live_data(State(dtu): State<Arc<OpenDtu>>) -> Json<Value> {
+    let readings = dtu.plant.readings();
+    let reachable = dtu.plant.awake();
+    let total: f64 = readings.power.iter().sum();
+    let dc: serde_json::Map<String, Value> = (0..2)
+        .map(|channel| {
+            let dc = json!({
+                "name": { "u": format!("panel {}", channel + 1) },
+                "Power": value(readings.power[channel], "W", 1),
+                "YieldDay": value(readings.energy_today[channel] * 1000.0, "Wh", 0),
+                "YieldTotal": value(readings.energy_lifetime[channel], "kWh", 3),
+            });
+            (channel.to_string(), dc)
+        })
+        .collect();
+    Json(json!({
+        "inverters": [{
+            "serial": SERIAL,
+            "name": "balcony",
+            "order": 0,
+            "data_age": 0,
+            "poll_enabled": true,
+            "reachable": reachable,
+            "producing": reachable && total > 0.0,
+            "limit_relative": dtu.limit_relative(),
+            "limit_absolute": dtu.plant.max_power(),
+            "AC": { "0": { "Power": value(total, "W", 1) } },
+            "DC": dc,
+        }],
+        "total": { "Power": value(total, "W", 1) },
+        "hints": { "time_sync": false, "radio_problem": false, "default_password": false },
+    }))
+}
+
+async fn limit(State(dtu): State<Arc<OpenDtu>>) -> Json<Value> {
+    Json(json!({
+        SERIAL: {
+            "limit_relative": dtu.limit_relative(),
+            "max_power": RATED_POWER,
+            "limit_set_status": "Ok",
+        }
+    }))
+}
+
+async fn device_info() -> Json<Value> {
+    Json(json!({
+        "valid_data": true,
+        "fw_bootloader_version": 1,
+        "fw_build_version": 10010,
+        "fw_build_datetime": "2020-06-05 11:16:00",
+        "hw_part_number": 269619201,
+        "hw_version": "01.00",
+        "hw_model_name": "HM-800",
+        "max_power": RATED_POWER,
+    }))
+}
@@ -0,0 +1,199 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Two panels on a microinverter with a synthetic power curve, shared by the simulated
+//! devices that report on it
+use std::f64::consts::FRAC_PI_4;
+use std::sync::Mutex;
+
+use super::Clock;
+
+/// the power the inverter is rated for in W
+pub const RATED_POWER: u32 = 800;
+/// peak power of each of the two simulated panels in W
+const PANEL_POWER: f64 = 410.0;
+/// the power curve is integrated in steps of this length
+const STEP: time::Duration = time::Duration::minutes(1);
+
+/// What the inverter reports at a given point in time, per channel
+#[derive(Debug)]
+pub struct Readings {
+    pub power: [f64; 2],
+    pub energy_today: [f64; 2],
+    pub energy_lifetime: [f64; 2],
+}
+
+struct State {
+    max_power: u32,
+    on: bool,
+    updated: time::OffsetDateTime,
+    energy_today: [f64; 2],
+    energy_lifetime: [f64; 2],
+}
+
+impl State {
+    /// Integrate the power curve up to `now`
+    fn advance(&mut self, now: time::OffsetDateTime) {
+        while self.updated < now {
+            let step = STEP.min(now - self.updated);
+            let next = self.updated + step;
+            // like the real devices, start counting the daily energy when waking up
+            if awake(next) && !awake(self.updated) {
+                self.energy_today = [0.0; 2];
+            }
+            if self.on {
+                let power = power(next, self.max_power);
+                for (channel, power) in power.into_iter().enumerate() {
+                    let energy = power * step.as_seconds_f64() / 3600.0 / 1000.0;
+                    self.energy_today[channel] += energy;
+                    self.energy_lifetime[channel] += energy;
+                }
+            }
+            self.updated = next;
+        }
+    }
+}
+
+/// Microinverters are powered by the panels and thus switched off while the sun is down
+pub fn awake(time: time::OffsetDateTime) -> bool {
+    crate::sun::position(time).altitude > 0.0
+}
+
+/// Share of the sky covered by slowly drifting clouds
+pub fn cloud_cover(time: time::OffsetDateTime) -> f64 {
+    let hours = time.unix_timestamp() as f64 / 3600.0;
+    (0.5 + 0.3 * (hours / 5.0).sin() + 0.2 * (hours * 1.7).sin()).clamp(0.0, 1.0)
+}
+
+/// Power of both channels in W, each limited to half of `max_power`
+pub fn power(time: time::OffsetDateTime, max_power: u32) -> [f64; 2] {
+    let sun = crate::sun::position(time);
+    if sun.altitude <= 0.0 {
+        return [0.0; 2];
+    }
+    let clouds = 1.0 - 0.75 * cloud_cover(time).powi(3);
+    // one panel faces south east, the other south west
+    [-FRAC_PI_4, FRAC_PI_4].map(|orientation| {
+        let incidence = 0.7 + 0.3 * (sun.azimuth - orientation).cos();
+        (PANEL_POWER * sun.altitude.sin() * incidence * clouds).min(max_power as f64 / 2.0)
+    })
+}
+
+pub struct Plant {
+    clock: Clock,
+    state: Mutex<State>,
+}
+
+impl Plant {
+    pub fn new(clock: Clock) -> Self {
+        let updated = clock();
+        Self {
+            clock,
+            state: Mutex::new(State {
+                max_power: RATED_POWER,
+                on: true,
+                updated,
+                energy_today: [0.0; 2],
+                energy_lifetime: [0.0; 2],
+            }),
+        }
+    }
+
+    pub fn now(&self) -> time::OffsetDateTime {
+        (self.clock)()
+    }
+
+    pub fn awake(&self) -> bool {
+        awake(self.now())
+    }
+
+    /// The state brought up to date with the clock
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        let now = self.now();
+        let mut state = self.state.lock().unwrap();
+        state.advance(now);
+        state
+    }
+
+    pub fn readings(&self) -> Readings {
+        let now = self.now();
+        let state = self.state();
+        Readings {
+            power: match state.on {
+                true => power(now, state.max_power),
+                false => [0.0; 2],
+            },
+            energy_today: state.energy_today,
+            energy_lifetime: state.energy_lifetime,
+        }
+    }
+
+    pub fn max_power(&self) -> u32 {
+        self.state().max_power
+    }
+
+    pub fn set_max_power(&self, max_power: u32) {
+        self.state().max_power = max_power;
+    }
+
+    pub fn on(&self) -> bool {
+        self.state().on
+    }
+
+    pub fn set_on(&self, on: bool) {
+        self.state().on = on;
+    }
+
+    /// Start counting from zero again, like the EZ1 firmware occasionally does
+    pub fn reset_counters(&self) {
+        let mut state = self.state();
+        state.energy_today = [0.0; 2];
+        state.energy_lifetime = [0.0; 2];
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::Plant;
+
+    /// 2024-06-21 00:00 UTC
+    const MIDNIGHT: i64 = 1718928000;
+
+    pub(crate) fn virtual_clock() -> (Arc<Mutex<time::OffsetDateTime>>, crate::simulator::Clock) {
+        let now = Arc::new(Mutex::new(
+            time::OffsetDateTime::from_unix_timestamp(MIDNIGHT).unwrap(),
+        ));
+        let clock = now.clone();
+        (now, Arc::new(move || *clock.lock().unwrap()))
+    }
+
+    #[test]
+    fn energy_counters_follow_the_sun() {
+        let (now, clock) = virtual_clock();
+        let plant = Plant::new(clock);
+
+        let mut lifetime = [0.0; 2];
+        let mut peak: f64 = 0.0;
+        for _ in 0..(24 * 12) {
+            *now.lock().unwrap() += time::Duration::minutes(5);
+            let readings = plant.readings();
+            assert!(readings.energy_lifetime[0] >= lifetime[0]);
+            assert!(readings.energy_lifetime[1] >= lifetime[1]);
+            assert!(readings.power.iter().all(|power| *power <= 400.0));
+            lifetime = readings.energy_lifetime;
+            peak = peak.max(readings.power[0]);
+        }
+        // it's dark at midnight, the daily counters restart when waking up
+        let readings = plant.readings();
+        assert_eq!(readings.power, [0.0; 2]);
+        assert_eq!(readings.energy_today, readings.energy_lifetime);
+        assert!(peak > 100.0);
+        assert!(lifetime.iter().all(|energy| (0.5..3.0).contains(energy)));
+        *now.lock().unwrap() += time::Duration::hours(6);
+        assert!(plant.readings().energy_today[0] < lifetime[0] / 10.0);
+
+        plant.reset_counters();
+        assert_eq!(plant.readings().energy_lifetime, [0.0; 2]);
+    }
+}
@@ -0,0 +1,69 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! A Shelly Plus 1PM switching and measuring the output of the inverter on a simulated
+//! [`Plant`]
+use std::sync::Arc;
+
+use axum::{Json, Router, extract::State, routing::get};
+use serde_json::{Value, json};
+
+use super::plant::Plant;
+use crate::driver::shelly::{DEVICE_INFO, STATUS};
+
+pub const MAC: &str = "A8032AB1C2D3";
+
+pub struct ShellyPlusPm {
+    plant: Arc<Plant>,
+}
+
+impl ShellyPlusPm {
+    pub fn new(plant: Arc<Plant>) -> Self {
+        Self { plant }
+    }
+
+    pub fn router(self: Arc<Self>) -> Router {
+        Router::new()
+            .route(&format!("/{STATUS}"), get(status))
+            .route(&format!("/{DEVICE_INFO}"), get(device_info))
+            .with_state(self)
+    }
+}
+
+async fn status(State(shelly): State<Arc<ShellyPlusPm>>) -> Json<Value> {
+    let readings = shelly.plant.readings();
+    let power: f64 = readings.power.iter().sum();
+    let energy: f64 = readings.energy_lifetime.iter().sum::<f64>() * 1000.0;
+    Json(json!({
+        "switch:0": {
+            "id": 0,
+            "source": "init",
+            "output": shelly.plant.on(),
+            "apower": (power * 10.0).round() / 10.0,
+            "voltage": 230.1,
+            "current": (power / 230.1 * 1000.0).round() / 1000.0,
+            "aenergy": { "total": (energy * 1000.0).round() / 1000.0 },
+            "ret_aenergy": { "total": 0.0 },
+            "temperature": { "tC": 41.2, "tF": 106.2 },
+        },
+        "sys": {
+            "mac": MAC,
+            "unixtime": shelly.plant.now().unix_timestamp(),
+        },
+    }))
+}
+
+async fn device_info() -> Json<Value> {
+    Json(json!({
+        "name": "balcony",
+        "id": format!("shellyplus1pm-{}", MAC.to_lowercase()),
+        "mac": MAC,
+        "slot": 0,
+        "model": "SNSW-001P16EU",
+        "gen": 2,
+        "fw_id": "20231107-164738/1.0.8-g8c7bb8d",
+        "ver": "1.0.8",
+        "app": "Plus1PM",
+        "auth_en": false,
+        "auth_domain": null,
+    }))
+}
@@ -0,0 +1,172 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! A SunSpec compatible inverter on a simulated [`Plant`], served via Modbus TCP
+use std::sync::Arc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use tokio::io::AsyncWriteExt;
+use tokio::net::{TcpListener, TcpStream};
+
+use super::plant::{Plant, RATED_POWER};
+use crate::driver::sunspec::*;
+
+pub const SERIAL: &str = "SIM000000001";
+
+/// `value` padded with NULs to `length` registers
+fn string(value: &str, length: usize) -> Vec<u16> {
+    let mut bytes = value.as_bytes().to_vec();
+    bytes.resize(2 * length, 0);
+    bytes
+        .chunks(2)
+        .map(|word| u16::from_be_bytes([word[0], word[1]]))
+        .collect()
+}
+
+fn acc32(value: f64) -> [u16; 2] {
+    let value = value.round() as u32;
+    [(value >> 16) as u16, value as u16]
+}
+
+fn model(id: u16, registers: Vec<u16>) -> Vec<u16> {
+    [vec![id, registers.len() as u16], registers].concat()
+}
+
+pub struct SunSpec {
+    plant: Arc<Plant>,
+    connections: AtomicUsize,
+}
+
+impl SunSpec {
+    pub fn new(plant: Arc<Plant>) -> Self {
+        Self {
+            plant,
+            connections: AtomicUsize::new(0),
+        }
+    }
+
+    /// The number of clients that connected so far
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::Relaxed)
+    }
+
+    /// The SunSpec map starting at [`BASE_ADDRESS`], with energies in Wh
+    pub fn registers(&self) -> Vec<u16> {
+        let readings = self.plant.readings();
+        let on = self.plant.on();
+        let state = match (self.plant.awake(), on) {
+            (false, _) => STATE_SLEEPING,
+            (true, false) => STATE_OFF,
+            (true, true) => STATE_MPPT,
+        };
+        let power: f64 = readings.power.iter().sum();
+        let energy: f64 = readings.energy_lifetime.iter().sum::<f64>() * 1000.0;
+
+        let common = [
+            string("powerlog", 16),
+            string("SIM-800", 16),
+            string("", 8),
+            string("1.0.0", 8),
+            string(SERIAL, 16),
+            vec![1, 0x8000],
+        ]
+        .concat();
+
+        let mut inverter = vec![0; 50];
+        inverter[12] = power.round() as i16 as u16;
+        inverter[22..24].copy_from_slice(&acc32(energy));
+        inverter[36] = state;
+
+        let mut nameplate = vec![0; 26];
+        nameplate[0] = 4;
+        nameplate[1] = RATED_POWER as u16;
+
+        // the limit in percent with one decimal
+        let mut controls = vec![0; 24];
+        controls[2] = on as u16;
+        controls[3] = (self.plant.max_power() * 1000 / RATED_POWER) as u16;
+        controls[7] = (self.plant.max_power() < RATED_POWER) as u16;
+        controls[21] = -1_i16 as u16;
+
+        let mut mppt = vec![0; 8];
+        mppt[6] = 2;
+        for channel in 0..2 {
+            let mut module = vec![0; 20];
+            module[0] = channel as u16 + 1;
+            module[1..9].copy_from_slice(&string(&format!("panel {}", channel + 1), 8));
+            module[11] = readings.power[channel].round() as u16;
+            module[12..14].copy_from_slice(&acc32(readings.energy_lifetime[channel] * 1000.0));
+            mppt.extend(module);
+        }
+
+        [
+            MARKER.to_vec(),
+            model(COMMON, common),
+            model(INVERTERS[2], inverter),
+            model(NAMEPLATE, nameplate),
+            model(CONTROLS, controls),
+            model(MPPT, mppt),
+            vec![END, 0],
+        ]
+        .concat()
+    }
+
+    /// Answer read requests on `listener` until it fails. Like many devices, only one client
+    /// is served at a time, the next one waits until it disconnects.
+    pub async fn serve(self: Arc<Self>, listener: TcpListener) -> std::io::Result<()> {
+        loop {
+            let (stream, _) = listener.accept().await?;
+            self.connections.fetch_add(1, Ordering::Relaxed);
+            // ends with the client disconnecting
+            let _ = self.clone().connection(stream).await;
+        }
+    }
+
+    async fn connection(self: Arc<Self>, mut stream: TcpStream) -> std::io::Result<()> {
+        loop {
+            let (transaction, unit_id, request) = read_frame(&mut stream).await?;
+            let response = self.respond(&request);
+            stream
+                .write_all(&frame(transaction, unit_id, &response))
+                .await?;
+        }
+    }
+
+    fn respond(&self, request: &[u8]) -> Vec<u8> {
+        const ILLEGAL_FUNCTION: u8 = 1;
+        const ILLEGAL_ADDRESS: u8 = 2;
+
+        let &[
+            READ_HOLDING_REGISTERS,
+            address_high,
+            address_low,
+            count_high,
+            count_low,
+        ] = request
+        else {
+            return vec![
+                request.first().copied().unwrap_or(0) | 0x80,
+                ILLEGAL_FUNCTION,
+            ];
+        };
+        let address = u16::from_be_bytes([address_high, address_low]);
+        let count = u16::from_be_bytes([count_high, count_low]);
+        let registers = self.registers();
+        let start = address.wrapping_sub(BASE_ADDRESS) as usize;
+        let registers = match registers.get(start..start + count as usize) {
+            Some(registers) if (1..=MAX_READ).contains(&count) => registers,
+            _ => return vec![READ_HOLDING_REGISTERS | 0x80, ILLEGAL_ADDRESS],
+        };
+        let mut response = vec![READ_HOLDING_REGISTERS, 2 * count as u8];
+        response.extend(registers.iter().flat_map(|register| register.to_be_bytes()));
+        response
+    }
+}
+
+/// Serve the Modbus TCP server of `sunspec` on an ephemeral port of the loopback
+/// interface in the background
+pub async fn spawn(sunspec: Arc<SunSpec>) -> std::io::Result<std::net::SocketAddr> {
+    let listener = TcpListener::bind("127.0.0.1:0").await?;
+    let address = listener.local_addr()?;
+    tokio::spawn(sunspec.serve(listener));
+    Ok(address)
+}
@@ -0,0 +1,132 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Destinations a [`Sample`] gets written to by the collector
+use anyhow::Result;
+use futures::future::BoxFuture;
+
+use crate::sample::Sample;
+
+pub trait Sink: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn write<'a>(&'a self, sample: &'a Sample) -> BoxFuture<'a, Result<()>>;
+}
+
+pub struct Sqlite {
+    pub db: sea_orm::DatabaseConnection,
+}
+
+impl Sink for Sqlite {
+    fn name(&self) -> &'static str {
+        "sqlite"
+    }
+
+    fn write<'a>(&'a self, sample: &'a Sample) -> BoxFuture<'a, Result<()>> {
+        Box::pin(async { Ok(crate::db::insert(&self.db, sample).await?) })
+    }
+}
+
+pub struct Mqtt {
+    pub broker: (&'static str, u16),
+}
+
+impl Sink for Mqtt {
+    fn name(&self) -> &'static str {
+        "mqtt"
+    }
+
+    fn write<'a>(&'a self, sample: &'a Sample) -> BoxFuture<'a, Result<()>> {
+        Box::pin(crate::mqtt::publish(self.broker, sample))
+    }
+}
+
+pub struct Influx {
+    pub output: &'static crate::influx::Output,
+    pub client: reqwest::Client,
+}
+
+impl Sink for Influx {
+    fn name(&self) -> &'static str {
+        "influx"
+    }
+
+    fn write<'a>(&'a self, sample: &'a Sample) -> BoxFuture<'a, Result<()>> {
+        Box::pin(crate::influx::write(self.output, &self.client, sample))
+    }
+}
+
+/// The SQLite database plus all sinks enabled in [`crate::config`]
+pub fn configured(db: sea_orm::DatabaseConnection, client: reqwest::Client) -> Vec<Box<dyn Sink>> {
+    let mut sinks: Vec<Box<dyn Sink>> = vec![Box::new(Sqlite { db })];
+    if let Some(broker) = crate::config::MQTT_BROKER {
+        sinks.push(Box::new(Mqtt { broker }));
+    }
+    if let Some(output) = &crate::config::INFLUX_OUTPUT {
+        sinks.push(Box::new(Influx { output, client }));
+    }
+    sinks
+}
+
+/// Write `sample` to all `sinks` concurrently. A failing sink does not affect the others,
+/// its error is returned together with the name of the sink instead.
+pub async fn fan_out(
+    sinks: &[Box<dyn Sink>],
+    sample: &Sample,
+) -> Vec<(&'static str, anyhow::Error)> {
+    let results = futures::future::join_all(sinks.iter().map(|sink| sink.write(sample))).await;
+    sinks
+        .iter()
+        .zip(results)
+        .filter_map(|(sink, result)| result.err().map(|err| (sink.name(), err)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::{Result, bail};
+    use futures::future::BoxFuture;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::sample::Sample;
+    use crate::sink::Sink;
+
+    struct Failing;
+
+    impl Sink for Failing {
+        fn name(&self) -> &'static str {
+            "failing"
+        }
+
+        fn write<'a>(&'a self, _sample: &'a Sample) -> BoxFuture<'a, Result<()>> {
+            Box::pin(async { bail!("broken") })
+        }
+    }
+
+    struct Counting(Arc<AtomicUsize>);
+
+    impl Sink for Counting {
+        fn name(&self) -> &'static str {
+            "counting"
+        }
+
+        fn write<'a>(&'a self, _sample: &'a Sample) -> BoxFuture<'a, Result<()>> {
+            self.0.fetch_add(1, Ordering::SeqCst);
+            Box::pin(async { Ok(()) })
+        }
+    }
+
+    #[tokio::test]
+    async fn fan_out_isolates_errors() {
+        let writes = Arc::new(AtomicUsize::new(0));
+        let sinks: Vec<Box<dyn Sink>> = vec![
+            Box::new(Failing),
+            Box::new(Counting(writes.clone())),
+            Box::new(Failing),
+        ];
+        let errors = crate::sink::fan_out(&sinks, &crate::sample::tests::sample()).await;
+        assert_eq!(writes.load(Ordering::SeqCst), 1);
+        assert_eq!(errors.len(), 2);
+        assert!(errors.iter().all(|(name, _)| *name == "failing"));
+    }
+}
@@ -0,0 +1,9 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+pub fn position(time: time::OffsetDateTime) -> sun::Position {
+    sun::pos(
+        time.unix_timestamp() * 1000,
+        crate::config::LATITUDE,
+        crate::config::LONGITUDE,
+    )
+}
@@ -0,0 +1,59 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use crate::error::{PowerlogError, Result};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+struct CurrentWeatherResponse {
+    current: CurrentWeather,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CurrentWeather {
+    pub cloud_cover: f32,
+
+    pub terrestrial_radiation_instant: f32,
+    pub direct_radiation_instant: f32,
+    pub diffuse_radiation_instant: f32,
+    pub shortwave_radiation_instant: f32,
+    pub direct_normal_irradiance_instant: f32,
+    pub global_tilted_irradiance_instant: f32,
+}
+
+pub async fn query(client: &reqwest::Client, url: &str) -> Result<CurrentWeather> {
+    let weather_api_url = format!(
+        "{url}/v1/dwd-icon?latitude={}&longitude={}&current=cloud_cover,shortwave_radiation_instant,direct_radiation_instant,diffuse_radiation_instant,direct_normal_irradiance_instant,global_tilted_irradiance_instant,terrestrial_radiation_instant&tilt=90",
+        crate::config::LATITUDE,
+        crate::config::LONGITUDE
+    );
+    let response = client
+        .get(weather_api_url)
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(PowerlogError::WeatherUnavailable)?
+        .json::<CurrentWeatherResponse>()
+        .await
+        .map_err(PowerlogError::WeatherUnavailable)?;
+    Ok(response.current)
+}
+
+#[cfg(test)]
+mod tests {
+    #[test]
+    fn parse_cloud_cover() {
+        let response = r#"
+{"latitude":52.52,"longitude":13.419998,"generationtime_ms":0.05900859832763672,"utc_offset_seconds":0,"timezone":"GMT","timezone_abbreviation":"GMT","elevation":38.0,"current_units":{"time":"iso8601","interval":"seconds","cloud_cover":"%","shortwave_radiation_instant":"W/m²","direct_radiation_instant":"W/m²","diffuse_radiation_instant":"W/m²","direct_normal_irradiance_instant":"W/m²","global_tilted_irradiance_instant":"W/m²","terrestrial_radiation_instant":"W/m²"},"current":{"time":"2024-04-16T09:30","interval":900,"cloud_cover":100,"shortwave_radiation_instant":303.7,"direct_radiation_instant":123.5,"diffuse_radiation_instant":180.2,"direct_normal_irradiance_instant":179.1,"global_tilted_irradiance_instant":227.9,"terrestrial_radiation_instant":937.0}}
+            "#;
+
+        let weather: crate::weather::CurrentWeatherResponse =
+            serde_json::from_str(response).unwrap();
+        assert_eq!(weather.current.cloud_cover, 100.0);
+        assert_eq!(weather.current.shortwave_radiation_instant, 303.7);
+        assert_eq!(weather.current.direct_radiation_instant, 123.5);
+        assert_eq!(weather.current.diffuse_radiation_instant, 180.2);
+        assert_eq!(weather.current.direct_normal_irradiance_instant, 179.1);
+        assert_eq!(weather.current.global_tilted_irradiance_instant, 227.9);
+        assert_eq!(weather.current.terrestrial_radiation_instant, 937.0);
+    }
+}
@@ -0,0 +1,322 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Alert rules checked by the collector against the simulated EZ1 and every notifier against the
+//! stand-ins of the simulator
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use powerlog::alert::{Condition, Rule};
+use powerlog::collector::{Collector, Outcome};
+use powerlog::driver::Ez1 as Ez1Driver;
+use powerlog::notify::{Gotify, Notification, Notifier, Ntfy, Smtp, Status, Webhook};
+use powerlog::retry::Policy;
+use powerlog::simulator::{
+    self,
+    ez1::{Ez1, Faults},
+    inbox,
+    inbox::Inbox,
+    open_meteo,
+};
+
+/// 2024-06-21 12:00 UTC
+const NOON: i64 = 1718971200;
+
+const NO_RETRY: Policy = Policy {
+    attempts: 1,
+    initial_backoff: Duration::ZERO,
+    max_backoff: Duration::ZERO,
+};
+
+const RULES: &[Rule] = &[
+    Rule {
+        condition: Condition::OffDuringDaylight,
+        cooldown: Duration::from_secs(60 * 60),
+    },
+    Rule {
+        condition: Condition::LifetimeReset,
+        cooldown: Duration::from_secs(60 * 60),
+    },
+];
+
+/// notify on every run the reset is seen
+const RESET_RULES: &[Rule] = &[Rule {
+    condition: Condition::LifetimeReset,
+    cooldown: Duration::ZERO,
+}];
+
+#[tokio::test]
+async fn notify_changes_once_per_cooldown() {
+    let db = powerlog::db::connect("sqlite::memory:").await.unwrap();
+    let noon = time::OffsetDateTime::from_unix_timestamp(NOON).unwrap();
+    let now = Arc::new(Mutex::new(noon));
+    let clock: simulator::Clock = {
+        let now = now.clone();
+        Arc::new(move || *now.lock().unwrap())
+    };
+
+    let ez1 = Arc::new(Ez1::new(clock));
+    let open_meteo = Arc::new(open_meteo::OpenMeteo::new(vec![
+        open_meteo::Reply::fixture("fixtures/open-meteo/dwd-icon-clear.json").unwrap(),
+    ]));
+    let inbox = Arc::new(Inbox::default());
+    let devices = simulator::spawn(
+        ez1.clone()
+            .router()
+            .merge(open_meteo.router())
+            .merge(inbox.clone().router()),
+    )
+    .await
+    .unwrap();
+    let devices = format!("http://{devices}");
+
+    let collector = Collector {
+        db: db.clone(),
+        inverter: Box::new(Ez1Driver {
+            client: reqwest::Client::new(),
+            url: devices.clone(),
+            lifetime_offsets: &[],
+        }),
+        inverter_retry: NO_RETRY,
+        inverter_precheck_retry: NO_RETRY,
+        client: reqwest::Client::new(),
+        weather_url: devices.clone(),
+        weather_retry: NO_RETRY,
+        meter: None,
+        meter_retry: NO_RETRY,
+        alert_rules: RULES,
+        notifiers: vec![Box::new(Webhook {
+            client: reqwest::Client::new(),
+            url: format!("{devices}/notify/webhook"),
+        })],
+    };
+
+    // (switched on, reset the counters) before each run
+    let steps = [
+        (true, false),
+        (false, false),
+        // still off, but notified already
+        (false, false),
+        (true, false),
+        // off again within the cooldown, which also keeps the resolution quiet
+        (false, false),
+        (true, false),
+        (true, true),
+        (true, false),
+    ];
+    let mut time = noon;
+    for (on, reset) in steps {
+        time += powerlog::config::COLLECT_INTERVAL;
+        *now.lock().unwrap() = time;
+        ez1.plant().set_on(on);
+        if reset {
+            ez1.plant().reset_counters();
+        }
+        assert_eq!(collector.run(time).await.unwrap(), Outcome::Complete);
+    }
+
+    let received: Vec<(String, String)> = inbox
+        .requests()
+        .iter()
+        .map(|request| {
+            let notification: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+            (
+                notification["rule"].as_str().unwrap().to_string(),
+                notification["status"].as_str().unwrap().to_string(),
+            )
+        })
+        .collect();
+    let expected = [
+        ("off-during-daylight", "firing"),
+        ("off-during-daylight", "resolved"),
+        ("lifetime-reset", "firing"),
+        ("lifetime-reset", "resolved"),
+    ];
+    assert_eq!(
+        received,
+        expected.map(|(rule, status)| (rule.to_string(), status.to_string()))
+    );
+
+    let alerts = powerlog::db::select_alerts(&db).await.unwrap();
+    assert_eq!(alerts.len(), 2);
+    assert!(alerts.iter().all(|alert| !alert.active));
+    let off = alerts
+        .iter()
+        .find(|alert| alert.rule == "off-during-daylight")
+        .unwrap();
+    assert_eq!(
+        off.last_notified,
+        Some(noon + 2 * powerlog::config::COLLECT_INTERVAL)
+    );
+    assert_eq!(
+        off.since,
+        Some(noon + 5 * powerlog::config::COLLECT_INTERVAL)
+    );
+}
+
+#[tokio::test]
+async fn offline_run_after_reset() {
+    let db = powerlog::db::connect("sqlite::memory:").await.unwrap();
+    let noon = time::OffsetDateTime::from_unix_timestamp(NOON).unwrap();
+    let now = Arc::new(Mutex::new(noon));
+    let clock: simulator::Clock = {
+        let now = now.clone();
+        Arc::new(move || *now.lock().unwrap())
+    };
+
+    let ez1 = Arc::new(Ez1::new(clock));
+    let open_meteo = Arc::new(open_meteo::OpenMeteo::new(vec![
+        open_meteo::Reply::fixture("fixtures/open-meteo/dwd-icon-clear.json").unwrap(),
+    ]));
+    let inbox = Arc::new(Inbox::default());
+    let devices = simulator::spawn(
+        ez1.clone()
+            .router()
+            .merge(open_meteo.router())
+            .merge(inbox.clone().router()),
+    )
+    .await
+    .unwrap();
+    let devices = format!("http://{devices}");
+
+    let collector = Collector {
+        db: db.clone(),
+        inverter: Box::new(Ez1Driver {
+            client: reqwest::Client::builder()
+                .timeout(Duration::from_millis(50))
+                .build()
+                .unwrap(),
+            url: devices.clone(),
+            lifetime_offsets: &[],
+        }),
+        inverter_retry: NO_RETRY,
+        inverter_precheck_retry: NO_RETRY,
+        client: reqwest::Client::new(),
+        weather_url: devices.clone(),
+        weather_retry: NO_RETRY,
+        meter: None,
+        meter_retry: NO_RETRY,
+        alert_rules: RESET_RULES,
+        notifiers: vec![Box::new(Webhook {
+            client: reqwest::Client::new(),
+            url: format!("{devices}/notify/webhook"),
+        })],
+    };
+
+    let mut time = noon;
+    for (offline, reset) in [(false, false), (false, true), (true, false)] {
+        time += powerlog::config::COLLECT_INTERVAL;
+        *now.lock().unwrap() = time;
+        ez1.set_faults(Faults {
+            offline,
+            ..Faults::default()
+        });
+        if reset {
+            ez1.plant().reset_counters();
+        }
+        let expected = match offline {
+            true => Outcome::Offline,
+            false => Outcome::Complete,
+        };
+        assert_eq!(collector.run(time).await.unwrap(), expected);
+    }
+
+    // the offline run has no readings to compare, the reset of the previous one isn't repeated
+    let received: Vec<String> = inbox
+        .requests()
+        .iter()
+        .map(|request| {
+            let notification: serde_json::Value = serde_json::from_str(&request.body).unwrap();
+            notification["status"].as_str().unwrap().to_string()
+        })
+        .collect();
+    assert_eq!(received, ["firing", "resolved"]);
+}
+
+#[tokio::test]
+async fn notifiers_reach_stand_ins() {
+    let inbox = Arc::new(Inbox::default());
+    let http = simulator::spawn(inbox.clone().router()).await.unwrap();
+    let http = format!("http://{http}/notify");
+    let smtp = inbox::spawn_smtp(inbox.clone()).await.unwrap();
+
+    let client = reqwest::Client::new();
+    let notifiers: Vec<Box<dyn Notifier>> = vec![
+        Box::new(Webhook {
+            client: client.clone(),
+            url: format!("{http}/webhook"),
+        }),
+        Box::new(Ntfy {
+            client: client.clone(),
+            url: format!("{http}/powerlog"),
+            token: Some("tk_secret".to_string()),
+        }),
+        Box::new(Gotify {
+            client,
+            url: http.clone(),
+            token: "AbCdEf".to_string(),
+        }),
+        Box::new(Smtp {
+            address: smtp.to_string(),
+            from: "powerlog@example.org".to_string(),
+            to: vec!["me@example.org".to_string(), "you@example.org".to_string()],
+            timeout: Duration::from_secs(5),
+        }),
+    ];
+    let notification = Notification::alert(
+        "channel-zero",
+        Status::Firing,
+        "channel 2 reads 0 W while channel 1 produces 180 W\n.dotted line".to_string(),
+        time::OffsetDateTime::from_unix_timestamp(NOON).unwrap(),
+    );
+    let errors = powerlog::notify::send_all(&notifiers, &notification).await;
+    assert!(errors.is_empty(), "{errors:?}");
+
+    let mut requests = inbox.requests();
+    requests.sort_by(|a, b| a.path.cmp(&b.path));
+    assert_eq!(requests.len(), 3);
+
+    let gotify = &requests[0];
+    assert_eq!(gotify.path, "/notify/message");
+    assert_eq!(gotify.header("X-Gotify-Key"), Some("AbCdEf"));
+    let message: serde_json::Value = serde_json::from_str(&gotify.body).unwrap();
+    assert_eq!(message["title"], "powerlog: channel-zero firing");
+    assert_eq!(message["priority"], 8);
+
+    let ntfy = &requests[1];
+    assert_eq!(ntfy.path, "/notify/powerlog");
+    assert_eq!(ntfy.header("Title"), Some("powerlog: channel-zero firing"));
+    assert_eq!(ntfy.header("Priority"), Some("high"));
+    assert_eq!(ntfy.header("Authorization"), Some("Bearer tk_secret"));
+    assert_eq!(ntfy.body, notification.message);
+
+    let webhook = &requests[2];
+    assert_eq!(webhook.path, "/notify/webhook");
+    let json: serde_json::Value = serde_json::from_str(&webhook.body).unwrap();
+    assert_eq!(json["rule"], "channel-zero");
+    assert_eq!(json["status"], "firing");
+    assert_eq!(json["message"], notification.message);
+
+    let mails = inbox.mails();
+    assert_eq!(mails.len(), 1);
+    assert_eq!(mails[0].from, "powerlog@example.org");
+    assert_eq!(mails[0].to, ["me@example.org", "you@example.org"]);
+    assert!(
+        mails[0]
+            .data
+            .contains("Subject: powerlog: channel-zero firing\n")
+    );
+    assert!(
+        mails[0]
+            .data
+            .ends_with("\n\nchannel 2 reads 0 W while channel 1 produces 180 W\n.dotted line\n")
+    );
+
+    let unreachable = Smtp {
+        address: "127.0.0.1:1".to_string(),
+        from: "powerlog@example.org".to_string(),
+        to: vec!["me@example.org".to_string()],
+        timeout: Duration::from_secs(5),
+    };
+    assert!(unreachable.send(&notification).await.is_err());
+}
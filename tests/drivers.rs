@@ -0,0 +1,319 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Read the same simulated panels through every supported inverter driver and meter
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use powerlog::collector::{Collector, Outcome};
+use powerlog::driver::{Config, InverterDriver, LifetimeOffset};
+use powerlog::error::PowerlogError;
+use powerlog::inverter::Status;
+use powerlog::meter::{self, Meter};
+use powerlog::retry::Policy;
+use powerlog::simulator::{
+    self, ez1, household::Household, open_meteo, opendtu, plant::Plant, shelly, sunspec,
+};
+
+/// 2024-06-21 12:00 UTC
+const NOON: i64 = 1718971200;
+
+const NO_RETRY: Policy = Policy {
+    attempts: 1,
+    initial_backoff: Duration::ZERO,
+    max_backoff: Duration::ZERO,
+};
+
+struct Devices {
+    now: Arc<Mutex<time::OffsetDateTime>>,
+    plant: Arc<Plant>,
+    sunspec: Arc<sunspec::SunSpec>,
+    http: String,
+    modbus: String,
+}
+
+async fn spawn() -> Devices {
+    let now = Arc::new(Mutex::new(
+        time::OffsetDateTime::from_unix_timestamp(NOON).unwrap(),
+    ));
+    let clock: simulator::Clock = {
+        let now = now.clone();
+        Arc::new(move || *now.lock().unwrap())
+    };
+    let plant = Arc::new(Plant::new(clock));
+    // let the counters run for a while
+    *now.lock().unwrap() += time::Duration::hours(1);
+
+    let opendtu = Arc::new(opendtu::OpenDtu::new(plant.clone()));
+    let shelly = Arc::new(shelly::ShellyPlusPm::new(plant.clone()));
+    let http = simulator::spawn(opendtu.router().merge(shelly.router()))
+        .await
+        .unwrap();
+    let sunspec = Arc::new(sunspec::SunSpec::new(plant.clone()));
+    let modbus = sunspec::spawn(sunspec.clone()).await.unwrap();
+    Devices {
+        now,
+        plant,
+        sunspec,
+        http: format!("http://{http}"),
+        modbus: modbus.to_string(),
+    }
+}
+
+fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+    assert!(
+        (actual - expected).abs() <= tolerance,
+        "{actual} differs from {expected}"
+    );
+}
+
+fn collector(
+    db: &sea_orm::DatabaseConnection,
+    inverter: Box<dyn InverterDriver>,
+    weather_url: &str,
+) -> Collector {
+    Collector {
+        db: db.clone(),
+        inverter,
+        inverter_retry: NO_RETRY,
+        inverter_precheck_retry: NO_RETRY,
+        client: reqwest::Client::new(),
+        weather_url: weather_url.to_string(),
+        weather_retry: NO_RETRY,
+        meter: None,
+        meter_retry: NO_RETRY,
+        alert_rules: &[],
+        notifiers: Vec::new(),
+    }
+}
+
+fn drivers(devices: &Devices) -> [Box<dyn InverterDriver>; 3] {
+    let client = reqwest::Client::builder()
+        .timeout(std::time::Duration::from_millis(500))
+        .build()
+        .unwrap();
+    [
+        Config::OpenDtu {
+            url: "",
+            serial: opendtu::SERIAL,
+        }
+        .driver(client.clone(), Some(devices.http.clone())),
+        Config::ShellyPlusPm {
+            url: "",
+            id: 0,
+            returned: false,
+            max_power: 800.0,
+        }
+        .driver(client, Some(devices.http.clone())),
+        Config::SunSpec {
+            address: "",
+            unit_id: 1,
+        }
+        .driver(reqwest::Client::new(), Some(devices.modbus.clone())),
+    ]
+}
+
+#[tokio::test]
+async fn read_all_drivers() {
+    let devices = spawn().await;
+    let readings = devices.plant.readings();
+    let power: f64 = readings.power.iter().sum();
+    let lifetime: f64 = readings.energy_lifetime.iter().sum();
+    assert!(power > 100.0);
+
+    for driver in drivers(&devices) {
+        let name = driver.name();
+        let output_data = driver.output_data().await.unwrap();
+        let channels = &output_data.channels;
+        match name {
+            // a meter only sees the sum of both panels
+            "shelly" => {
+                assert_eq!(output_data.device_id, shelly::MAC);
+                assert_eq!(channels.len(), 1);
+                assert_close(channels[0].power, power, 0.1);
+                assert_close(channels[0].energy_generation_lifetime, lifetime, 1e-6);
+            }
+            _ => {
+                assert_eq!(channels.len(), 2, "{name}");
+                for (channel, reading) in channels.iter().zip(readings.power) {
+                    assert_close(channel.power, reading, 1.0);
+                }
+                for (channel, reading) in channels.iter().zip(readings.energy_lifetime) {
+                    assert_close(channel.energy_generation_lifetime, reading, 1e-3);
+                }
+            }
+        }
+        if name == "opendtu" {
+            assert_eq!(output_data.device_id, opendtu::SERIAL);
+            assert_close(
+                channels[0].energy_generation_startup,
+                readings.energy_today[0],
+                1e-3,
+            );
+        }
+
+        let model = driver.device_info().await.unwrap();
+        assert_eq!(model.device_id, output_data.device_id, "{name}");
+        assert_eq!(model.max_power, 800.0, "{name}");
+        assert_eq!(driver.max_power().await.unwrap(), 800.0, "{name}");
+        assert_eq!(driver.on_off().await.unwrap(), Status::On, "{name}");
+    }
+
+    devices.plant.set_max_power(600);
+    for driver in &drivers(&devices)[..] {
+        let expected = match driver.name() {
+            "shelly" => 800.0,
+            _ => 600.0,
+        };
+        assert_eq!(driver.max_power().await.unwrap(), expected);
+    }
+
+    devices.plant.set_on(false);
+    for driver in &drivers(&devices)[..] {
+        assert_eq!(driver.on_off().await.unwrap(), Status::Off);
+    }
+}
+
+#[tokio::test]
+async fn offline_at_night() {
+    let devices = spawn().await;
+    *devices.now.lock().unwrap() += time::Duration::hours(12);
+
+    let [opendtu, _, sunspec] = drivers(&devices);
+    for driver in [opendtu, sunspec] {
+        let err = driver.on_off().await.unwrap_err();
+        assert!(
+            matches!(err, PowerlogError::InverterOffline(_)),
+            "{}: {err:?}",
+            driver.name()
+        );
+    }
+
+    let unreachable = Config::SunSpec {
+        address: "",
+        unit_id: 1,
+    }
+    .driver(reqwest::Client::new(), Some("127.0.0.1:1".to_string()));
+    let err = unreachable.output_data().await.unwrap_err();
+    assert!(matches!(err, PowerlogError::InverterOffline(_)));
+}
+
+#[tokio::test]
+async fn read_meters() {
+    let devices = spawn().await;
+    let household = Arc::new(Household::new(devices.plant.clone()));
+    let url = simulator::spawn(household.clone().router()).await.unwrap();
+    let url = format!("http://{url}");
+
+    let client = reqwest::Client::new();
+    let meters: [Box<dyn Meter>; 2] = [
+        Box::new(meter::Tasmota {
+            client: client.clone(),
+            url: url.clone(),
+            fields: &simulator::household::TASMOTA_FIELDS,
+        }),
+        Box::new(meter::Shelly3Em { client, url }),
+    ];
+    for meter in meters {
+        *devices.now.lock().unwrap() += time::Duration::minutes(30);
+        let reading = meter.read().await.unwrap();
+        let expected = household.reading();
+        assert_close(reading.import_total, expected.import_total, 1e-9);
+        assert_close(reading.export_total, expected.export_total, 1e-9);
+        assert_close(reading.power, expected.power, 0.5);
+        // around noon the panels produce more than the base load
+        assert!(reading.export_total > 321.0, "{}", meter.name());
+    }
+}
+
+#[tokio::test]
+async fn lifetime_offsets_of_the_ez1() {
+    let devices = spawn().await;
+    let open_meteo = Arc::new(open_meteo::OpenMeteo::new(vec![
+        open_meteo::Reply::fixture("fixtures/open-meteo/dwd-icon-clear.json").unwrap(),
+    ]));
+    let ez1 = Arc::new(ez1::Ez1::with_plant(devices.plant.clone()));
+    let url = simulator::spawn(open_meteo.router().merge(ez1.router()))
+        .await
+        .unwrap();
+    let url = format!("http://{url}");
+
+    let [opendtu, _, sunspec] = drivers(&devices);
+    let inverters = [
+        (opendtu, 0.0),
+        (sunspec, 0.0),
+        (
+            Config::Ez1 {
+                url: "",
+                lifetime_offsets: &[LifetimeOffset {
+                    device_id: Some(opendtu::SERIAL),
+                    channel: 2,
+                    energy: 540.0,
+                }],
+            }
+            .driver(reqwest::Client::new(), Some(url.clone())),
+            0.0,
+        ),
+        (
+            Config::Ez1 {
+                url: "",
+                lifetime_offsets: &[LifetimeOffset {
+                    device_id: Some(ez1::DEVICE_ID),
+                    channel: 2,
+                    energy: 540.0,
+                }],
+            }
+            .driver(reqwest::Client::new(), Some(url.clone())),
+            540.0,
+        ),
+        // the counter reset of the shipped configuration must not get lost
+        (
+            powerlog::config::INVERTER.driver(reqwest::Client::new(), Some(url.clone())),
+            540.606323242188,
+        ),
+    ];
+
+    let time = *devices.now.lock().unwrap();
+    let readings = devices.plant.readings();
+    for (inverter, offset) in inverters {
+        let name = inverter.name();
+        let db = powerlog::db::connect("sqlite::memory:").await.unwrap();
+        let collector = collector(&db, inverter, &url);
+        assert_eq!(collector.run(time).await.unwrap(), Outcome::Complete);
+
+        let latest = powerlog::db::select_latest(&db).await.unwrap().unwrap();
+        let channels = powerlog::db::select_channels(&db, latest.id).await.unwrap();
+        assert_eq!(channels.len(), 2, "{name}");
+        assert_close(
+            channels[0].energy_total.into(),
+            readings.energy_lifetime[0],
+            1e-3,
+        );
+        assert_close(
+            channels[1].energy_total.into(),
+            readings.energy_lifetime[1] + offset,
+            1e-3,
+        );
+    }
+}
+
+#[tokio::test]
+async fn sunspec_single_connection() {
+    let devices = spawn().await;
+    let open_meteo = Arc::new(open_meteo::OpenMeteo::new(vec![
+        open_meteo::Reply::fixture("fixtures/open-meteo/dwd-icon-clear.json").unwrap(),
+    ]));
+    let url = simulator::spawn(open_meteo.router()).await.unwrap();
+
+    // the simulator serves one client at a time like many devices, the concurrent requests of
+    // the collector have to share the connection
+    let [_, _, sunspec] = drivers(&devices);
+    let db = powerlog::db::connect("sqlite::memory:").await.unwrap();
+    let collector = collector(&db, sunspec, &format!("http://{url}"));
+    for _ in 0..2 {
+        *devices.now.lock().unwrap() += powerlog::config::COLLECT_INTERVAL;
+        let time = *devices.now.lock().unwrap();
+        assert_eq!(collector.run(time).await.unwrap(), Outcome::Complete);
+    }
+    assert_eq!(devices.sunspec.connections(), 1);
+}
@@ -0,0 +1,344 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Collect a full simulated day against the EZ1 and Open-Meteo simulators, then check that the API
+//! serves exactly what the inverter reported
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use powerlog::collector::{Collector, Outcome};
+use powerlog::driver::Ez1 as Ez1Driver;
+use powerlog::finance::Period;
+use powerlog::meter;
+use powerlog::report::Format;
+use powerlog::retry::Policy;
+use powerlog::simulator::{self, ez1::Ez1, household::Household, open_meteo};
+
+const NO_RETRY: Policy = Policy {
+    attempts: 1,
+    initial_backoff: Duration::ZERO,
+    max_backoff: Duration::ZERO,
+};
+
+/// The ground truth of a complete run as reported by the simulated inverter
+struct Reading {
+    time: time::OffsetDateTime,
+    power: [f64; 2],
+    lifetime: [f64; 2],
+    meter: meter::Reading,
+}
+
+fn assert_close(actual: f64, expected: f64) {
+    assert!(
+        (actual - expected).abs() < 1e-3,
+        "{actual} differs from {expected}"
+    );
+}
+
+/// 2024-06-21 00:00 UTC
+const MIDNIGHT: i64 = 1718928000;
+
+/// A database file in the temp directory, removed when dropped even if an assertion failed
+struct TempDb(std::path::PathBuf);
+
+impl TempDb {
+    fn new() -> Self {
+        let unique = time::OffsetDateTime::now_utc().unix_timestamp_nanos();
+        Self(std::env::temp_dir().join(format!(
+            "powerlog-end-to-end-{}-{unique}.sqlite3",
+            std::process::id()
+        )))
+    }
+}
+
+impl Drop for TempDb {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+async fn get_json(client: &reqwest::Client, url: &str) -> serde_json::Value {
+    let response = client.get(url).send().await.unwrap();
+    assert!(
+        response.status().is_success(),
+        "{url}: {}",
+        response.status()
+    );
+    response.json().await.unwrap()
+}
+
+#[tokio::test]
+async fn collect_a_day_and_serve_it() {
+    let path = TempDb::new();
+    let db = powerlog::db::connect(&format!("sqlite://{}?mode=rwc", path.0.display()))
+        .await
+        .unwrap();
+
+    // the API tells "today" by the same clock as the simulators
+    let midnight = time::OffsetDateTime::from_unix_timestamp(MIDNIGHT).unwrap();
+    let now = Arc::new(Mutex::new(midnight));
+    let clock: simulator::Clock = {
+        let now = now.clone();
+        Arc::new(move || *now.lock().unwrap())
+    };
+
+    let ez1 = Arc::new(Ez1::new(clock));
+    let open_meteo = Arc::new(open_meteo::OpenMeteo::new(vec![
+        open_meteo::Reply::fixture("fixtures/open-meteo/dwd-icon-clear.json").unwrap(),
+    ]));
+    let household = Arc::new(Household::new(ez1.plant().clone()));
+    let devices = simulator::spawn(
+        ez1.clone()
+            .router()
+            .merge(open_meteo.clone().router())
+            .merge(household.clone().router()),
+    )
+    .await
+    .unwrap();
+    let devices = format!("http://{devices}");
+
+    // the sleeping inverter never answers, keep the timeouts short to get through the night fast
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(50))
+        .build()
+        .unwrap();
+    let collector = Collector {
+        db: db.clone(),
+        inverter: Box::new(Ez1Driver {
+            client: client.clone(),
+            url: devices.clone(),
+            lifetime_offsets: &[],
+        }),
+        inverter_retry: NO_RETRY,
+        inverter_precheck_retry: NO_RETRY,
+        client: reqwest::Client::new(),
+        weather_url: devices.clone(),
+        weather_retry: NO_RETRY,
+        meter: Some(Box::new(meter::Tasmota {
+            client,
+            url: devices.clone(),
+            fields: &simulator::household::TASMOTA_FIELDS,
+        })),
+        meter_retry: NO_RETRY,
+        alert_rules: &[],
+        notifiers: Vec::new(),
+    };
+
+    let mut readings = Vec::new();
+    let mut time = midnight;
+    while time < midnight + time::Duration::DAY {
+        *now.lock().unwrap() = time;
+        let outcome = collector.run(time).await.unwrap();
+        if powerlog::sun::position(time).altitude > 0.0 {
+            assert_eq!(outcome, Outcome::Complete, "at {time}");
+            let reading = ez1.plant().readings();
+            readings.push(Reading {
+                time,
+                power: reading.power,
+                lifetime: reading.energy_lifetime,
+                meter: household.reading(),
+            });
+        } else {
+            assert_eq!(outcome, Outcome::Offline, "at {time}");
+        }
+        time += powerlog::config::COLLECT_INTERVAL;
+    }
+    assert!(readings.len() > 50);
+    let last = readings.last().unwrap();
+
+    let api = simulator::spawn(powerlog::api::app_with_clock(db.clone(), {
+        let now = now.clone();
+        Arc::new(move || *now.lock().unwrap())
+    }))
+    .await
+    .unwrap();
+    let api = format!("http://{api}");
+    let client = reqwest::Client::new();
+
+    let power_today = get_json(&client, &format!("{api}/powerToday")).await;
+    let power_today = power_today.as_array().unwrap();
+    assert_eq!(power_today.len(), readings.len());
+    for (row, reading) in power_today.iter().zip(&readings) {
+        assert_close(row["power_ch1"].as_f64().unwrap(), reading.power[0].round());
+        assert_close(row["power_ch2"].as_f64().unwrap(), reading.power[1].round());
+        assert_close(
+            row["energy_total_ch1"].as_f64().unwrap(),
+            reading.lifetime[0],
+        );
+        assert_close(
+            row["energy_total_ch2"].as_f64().unwrap(),
+            reading.lifetime[1],
+        );
+        // stored as a ratio instead of the percentage reported by Open-Meteo
+        assert_close(row["cloud_cover"].as_f64().unwrap(), 0.03);
+    }
+
+    let channels_today = get_json(&client, &format!("{api}/channelsToday")).await;
+    let channels_today = channels_today.as_array().unwrap();
+    assert_eq!(channels_today.len(), 2 * readings.len());
+    for (rows, reading) in channels_today.chunks(2).zip(&readings) {
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(row["channel"], i + 1);
+            assert_close(row["power"].as_f64().unwrap(), reading.power[i].round());
+            assert_close(row["energy_total"].as_f64().unwrap(), reading.lifetime[i]);
+        }
+    }
+
+    // the lifetime energy at the end of each hour, the first hour has nothing to compare to
+    let mut by_hour = BTreeMap::new();
+    for reading in &readings {
+        by_hour.insert(format!("{:02}", reading.time.hour()), reading.lifetime);
+    }
+    let generated_by_hour = get_json(&client, &format!("{api}/generatedByHourToday")).await;
+    let generated_by_hour = generated_by_hour.as_array().unwrap();
+    assert_eq!(generated_by_hour.len(), by_hour.len());
+    let mut previous: Option<[f64; 2]> = None;
+    for (row, (hour, lifetime)) in generated_by_hour.iter().zip(&by_hour) {
+        assert_eq!(row["hour"], hour.as_str());
+        match previous {
+            None => assert!(row["ch1"].is_null() && row["ch2"].is_null()),
+            Some(previous) => {
+                assert_close(row["ch1"].as_f64().unwrap(), lifetime[0] - previous[0]);
+                assert_close(row["ch2"].as_f64().unwrap(), lifetime[1] - previous[1]);
+            }
+        }
+        previous = Some(*lifetime);
+    }
+
+    let generated_by_day = get_json(&client, &format!("{api}/generatedByDay")).await;
+    let generated_by_day = generated_by_day.as_array().unwrap();
+    assert_eq!(generated_by_day.len(), 1);
+    assert_eq!(generated_by_day[0]["date"], midnight.date().to_string());
+    assert_close(
+        generated_by_day[0]["ch1"].as_f64().unwrap(),
+        last.lifetime[0],
+    );
+    assert_close(
+        generated_by_day[0]["ch2"].as_f64().unwrap(),
+        last.lifetime[1],
+    );
+
+    // the meter is only read while the inverter is online
+    let first = readings.first().unwrap();
+    let self_consumption = get_json(&client, &format!("{api}/selfConsumptionByDay")).await;
+    let self_consumption = self_consumption.as_array().unwrap();
+    assert_eq!(self_consumption.len(), 1);
+    let day = &self_consumption[0];
+    let generated = last.lifetime.iter().sum::<f64>() - first.lifetime.iter().sum::<f64>();
+    let imported = last.meter.import_total - first.meter.import_total;
+    let feed_in = last.meter.export_total - first.meter.export_total;
+    assert_close(day["generated"].as_f64().unwrap(), generated);
+    assert_close(day["imported"].as_f64().unwrap(), imported);
+    assert_close(day["feed_in"].as_f64().unwrap(), feed_in);
+    assert!(feed_in > 0.0 && feed_in < generated);
+    let self_consumed = generated - feed_in;
+    assert_close(day["self_consumed"].as_f64().unwrap(), self_consumed);
+    assert_close(
+        day["self_consumption_ratio"].as_f64().unwrap(),
+        self_consumed / generated,
+    );
+    assert_close(
+        day["autarky"].as_f64().unwrap(),
+        self_consumed / (imported + self_consumed),
+    );
+
+    // the first day counts everything since the inverter started, valued at the configured rates
+    let savings = get_json(&client, &format!("{api}/savings?period=month")).await;
+    let savings = savings.as_array().unwrap();
+    assert_eq!(savings.len(), 1);
+    let month = &savings[0];
+    assert_eq!(
+        month["period"],
+        format!("{}-{:02}", midnight.year(), midnight.month() as u8)
+    );
+    assert_close(
+        month["generated"].as_f64().unwrap(),
+        last.lifetime.iter().sum(),
+    );
+    assert_close(month["feed_in"].as_f64().unwrap(), feed_in);
+    let amortization = get_json(&client, &format!("{api}/amortization")).await;
+    assert_close(
+        amortization["cumulative"].as_f64().unwrap(),
+        month["cumulative"].as_f64().unwrap(),
+    );
+    assert_eq!(
+        amortization["hardware_cost"],
+        powerlog::config::HARDWARE_COST
+    );
+    let invalid = client
+        .get(format!("{api}/savings?period=week"))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), 400);
+
+    let co2 = get_json(&client, &format!("{api}/analytics/co2")).await;
+    assert_eq!(co2["days"].as_array().unwrap().len(), 1);
+    assert_eq!(co2["months"][0]["period"], month["period"]);
+    let intensity = match powerlog::config::CO2_INTENSITY {
+        powerlog::co2::Intensity::PerYear(factors) => {
+            powerlog::co2::per_year(factors, midnight.year()).unwrap()
+        }
+        powerlog::co2::Intensity::Csv { .. } => unreachable!("the tests use static factors"),
+    };
+    assert_close(
+        co2["total_kg"].as_f64().unwrap(),
+        last.lifetime.iter().sum::<f64>() * intensity / 1000.0,
+    );
+
+    let report = powerlog::report::load(&db, Period::Day, midnight.date())
+        .await
+        .unwrap();
+    assert_eq!(report.channels.len(), 2);
+    assert_close(report.generated, generated);
+    // the power is stored rounded per channel
+    let peak = readings
+        .iter()
+        .map(|reading| reading.power.iter().map(|power| power.round()).sum::<f64>())
+        .fold(0.0, f64::max);
+    assert_close(report.peak_power.unwrap().0, peak);
+    assert!(report.expected.is_some() && report.production_hours > 10.0);
+    let report = report.render(Format::Html);
+    assert!(report.contains(&format!("<h1>powerlog report for {}</h1>", midnight.date())));
+
+    let metrics = client
+        .get(format!("{api}/metrics"))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(metrics.contains(&format!(
+        "powerlog_last_sample_timestamp_seconds {}",
+        last.time.unix_timestamp()
+    )));
+    assert!(metrics.contains(r#"powerlog_power_watts{channel="2"}"#));
+
+    let status = get_json(&client, &format!("{api}/status")).await;
+    assert_eq!(status["database_rows"], readings.len());
+    assert_eq!(status["last_run"]["outcome"], "offline");
+    assert_eq!(status["weather_success_rate"], 1.0);
+
+    // the last sample is hours old, which only matters once the sun is up again
+    let health = client.get(format!("{api}/health")).send().await.unwrap();
+    assert_eq!(health.status(), reqwest::StatusCode::OK);
+    let night = *now.lock().unwrap();
+    *now.lock().unwrap() = midnight + time::Duration::hours(36);
+    let health = client.get(format!("{api}/health")).send().await.unwrap();
+    assert_eq!(health.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    *now.lock().unwrap() = night;
+
+    let dashboard = client.get(&api).send().await.unwrap();
+    assert!(dashboard.text().await.unwrap().contains("<html"));
+
+    // the live feed replays the most recent samples to new subscribers
+    let mut live = client.get(format!("{api}/live")).send().await.unwrap();
+    let event = tokio::time::timeout(Duration::from_secs(10), live.chunk())
+        .await
+        .unwrap()
+        .unwrap()
+        .unwrap();
+    assert!(String::from_utf8_lossy(&event).contains("event: sample"));
+}
@@ -0,0 +1,140 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use axum::extract::Path;
+use axum::http::StatusCode;
+use axum::routing::get;
+use powerlog::error::PowerlogError;
+use powerlog::inverter::{self, Status};
+
+/// Serve the responses recorded in `fixtures/ez1/<variant>` on the paths of the local API
+async fn serve(variant: &'static str) -> String {
+    let app = axum::Router::new().route(
+        "/:endpoint",
+        get(move |Path(endpoint): Path<String>| async move {
+            std::fs::read_to_string(format!("fixtures/ez1/{variant}/{endpoint}.json"))
+                .map_err(|_| StatusCode::NOT_FOUND)
+        }),
+    );
+    let address = powerlog::simulator::spawn(app).await.unwrap();
+    format!("http://{address}")
+}
+
+struct Expected {
+    variant: &'static str,
+    device_id: &'static str,
+    model: (&'static str, &'static str),
+    /// power, energy today and lifetime energy of each channel
+    channels: &'static [[f64; 3]],
+    max_power: f64,
+    status: Status,
+}
+
+#[tokio::test]
+async fn firmware_variants() {
+    let corpus = [
+        Expected {
+            variant: "dual-panel",
+            device_id: "E07000000001",
+            model: ("EZ1", "1.6.0"),
+            channels: &[[187.0, 0.83453, 412.47595], [192.0, 0.85741, 398.61728]],
+            max_power: 800.0,
+            status: Status::On,
+        },
+        Expected {
+            variant: "numbers-as-strings",
+            device_id: "E07000000002",
+            model: ("EZ1", "1.7.0"),
+            channels: &[[65.0, 0.21364, 1023.84512], [61.0, 0.19823, 1001.2279]],
+            max_power: 600.0,
+            status: Status::Off,
+        },
+        Expected {
+            variant: "single-panel",
+            device_id: "E07000000003",
+            model: ("EZ1", "1.9.0"),
+            channels: &[[243.0, 1.20514, 87.33021]],
+            max_power: 800.0,
+            status: Status::On,
+        },
+        Expected {
+            variant: "extra-fields",
+            device_id: "E07000000004",
+            model: ("EZ1", "2.0.0"),
+            channels: &[[12.0, 0.01532, 2.84211], [11.0, 0.01498, 2.80945]],
+            max_power: 800.0,
+            status: Status::On,
+        },
+        Expected {
+            variant: "four-channels",
+            device_id: "E17000000005",
+            model: ("QT2", "1.0.0"),
+            channels: &[
+                [301.0, 1.42011, 652.10344],
+                [297.0, 1.40387, 648.9121],
+                [288.0, 1.37902, 640.55573],
+                [305.0, 1.43529, 655.0132],
+            ],
+            max_power: 2000.0,
+            status: Status::On,
+        },
+    ];
+
+    let client = reqwest::Client::new();
+    for expected in corpus {
+        let url = serve(expected.variant).await;
+        let variant = expected.variant;
+
+        let output_data = inverter::output_data(&client, &url).await.unwrap();
+        assert_eq!(output_data.device_id, expected.device_id, "{variant}");
+        let channels: Vec<_> = output_data
+            .channels
+            .iter()
+            .map(|channel| {
+                [
+                    channel.power,
+                    channel.energy_generation_startup,
+                    channel.energy_generation_lifetime,
+                ]
+            })
+            .collect();
+        assert_eq!(channels, expected.channels, "{variant}");
+
+        let model = inverter::device_info(&client, &url).await.unwrap();
+        assert_eq!(model.device_id, expected.device_id, "{variant}");
+        assert_eq!(
+            (model.name.as_str(), model.firmware.as_str()),
+            expected.model,
+            "{variant}"
+        );
+        assert_eq!(model.max_power, expected.max_power, "{variant}");
+
+        let max_power = inverter::max_power(&client, &url).await.unwrap();
+        assert_eq!(max_power, expected.max_power, "{variant}");
+
+        let status = inverter::on_off(&client, &url).await.unwrap();
+        assert_eq!(status, expected.status, "{variant}");
+    }
+}
+
+#[tokio::test]
+async fn invalid_numbers() {
+    let app = axum::Router::new().route(
+        "/getOutputData",
+        get(|| async {
+            r#"{"data":{"p1":"n/a","e1":0,"te1":0},"message":"SUCCESS","deviceId":"E07000000001"}"#
+        }),
+    );
+    let url = format!("http://{}", powerlog::simulator::spawn(app).await.unwrap());
+
+    let err = inverter::output_data(&reqwest::Client::new(), &url)
+        .await
+        .unwrap_err();
+    assert!(matches!(
+        err,
+        PowerlogError::BadResponse {
+            endpoint: "getOutputData",
+            ..
+        }
+    ));
+    assert!(!err.is_transient());
+}
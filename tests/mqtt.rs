@@ -0,0 +1,99 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+//! Publish a sample to the broker stand-in of the simulator like to Home Assistant's Mosquitto
+
+use std::sync::Arc;
+
+use powerlog::inverter::{Model, OutputChannel, OutputData, Status};
+use powerlog::sample::{Retries, Sample};
+use powerlog::simulator::broker::{self, Broker};
+
+fn sample() -> Sample {
+    // 2024-06-21 12:00 UTC
+    let time = time::OffsetDateTime::from_unix_timestamp(1718971200).unwrap();
+    Sample {
+        time,
+        output_data: OutputData {
+            device_id: "E07000000001".to_string(),
+            channels: vec![
+                OutputChannel {
+                    power: 180.0,
+                    energy_generation_startup: 0.5,
+                    energy_generation_lifetime: 120.25,
+                },
+                OutputChannel {
+                    power: 175.5,
+                    energy_generation_startup: 0.25,
+                    energy_generation_lifetime: 118.0,
+                },
+            ],
+        },
+        model: Some(Model {
+            device_id: "E07000000001".to_string(),
+            manufacturer: "APsystems".to_string(),
+            name: "EZ1".to_string(),
+            firmware: "1.6.0".to_string(),
+            min_power: 30.0,
+            max_power: 800.0,
+        }),
+        max_power: 600.0,
+        on_off: Status::On,
+        weather: None,
+        meter: None,
+        sunpos: powerlog::sun::position(time),
+        retries: Retries::default(),
+    }
+}
+
+#[tokio::test]
+async fn publish_retained_states_and_discovery() {
+    let broker = Arc::new(Broker::default());
+    let address = broker::spawn(broker.clone()).await.unwrap();
+
+    powerlog::mqtt::publish(("127.0.0.1", address.port()), &sample())
+        .await
+        .unwrap();
+
+    let messages = broker.messages();
+    // a discovery config for each of the 17 sensors, states for all but the 7 weather ones
+    assert_eq!(messages.len(), 17 + 10);
+    // Home Assistant only picks up retained messages when it (re)connects later on
+    assert!(
+        messages
+            .iter()
+            .all(|message| message.retain && message.qos == 1)
+    );
+    let payload = |topic: &str| {
+        messages
+            .iter()
+            .find(|message| message.topic == topic)
+            .map(|message| message.payload.as_str())
+    };
+    assert_eq!(payload("powerlog/E07000000001/power_ch2"), Some("175.5"));
+    assert_eq!(
+        payload("powerlog/E07000000001/energy_total_ch1"),
+        Some("120.25")
+    );
+    assert_eq!(payload("powerlog/E07000000001/on_off"), Some("ON"));
+    assert_eq!(payload("powerlog/E07000000001/cloud_cover"), None);
+
+    let config: serde_json::Value = serde_json::from_str(
+        payload("homeassistant/sensor/E07000000001/energy_total_ch2/config").unwrap(),
+    )
+    .unwrap();
+    assert_eq!(
+        config["state_topic"],
+        "powerlog/E07000000001/energy_total_ch2"
+    );
+    assert_eq!(config["device"]["sw_version"], "1.6.0");
+    assert!(payload("homeassistant/binary_sensor/E07000000001/on_off/config").is_some());
+}
+
+#[tokio::test]
+async fn unreachable_broker() {
+    assert!(
+        powerlog::mqtt::publish(("127.0.0.1", 1), &sample())
+            .await
+            .is_err()
+    );
+}
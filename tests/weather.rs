@@ -0,0 +1,87 @@
+// SPDX-License-Identifier: GPL-3.0-or-later
+
+use std::sync::Arc;
+
+use axum::http::StatusCode;
+use powerlog::error::PowerlogError;
+use powerlog::simulator::open_meteo::{OpenMeteo, Reply};
+
+fn fixture(name: &str) -> Reply {
+    Reply::fixture(format!("fixtures/open-meteo/{name}.json")).unwrap()
+}
+
+async fn serve(replies: Vec<Reply>) -> (Arc<OpenMeteo>, String) {
+    let open_meteo = Arc::new(OpenMeteo::new(replies));
+    let address = powerlog::simulator::spawn(open_meteo.clone().router())
+        .await
+        .unwrap();
+    (open_meteo, format!("http://{address}"))
+}
+
+#[tokio::test]
+async fn recorded_responses() {
+    let (_, url) = serve(vec![
+        fixture("dwd-icon-overcast"),
+        fixture("dwd-icon-clear"),
+        fixture("dwd-icon-night"),
+    ])
+    .await;
+    let client = reqwest::Client::new();
+
+    let overcast = powerlog::weather::query(&client, &url).await.unwrap();
+    assert_eq!(overcast.cloud_cover, 100.0);
+    assert_eq!(overcast.direct_radiation_instant, 123.5);
+
+    let clear = powerlog::weather::query(&client, &url).await.unwrap();
+    assert_eq!(clear.cloud_cover, 3.0);
+    assert_eq!(clear.global_tilted_irradiance_instant, 412.4);
+
+    let night = powerlog::weather::query(&client, &url).await.unwrap();
+    assert_eq!(night.shortwave_radiation_instant, 0.0);
+}
+
+#[tokio::test]
+async fn too_many_requests() {
+    let mut reply = fixture("too-many-requests");
+    reply.status = StatusCode::TOO_MANY_REQUESTS;
+    let (open_meteo, url) = serve(vec![reply.clone()]).await;
+
+    let err = powerlog::weather::query(&reqwest::Client::new(), &url)
+        .await
+        .unwrap_err();
+    let PowerlogError::WeatherUnavailable(source) = &err else {
+        panic!("unexpected error {err:?}");
+    };
+    assert_eq!(source.status(), Some(StatusCode::TOO_MANY_REQUESTS));
+    assert!(err.is_transient());
+
+    // rate limiting is retried, the replies are served in turn starting at the second one
+    open_meteo.set_replies(vec![fixture("dwd-icon-clear"), reply]);
+    let policy = powerlog::retry::Policy {
+        attempts: 2,
+        initial_backoff: std::time::Duration::ZERO,
+        max_backoff: std::time::Duration::ZERO,
+    };
+    let client = reqwest::Client::new();
+    let retried = powerlog::retry::retry(&policy, || powerlog::weather::query(&client, &url)).await;
+    assert_eq!(retried.result.unwrap().cloud_cover, 3.0);
+    assert_eq!(retried.retries, 1);
+    assert_eq!(open_meteo.requests(), 3);
+}
+
+#[tokio::test]
+async fn missing_fields_and_null_values() {
+    for name in ["dwd-icon-missing-field", "dwd-icon-null-values"] {
+        let (open_meteo, url) = serve(vec![fixture(name)]).await;
+        let err = powerlog::weather::query(&reqwest::Client::new(), &url)
+            .await
+            .unwrap_err();
+        assert!(
+            matches!(&err, PowerlogError::WeatherUnavailable(source) if source.is_decode()),
+            "{name}: {err:?}"
+        );
+        // retrying won't change the response
+        assert!(!err.is_transient(), "{name}");
+        assert_eq!(open_meteo.requests(), 1);
+    }
+}